@@ -0,0 +1,88 @@
+//! Carry fields added to the listing schema over from a newer scrape into an older listing.
+//!
+//! This avoids re-scraping just to populate new fields. Bars are matched by id, and each field the
+//! newer listing has that the older one lacks is copied across. Bars without a match are left as
+//! they are, picking up the schema defaults when the result is read back as a listing.
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+/// Copy fields from `newer` into the matching bars of `older`.
+///
+/// Fields already present in an older bar are kept, unless they are named in `overwrite`, in which
+/// case the newer value wins. Returns the number of bars which had a match.
+pub fn backfill(older: &mut [Value], newer: &[Value], overwrite: &[String]) -> usize {
+    let newer_by_id: HashMap<&str, &Map<String, Value>> = newer
+        .iter()
+        .filter_map(Value::as_object)
+        .filter_map(|bar| bar.get("id").and_then(Value::as_str).map(|id| (id, bar)))
+        .collect();
+
+    let mut matched = 0;
+    for bar in older.iter_mut().filter_map(Value::as_object_mut) {
+        let source = match bar
+            .get("id")
+            .and_then(Value::as_str)
+            .and_then(|id| newer_by_id.get(id))
+        {
+            Some(source) => source,
+            None => continue,
+        };
+        matched += 1;
+
+        for (field, value) in source.iter() {
+            if !bar.contains_key(field) || overwrite.contains(field) {
+                bar.insert(field.clone(), value.clone());
+            }
+        }
+    }
+    matched
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bars(value: Value) -> Vec<Value> {
+        match value {
+            Value::Array(bars) => bars,
+            _ => panic!("expected a list of bars"),
+        }
+    }
+
+    #[test]
+    fn missing_fields_are_copied_from_the_matching_bar() {
+        let mut older = bars(json!([
+            {"id": "a", "name": "Old A", "tips": ["Pickleback!"]},
+            {"id": "b", "name": "B", "tips": ["Pickleback?"], "rating": 7.5},
+            {"id": "gone", "name": "Gone", "tips": ["Pickleback."]}
+        ]));
+        let newer = bars(json!([
+            {"id": "b", "name": "B", "tips": ["New tip"], "rating": 9.0, "popularity": 120},
+            {"id": "a", "name": "New A", "tips": ["New tip"], "neighborhood": "Manhattan"},
+            {"id": "new", "name": "New", "tips": ["Pickleback"]}
+        ]));
+        assert_eq!(backfill(&mut older, &newer, &[]), 2);
+        assert_eq!(
+            Value::Array(older),
+            json!([
+                {"id": "a", "name": "Old A", "tips": ["Pickleback!"], "neighborhood": "Manhattan"},
+                {"id": "b", "name": "B", "tips": ["Pickleback?"], "rating": 7.5, "popularity": 120},
+                {"id": "gone", "name": "Gone", "tips": ["Pickleback."]}
+            ])
+        );
+    }
+
+    #[test]
+    fn overwritten_fields_take_the_newer_value() {
+        let mut older = bars(json!([{"id": "a", "name": "Old A", "rating": 7.5, "tips": ["P"]}]));
+        let newer = bars(json!([{"id": "a", "name": "New A", "rating": 9.0, "tips": ["Q"]}]));
+        let overwrite = vec!["rating".to_string(), "name".to_string()];
+        assert_eq!(backfill(&mut older, &newer, &overwrite), 1);
+        assert_eq!(
+            older[0],
+            json!({"id": "a", "name": "New A", "rating": 9.0, "tips": ["P"]})
+        );
+    }
+}
@@ -1,16 +1,122 @@
-use std::fs::File;
-use std::sync::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::SystemTime;
 
-use log::{error, info};
+use log::{error, info, warn};
+use pickletrack::phrase_occurrences;
 use rand::seq::SliceRandom;
 use rand::{thread_rng, Rng};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CommentFallback, CommentRanking, Config, Utility};
+use crate::coordinates;
+use crate::deadline::{Deadline, Expired};
+use crate::language::language_matches;
+use crate::pages;
+use crate::remote;
+use crate::units::{Meters, Miles};
 
 /// Maximum distance of a bar that will be suggested to the user based on their current location.
-const MAXIMUM_DITANCE_MILES: f64 = 3.0;
+pub const MAXIMUM_DITANCE_MILES: f64 = 3.0;
 
 /// Path to JSON file containing list of bars with reviews mentioning picklebacks.
-const BARS_FILE_PATH: &str = "static/data/current.json";
+pub const BARS_FILE_PATH: &str = "static/data/current.json";
+
+/// Name of the index the scraper writes alongside a listing split by neighborhood.
+const PARTITION_INDEX_FILE_NAME: &str = "index.json";
+
+/// Phrases counted towards a tip's relevance when the listing doesn't record which it matched.
+const DEFAULT_RELEVANCE_PHRASES: &[&str] = &["pickleback", "pickle back", "pickle-back"];
+
+/// A tip as stored in the listing file.
+///
+/// Older listings store each tip as a plain string, newer ones store an object which may also
+/// record which search phrases the scraper matched in it, and the language it is written in.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTip {
+    Text(String),
+    Detailed {
+        text: String,
+        #[serde(default)]
+        matched: Vec<String>,
+        #[serde(default)]
+        lang: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(from = "StoredTip")]
+struct Tip {
+    text: String,
+    /// Search phrases the scraper matched in the text, empty for listings which predate this.
+    matched: Vec<String>,
+    /// Language of the tip as reported by Foursquare, if known.
+    lang: Option<String>,
+}
+
+impl From<StoredTip> for Tip {
+    fn from(stored: StoredTip) -> Self {
+        match stored {
+            StoredTip::Text(text) => Tip {
+                text,
+                matched: Vec::new(),
+                lang: None,
+            },
+            StoredTip::Detailed {
+                text,
+                matched,
+                lang,
+            } => Tip {
+                text,
+                matched,
+                lang,
+            },
+        }
+    }
+}
+
+impl Tip {
+    /// Whether the tip is long enough to make a good comment.
+    fn is_substantial(&self, min_words: usize) -> bool {
+        self.text.split_whitespace().count() >= min_words
+    }
+
+    /// How much the tip is about picklebacks: occurrences of its matched phrases per word.
+    ///
+    /// A short tip all about picklebacks scores higher than a long review which mentions them in
+    /// passing. Phrases are counted as whole words, as the scraper matches them. Listings which
+    /// don't record matched phrases are scored on common spellings of "pickleback".
+    fn relevance(&self) -> f64 {
+        let words = self.text.split_whitespace().count().max(1);
+        let occurrences: usize = if self.matched.is_empty() {
+            DEFAULT_RELEVANCE_PHRASES
+                .iter()
+                .map(|phrase| phrase_occurrences(&self.text, phrase))
+                .sum()
+        } else {
+            self.matched
+                .iter()
+                .map(|phrase| phrase_occurrences(&self.text, phrase))
+                .sum()
+        };
+        occurrences as f64 / words as f64
+    }
+
+    /// Whether every phrase the tip claims to match really appears in its text.
+    fn matches_are_genuine(&self) -> bool {
+        let text = self.text.to_lowercase();
+        self.matched
+            .iter()
+            .all(|phrase| text.contains(&phrase.to_lowercase()))
+    }
+}
 
 #[derive(Deserialize)]
 struct Bar {
@@ -18,115 +124,2078 @@ struct Bar {
     name: String,
     lat: f64,
     lng: f64,
-    tips: Vec<String>,
+    tips: Vec<Tip>,
+    /// Quality score computed by the scraper, zero for listings which predate it.
+    #[serde(default)]
+    score: f64,
+    /// Foursquare rating out of 10, for listings which record it.
+    #[serde(default)]
+    rating: Option<f64>,
+    /// Number of Foursquare check-ins, for listings which record it.
+    #[serde(default)]
+    popularity: Option<u64>,
+    /// The bar's page on Foursquare, for listings which record it.
+    #[serde(default)]
+    fsq_url: Option<String>,
+    /// Area the bar is in, for listings which record it.
+    #[serde(default)]
+    neighborhood: Option<String>,
+    /// Whether the bar seems to serve picklebacks only seasonally, for listings which classify it.
+    #[serde(default)]
+    seasonal: Option<bool>,
+    /// The bar's featured photo, for listings which record it.
+    #[serde(default)]
+    photo_url: Option<String>,
+    /// Filled in from `lat` and `lng` once the listing is loaded, see `set_positions`.
+    #[serde(skip)]
+    position: Position,
+}
+
+/// A location in radians, along with the cosine of its latitude.
+///
+/// Every request computes the distance to every bar near it, so bars keep these precomputed rather
+/// than redoing the trigonometry for each request.
+#[derive(Debug, Clone, Copy, Default)]
+struct Position {
+    lat: f64,
+    lng: f64,
+    cos_lat: f64,
+}
+
+impl Position {
+    fn new(lat_degrees: f64, lng_degrees: f64) -> Self {
+        let lat = lat_degrees.to_radians();
+        Position {
+            lat,
+            lng: lng_degrees.to_radians(),
+            cos_lat: lat.cos(),
+        }
+    }
+
+    /// Compute the distance in miles to another position.
+    fn distance_miles(&self, other: &Position) -> f64 {
+        let d_lat: f64 = other.lat - self.lat;
+        let d_lon: f64 = other.lng - self.lng;
+        let a = (d_lat / 2.0).sin().powf(2.0)
+            + self.cos_lat * other.cos_lat * (d_lon / 2.0).sin().powf(2.0);
+        let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+        3959.0 * c
+    }
+
+    /// A position at most `max_miles` away, in a random direction, spread evenly over the disc
+    /// around this one.
+    fn jittered<R: Rng>(&self, max_miles: f64, rng: &mut R) -> Position {
+        let distance = max_miles * rng.gen::<f64>().sqrt();
+        let bearing = rng.gen_range(0.0, 2.0 * PI);
+        let lat = self.lat + distance * bearing.cos() / 3959.0;
+        Position {
+            lat,
+            lng: self.lng + distance * bearing.sin() / (3959.0 * self.cos_lat),
+            cos_lat: lat.cos(),
+        }
+    }
+}
+
+/// Cells of `Grid` per degree of latitude or longitude. A twentieth of a degree is about three
+/// and a half miles north to south, so a search of the default radius looks at a few cells.
+const GRID_CELLS_PER_DEGREE: f64 = 20.0;
+
+/// Number of cells around a line of latitude.
+const GRID_COLUMNS: i64 = 360 * GRID_CELLS_PER_DEGREE as i64;
+
+/// The row or column of `Grid` containing `degrees` of latitude or longitude.
+fn grid_index(degrees: f64) -> i64 {
+    (degrees * GRID_CELLS_PER_DEGREE).floor() as i64
+}
+
+/// The bars of a listing bucketed into cells of latitude and longitude, so that finding the bars
+/// near a point only measures the distance to those in the cells around it rather than to every
+/// bar.
+#[derive(Default)]
+struct Grid {
+    /// Indexes into the listing of the bars in each cell, by row and column.
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl Grid {
+    fn new(bars: &[Bar]) -> Self {
+        let mut cells: HashMap<(i64, i64), Vec<usize>> = HashMap::new();
+        for (index, bar) in bars.iter().enumerate() {
+            let cell = (
+                grid_index(bar.lat),
+                grid_index(bar.lng).rem_euclid(GRID_COLUMNS),
+            );
+            cells.entry(cell).or_default().push(index);
+        }
+        Grid { cells }
+    }
+
+    /// Indexes of the bars which may be within `radius_miles` of a location, in listing order.
+    /// These are the bars in every cell overlapping the box around that circle, so callers still
+    /// need to check their distance.
+    fn near(&self, lat: f64, lng: f64, radius_miles: f64) -> Vec<usize> {
+        let lat_span = (radius_miles / 3959.0).to_degrees();
+        let rows = grid_index(lat - lat_span)..=grid_index(lat + lat_span);
+        // Meridians converge towards the poles, so the circle is widest in degrees of longitude
+        // at the edge of the box nearest a pole.
+        let widest = (lat.abs() + lat_span).min(90.0).to_radians().cos();
+        let mut indexes: Vec<usize> = if widest * 180.0 <= lat_span {
+            // The circle reaches all the way around, or over the pole.
+            self.cells
+                .iter()
+                .filter(|((row, _), _)| rows.contains(row))
+                .flat_map(|(_, cell)| cell.iter().cloned())
+                .collect()
+        } else {
+            let lng_span = lat_span / widest;
+            let columns = grid_index(lng - lng_span)..=grid_index(lng + lng_span);
+            let mut indexes = Vec::new();
+            for row in rows {
+                // Columns past the antimeridian wrap around to the other side.
+                for column in columns
+                    .clone()
+                    .map(|column| column.rem_euclid(GRID_COLUMNS))
+                {
+                    if let Some(cell) = self.cells.get(&(row, column)) {
+                        indexes.extend_from_slice(cell);
+                    }
+                }
+            }
+            indexes
+        };
+        indexes.sort_unstable();
+        indexes.dedup();
+        indexes
+    }
+}
+
+/// Precompute the position of each bar, after loading the listing.
+fn set_positions(bars: &mut [Bar]) {
+    for bar in bars {
+        bar.position = Position::new(bar.lat, bar.lng);
+    }
+}
+
+/// Keep only the bars with a photo, unless none of them have one.
+fn prefer_photos(nearby: Vec<(&Bar, f64)>) -> Vec<(&Bar, f64)> {
+    if nearby.iter().any(|(bar, _)| bar.photo_url.is_some()) {
+        nearby
+            .into_iter()
+            .filter(|(bar, _)| bar.photo_url.is_some())
+            .collect()
+    } else {
+        nearby
+    }
 }
 
-/// A rough estimate for the "utility" score of a bar.
+/// Pick up to `count` alternates to `chosen` from `nearby`, closest to the user first.
 ///
-/// This is a linear scoring of the likelihood the user would want to choose this bar. If three
-/// bars are available, with scores [1, 2, 3], the first bar would be picked 1 in 6 times.
-fn utility_from_distance(distance_miles: f64) -> f64 {
-    5000.0 / ((distance_miles.powf(4.0) * 40.0) + 0.96)
+/// With `min_separation_miles`, alternates are kept at least that far from the chosen bar and from
+/// each other, so they are genuinely different options. Where too few bars are spread out enough,
+/// the closest of the rest fill the remaining places.
+fn choose_alternates<'a>(
+    nearby: &[(&'a Bar, f64)],
+    chosen: &Bar,
+    count: usize,
+    min_separation_miles: Option<f64>,
+) -> Vec<(&'a Bar, f64)> {
+    let mut candidates: Vec<(&Bar, f64)> = nearby
+        .iter()
+        .cloned()
+        .filter(|(bar, _)| bar.id != chosen.id)
+        .collect();
+    let by_distance = |a: &(&Bar, f64), b: &(&Bar, f64)| {
+        a.1.partial_cmp(&b.1)
+            .unwrap()
+            .then_with(|| a.0.id.cmp(&b.0.id))
+    };
+    candidates.sort_by(by_distance);
+
+    let mut picked: Vec<(&Bar, f64)> = Vec::new();
+    if let Some(min_separation_miles) = min_separation_miles {
+        for &(bar, distance) in &candidates {
+            if picked.len() >= count {
+                break;
+            }
+            let spread = picked
+                .iter()
+                .map(|&(other, _)| other)
+                .chain(Some(chosen))
+                .all(|other| other.position.distance_miles(&bar.position) >= min_separation_miles);
+            if spread {
+                picked.push((bar, distance));
+            }
+        }
+    }
+    for &(bar, distance) in &candidates {
+        if picked.len() >= count {
+            break;
+        }
+        if !picked.iter().any(|(other, _)| other.id == bar.id) {
+            picked.push((bar, distance));
+        }
+    }
+    picked.sort_by(by_distance);
+    picked
+}
+
+/// Put `tips` in the order `ranking` prefers them, random where it has no preference.
+fn rank_tips<R: Rng>(tips: &mut [&Tip], ranking: CommentRanking, rng: &mut R) {
+    tips.shuffle(rng);
+    if ranking == CommentRanking::Relevance {
+        // The sort is stable, so equally relevant tips stay in random order.
+        tips.sort_by(|a, b| b.relevance().total_cmp(&a.relevance()));
+    }
 }
 
-/// Compute the distance in miles between two (lat, lng) pairs.
-fn distance_latlong(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
-    let d_lat: f64 = (lat2 - lat1).to_radians();
-    let d_lon: f64 = (lng2 - lng1).to_radians();
-    let a = (d_lat / 2.0).sin().powf(2.0)
-        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powf(2.0);
-    let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
-    3959.0 * c
+/// The tips in `candidates` in the order `ranking` prefers them, with those shorter than
+/// `min_words` after the rest, or left out with `CommentFallback::Generic`.
+fn rank_substantial<'a, R: Rng>(
+    candidates: Vec<&'a Tip>,
+    min_words: usize,
+    ranking: CommentRanking,
+    fallback: CommentFallback,
+    rng: &mut R,
+) -> Vec<&'a Tip> {
+    let (mut substantial, mut short): (Vec<&Tip>, Vec<&Tip>) = candidates
+        .into_iter()
+        .partition(|tip| tip.is_substantial(min_words));
+    rank_tips(&mut substantial, ranking, rng);
+    if fallback == CommentFallback::Relax {
+        rank_tips(&mut short, ranking, rng);
+        substantial.append(&mut short);
+    }
+    substantial
+}
+
+/// The tips of a bar which may be shown with it, most preferred first, see `choose_comment`.
+fn rank_comments<'a, R: Rng>(
+    tips: &'a [Tip],
+    languages: &[String],
+    min_words: usize,
+    ranking: CommentRanking,
+    fallback: CommentFallback,
+    rng: &mut R,
+) -> Vec<&'a Tip> {
+    let mut ranked: Vec<&Tip> = Vec::new();
+    let unranked = |ranked: &[&Tip], tip: &Tip| !ranked.iter().any(|&other| ptr::eq(other, tip));
+    for language in languages {
+        let candidates: Vec<&Tip> = tips
+            .iter()
+            .filter(|tip| language_matches(language, tip.lang.as_deref()) && unranked(&ranked, tip))
+            .collect();
+        let mut preferred = rank_substantial(candidates, min_words, ranking, fallback, rng);
+        ranked.append(&mut preferred);
+    }
+    if fallback == CommentFallback::Relax || languages.is_empty() {
+        let candidates: Vec<&Tip> = tips.iter().filter(|tip| unranked(&ranked, tip)).collect();
+        let mut rest = rank_substantial(candidates, min_words, ranking, fallback, rng);
+        ranked.append(&mut rest);
+    }
+    ranked
+}
+
+/// Choose the comment shown with a suggested bar.
+///
+/// This is a random tip written in the most preferred language the bar has any tips in, or a random
+/// tip in any language if none of them match the preferences. Within a language, tips of at least
+/// `min_words` words are preferred over shorter ones, and then the most relevant if `ranking` asks
+/// for it.
+///
+/// With `CommentFallback::Generic`, shorter tips and tips in other languages aren't used. Instead
+/// None is returned, for the caller to show a generic comment.
+fn choose_comment<'a, R: Rng>(
+    tips: &'a [Tip],
+    languages: &[String],
+    min_words: usize,
+    ranking: CommentRanking,
+    fallback: CommentFallback,
+    rng: &mut R,
+) -> Option<&'a Tip> {
+    rank_comments(tips, languages, min_words, ranking, fallback, rng)
+        .into_iter()
+        .next()
+}
+
+/// The utility of a bar at the given distance, scaled up by how many pickleback tips it has,
+/// capped at the configured maximum so that a bar right next to the user doesn't crowd out every
+/// other, and scaled down if the bar only serves picklebacks seasonally.
+///
+/// Tips count for `tips ^ popularity_weight`, so a weight below 1 favors well known bars without
+/// letting one with twenty tips drown out everything else, and a weight of 0 ignores them.
+fn bar_utility(utility: &Utility, bar: &Bar, distance_miles: f64) -> f64 {
+    let popularity = (bar.tips.len().max(1) as f64).powf(utility.popularity_weight);
+    let bar_utility = utility.scoring.utility_from_distance(distance_miles) * popularity;
+    let bar_utility = utility
+        .max_bar_utility
+        .map_or(bar_utility, |max_utility| bar_utility.min(max_utility));
+    if bar.seasonal == Some(true) {
+        bar_utility * utility.seasonal_weight
+    } else {
+        bar_utility
+    }
+}
+
+/// Pick an index into `weights` at random, each in proportion to its weight. Returns None if the
+/// weights sum to zero.
+fn weighted_index<R: Rng>(weights: &[f64], rng: &mut R) -> Option<usize> {
+    let total: f64 = weights.iter().sum();
+    if total == 0.0 {
+        return None;
+    }
+
+    let choice: f64 = rng.gen_range(0.0, total);
+
+    let mut sweep: f64 = 0.0;
+    for (index, &weight) in weights.iter().enumerate() {
+        if sweep + weight > choice {
+            return Some(index);
+        }
+        sweep += weight;
+    }
+
+    unreachable!();
+}
+
+/// Choose a bar from `nearby` at random, weighted by utility so that closer bars are returned more
+/// often. Returns None if there are no bars to choose from.
+///
+/// Each bar's utility is computed once, and the choice made over those.
+fn choose_weighted<'a, R: Rng>(
+    utility: &Utility,
+    nearby: &[(&'a Bar, f64)],
+    rng: &mut R,
+) -> Option<(&'a Bar, f64)> {
+    let weights: Vec<f64> = nearby
+        .iter()
+        .map(|&(bar, distance)| bar_utility(utility, bar, distance))
+        .collect();
+    weighted_index(&weights, rng).map(|index| nearby[index])
+}
+
+/// The bars within `radius_miles` of a location, along with their distance from it in miles, in
+/// listing order. `grid` must have been built from `bars`.
+fn bars_within<'a>(
+    bars: &'a [Bar],
+    grid: &Grid,
+    lat: f64,
+    lng: f64,
+    radius_miles: f64,
+) -> Vec<(&'a Bar, f64)> {
+    let origin = Position::new(lat, lng);
+    grid.near(lat, lng, radius_miles)
+        .into_iter()
+        .map(|index| &bars[index])
+        .map(|bar| (bar, origin.distance_miles(&bar.position)))
+        .filter(|&(_, distance)| distance <= radius_miles)
+        .collect()
+}
+
+/// Drop tips whose recorded search phrase matches don't appear in their text, along with any bars
+/// left without tips.
+///
+/// The scraper only records phrases it found, so a mismatch means the file was corrupted or edited
+/// by hand.
+fn drop_unverified_tips(bars: &mut Vec<Bar>) {
+    for bar in bars.iter_mut() {
+        let before = bar.tips.len();
+        bar.tips.retain(Tip::matches_are_genuine);
+        if bar.tips.len() != before {
+            warn!(
+                "Dropped {} tips for bar {} whose matched phrases don't appear in the text",
+                before - bar.tips.len(),
+                bar.id
+            );
+        }
+    }
+    bars.retain(|bar| !bar.tips.is_empty());
+}
+
+/// The file `BARS_FILE_PATH` resolves to and when that was modified, or None if there is no
+/// listing.
+///
+/// The scraper publishes a listing by pointing the symlink at a new file rather than rewriting the
+/// one it points at, so the target tells listings apart even where modification times are coarse.
+fn listing_file_version() -> Option<(PathBuf, SystemTime)> {
+    let target = fs::canonicalize(BARS_FILE_PATH).ok()?;
+    let modified = fs::metadata(&target)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    Some((target, modified))
+}
+
+/// The directory the scraper writes the partitions of a listing file into with
+/// `--partition-by-neighborhood`, named after the file, such as `20190401/` for `20190401.json`.
+fn partition_dir(listing_file: &Path) -> PathBuf {
+    listing_file.with_extension("")
+}
+
+/// An entry of the partition index, naming the file holding a neighborhood's bars.
+#[derive(Deserialize)]
+struct PartitionIndexEntry {
+    neighborhood: String,
+    file: String,
+}
+
+/// The partitions of a listing read so far, see `BarListing::neighborhood_bars`.
+#[derive(Default)]
+struct Partitions {
+    /// Directory the partitions were read from, None until one has been.
+    dir: Option<PathBuf>,
+    index: Vec<PartitionIndexEntry>,
+    /// Summaries of the bars in each partition read, by its file name.
+    bars: HashMap<String, Vec<BarSummary>>,
+}
+
+/// A bar suggested to the user.
+pub struct Suggestion {
+    pub id: String,
+    pub name: String,
+    /// A randomly selected tip mentioning picklebacks.
+    pub comment: String,
+    /// Distinct tips mentioning picklebacks, starting with the comment.
+    pub tips: Vec<String>,
+    pub fsq_url: Option<String>,
+    pub neighborhood: Option<String>,
+    pub seasonal: Option<bool>,
+    pub photo_url: Option<String>,
+    pub distance_miles: f64,
+    /// Other bars in range, closest first.
+    pub alternates: Vec<Alternate>,
+}
+
+/// Another bar in range, offered alongside a suggestion.
+pub struct Alternate {
+    pub id: String,
+    pub name: String,
+    pub neighborhood: Option<String>,
+    pub distance_miles: f64,
+}
+
+/// A bar in a ranked listing.
+pub struct RankedBar {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub score: f64,
+}
+
+/// A bar near the user, one of several offered to choose from.
+pub struct NearbyBar {
+    pub id: String,
+    pub name: String,
+    pub neighborhood: Option<String>,
+    pub distance_miles: f64,
+    /// A tip representative of the bar, or the generic fallback comment.
+    pub comment: String,
+}
+
+/// What the listing knows about a bar, for clients drawing it on a map.
+#[derive(Clone)]
+pub struct BarSummary {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub tip_count: usize,
+    pub score: f64,
+    pub rating: Option<f64>,
+    pub popularity: Option<u64>,
+    pub fsq_url: Option<String>,
+    pub seasonal: Option<bool>,
+}
+
+impl<'a> From<&'a Bar> for BarSummary {
+    fn from(bar: &'a Bar) -> Self {
+        BarSummary {
+            id: bar.id.clone(),
+            name: bar.name.clone(),
+            lat: bar.lat,
+            lng: bar.lng,
+            tip_count: bar.tips.len(),
+            score: bar.score,
+            rating: bar.rating,
+            popularity: bar.popularity,
+            fsq_url: bar.fsq_url.clone(),
+            seasonal: bar.seasonal,
+        }
+    }
+}
+
+/// Everything the listing records about a bar, as shown on its page.
+pub struct BarDetails {
+    pub id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub tips: Vec<String>,
+    pub score: f64,
+    pub rating: Option<f64>,
+    pub popularity: Option<u64>,
+    pub fsq_url: Option<String>,
+    pub neighborhood: Option<String>,
+    pub seasonal: Option<bool>,
+    pub photo_url: Option<String>,
+}
+
+impl<'a> From<&'a Bar> for BarDetails {
+    fn from(bar: &'a Bar) -> Self {
+        BarDetails {
+            id: bar.id.clone(),
+            name: bar.name.clone(),
+            lat: bar.lat,
+            lng: bar.lng,
+            tips: bar.tips.iter().map(|tip| tip.text.clone()).collect(),
+            score: bar.score,
+            rating: bar.rating,
+            popularity: bar.popularity,
+            fsq_url: bar.fsq_url.clone(),
+            neighborhood: bar.neighborhood.clone(),
+            seasonal: bar.seasonal,
+            photo_url: bar.photo_url.clone(),
+        }
+    }
+}
+
+/// Index of each bar by its Foursquare id. Should an id appear twice, the first bar with it wins.
+fn index_by_id(bars: &[Bar]) -> HashMap<String, usize> {
+    let mut by_id = HashMap::with_capacity(bars.len());
+    for (index, bar) in bars.iter().enumerate() {
+        by_id.entry(bar.id.clone()).or_insert(index);
+    }
+    by_id
+}
+
+/// Neighborhood which bars with no recorded neighborhood are counted under.
+pub const OTHER_NEIGHBORHOOD: &str = "other";
+
+/// How many bars the listing has in a neighborhood, and the area they span.
+#[derive(Clone)]
+pub struct NeighborhoodCoverage {
+    pub neighborhood: String,
+    pub bars: usize,
+    pub min_lat: f64,
+    pub min_lng: f64,
+    pub max_lat: f64,
+    pub max_lng: f64,
+}
+
+/// Summarize where a listing has bars, by neighborhood in alphabetical order.
+fn compute_coverage(bars: &[Bar]) -> Vec<NeighborhoodCoverage> {
+    let mut coverage: BTreeMap<&str, NeighborhoodCoverage> = BTreeMap::new();
+    for bar in bars {
+        let neighborhood = bar.neighborhood.as_deref().unwrap_or(OTHER_NEIGHBORHOOD);
+        let entry = coverage
+            .entry(neighborhood)
+            .or_insert_with(|| NeighborhoodCoverage {
+                neighborhood: neighborhood.to_string(),
+                bars: 0,
+                min_lat: bar.lat,
+                min_lng: bar.lng,
+                max_lat: bar.lat,
+                max_lng: bar.lng,
+            });
+        entry.bars += 1;
+        entry.min_lat = entry.min_lat.min(bar.lat);
+        entry.min_lng = entry.min_lng.min(bar.lng);
+        entry.max_lat = entry.max_lat.max(bar.lat);
+        entry.max_lng = entry.max_lng.max(bar.lng);
+    }
+    coverage.into_values().collect()
+}
+
+/// How `locate_pickleback` chooses between the bars in range.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocateMode {
+    /// At random, favoring closer bars.
+    #[default]
+    Weighted,
+    /// Always the closest bar, ties broken by id.
+    Nearest,
+}
+
+/// How a bar should be picked for the user.
+pub struct LocateOptions {
+    /// Only bars within this distance of the user are considered.
+    pub radius_miles: f64,
+    /// Number of distinct tips to return alongside the comment.
+    pub tip_count: usize,
+    /// Languages the user would like the comment in, most preferred first.
+    pub languages: Vec<String>,
+    pub mode: LocateMode,
+    /// Prefer bars with a photo, only suggesting one without if none in range have one.
+    pub has_photo: bool,
+    /// Number of other bars in range to offer alongside the suggestion.
+    pub alternates: usize,
+    /// Least distance between the suggestion and its alternates, and between alternates, where
+    /// there are enough bars that far apart.
+    pub min_alternate_separation_miles: Option<f64>,
+    /// How bars are weighed by distance in `LocateMode::Weighted`.
+    pub utility: Utility,
 }
 
 pub struct BarListing {
     bars: RwLock<Vec<Bar>>,
+    /// Index of where `bars` are. It is only replaced while holding the write lock on `bars`, and
+    /// only read while holding a read lock on it, so the two always match.
+    grid: RwLock<Grid>,
+    /// Index of `bars` by id, see `index_by_id`, kept in step with them as `grid` is.
+    by_id: RwLock<HashMap<String, usize>>,
+    /// Summary of where the loaded listing has bars, see `compute_coverage`.
+    coverage: RwLock<Vec<NeighborhoodCoverage>>,
+    /// Whether a listing has ever been loaded successfully.
+    loaded: AtomicBool,
+    /// Where to fetch the listing from, if not `BARS_FILE_PATH`.
+    bars_url: Option<String>,
+    /// The version of `BARS_FILE_PATH` last loaded, or tried, see `listing_file_version`.
+    file_version: RwLock<Option<(PathBuf, SystemTime)>>,
+    /// Partitions of the listing file by neighborhood, read as clients ask for them.
+    partitions: Mutex<Partitions>,
+    /// Where the site is served, for the links in the sitemap.
+    site_url: Option<String>,
+    /// The sitemap of the loaded listing, built when it is loaded, if there is a site URL.
+    sitemap: RwLock<Option<String>>,
+    verify_tip_matches: bool,
+    min_comment_words: usize,
+    comment_ranking: CommentRanking,
+    comment_fallback: CommentFallback,
+    fallback_comment: String,
+    /// Most distance the query point is moved before weighting bars by distance, see
+    /// `locate_pickleback`.
+    query_jitter_miles: Option<f64>,
 }
 
 impl BarListing {
-    /// Create a new directory of bars serving picklebacks.
+    /// Create a new directory of bars serving picklebacks, loading the initial listing from disk,
+    /// or from the configured URL.
     ///
-    /// # Panics
-    /// This panics if we can't load the initial listing from disk.
-    pub fn new() -> Self {
-        let mut f: File = File::open(BARS_FILE_PATH).unwrap();
-        let bars: Vec<Bar> = serde_json::from_reader(&mut f).unwrap();
+    /// If the listing can't be loaded, such as before the first scrape, the error is logged and
+    /// the directory starts out empty, so the server can still come up and serve its pages.
+    /// `is_loaded` tells whether there are bars yet.
+    pub fn new(config: &Config) -> Self {
+        let listing = Self::without_bars(config);
+        listing.reload_bars();
+        listing
+    }
+
+    /// Create a listing which has no bars until they are loaded, with `reload_bars` or
+    /// `replace_bars_from_json`.
+    pub fn without_bars(config: &Config) -> Self {
         Self {
-            bars: RwLock::new(bars),
+            bars: RwLock::new(Vec::new()),
+            grid: RwLock::new(Grid::default()),
+            by_id: RwLock::new(HashMap::new()),
+            coverage: RwLock::new(Vec::new()),
+            loaded: AtomicBool::new(false),
+            bars_url: config.bars_url.clone(),
+            file_version: RwLock::new(None),
+            partitions: Mutex::new(Partitions::default()),
+            site_url: config.site_url.clone(),
+            sitemap: RwLock::new(None),
+            verify_tip_matches: config.verify_tip_matches,
+            min_comment_words: config.min_comment_words,
+            comment_ranking: config.comment_ranking,
+            comment_fallback: config.comment_fallback,
+            fallback_comment: config.fallback_comment.clone(),
+            query_jitter_miles: config
+                .query_jitter_meters
+                .map(|meters| Miles::from(Meters(meters)).0),
+        }
+    }
+
+    /// Serve the listing in `json`, generated just now, in place of the current one. Returns the
+    /// number of bars read.
+    ///
+    /// If the listing can't be parsed, nothing is changed.
+    pub fn replace_bars_from_json(&self, json: &[u8]) -> serde_json::Result<usize> {
+        let bars: Vec<Bar> = serde_json::from_slice(json)?;
+        let count = bars.len();
+        self.install_bars(bars, Some(SystemTime::now()));
+        Ok(count)
+    }
+
+    /// Prepare freshly loaded bars for serving, and put them in place of the current listing.
+    fn install_bars(&self, mut bars: Vec<Bar>, generated: Option<SystemTime>) {
+        if self.verify_tip_matches {
+            drop_unverified_tips(&mut bars);
+        }
+        set_positions(&mut bars);
+        let coverage = compute_coverage(&bars);
+        let grid = Grid::new(&bars);
+        let by_id = index_by_id(&bars);
+
+        let mut current = self.bars.write().unwrap();
+        *self.grid.write().unwrap() = grid;
+        *self.by_id.write().unwrap() = by_id;
+        *current = bars;
+        drop(current);
+        // Bar pages in the sitemap are marked as modified when the listing was generated.
+        *self.sitemap.write().unwrap() = self
+            .site_url
+            .as_ref()
+            .map(|site_url| pages::sitemap(site_url, &self.bar_ids(), generated));
+        *self.coverage.write().unwrap() = coverage;
+        self.loaded.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether bar data has been loaded, so that we're able to serve suggestions.
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::SeqCst)
+    }
+
+    /// Read the directory of bars from disk, or fetch it from the configured URL, along with when
+    /// it was generated if we can tell.
+    fn read_bars(&self) -> Result<(Vec<Bar>, Option<SystemTime>), String> {
+        match &self.bars_url {
+            Some(url) => {
+                let (body, generated) = remote::fetch_listing(url)?;
+                let bars = serde_json::from_slice(&body).map_err(|err| {
+                    format!(
+                        "Couldn't parse bar listing from {} {:?}",
+                        remote::display_url(url),
+                        err
+                    )
+                })?;
+                Ok((bars, generated))
+            }
+            None => {
+                let mut file = File::open(BARS_FILE_PATH).map_err(|err| {
+                    format!(
+                        "Couldn't open bar listing file {} {:?}",
+                        BARS_FILE_PATH, err
+                    )
+                })?;
+                let bars = serde_json::from_reader(&mut file).map_err(|err| {
+                    format!(
+                        "Couldn't parse bar liting file {} {:?}",
+                        BARS_FILE_PATH, err
+                    )
+                })?;
+                let generated = file
+                    .metadata()
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                Ok((bars, generated))
+            }
         }
     }
 
-    /// Attempt to reload the directory of bars from disk.
+    /// Attempt to reload the directory of bars from disk, or from the configured URL.
     ///
     /// This can fail for various IO related reasons, including if the bar directory file is not
-    /// present or is corrupt. In these cases, nothing is changed and we continue using the
-    /// previously loaded listing.
+    /// present or is corrupt, or the URL can't be fetched. In these cases, nothing is changed and
+    /// we continue using the previously loaded listing.
     pub fn reload_bars(&self) {
         info!("Reloading bar listing");
-        match File::open(BARS_FILE_PATH) {
-            Ok(mut file) => match serde_json::from_reader(&mut file) {
-                Ok(bars) => {
-                    let mut listing = self.bars.write().unwrap();
-                    *listing = bars;
-                    info!("Successfully reloaded bar listing");
-                }
-                Err(err) => error!(
-                    "Couldn't parse bar liting file {} {:?}",
-                    BARS_FILE_PATH, err
-                ),
-            },
-            Err(err) => error!(
-                "Couldn't open bar listing file {} {:?}",
-                BARS_FILE_PATH, err
-            ),
+        // Taken before reading, so a listing published mid-read is seen as a change next time.
+        if self.bars_url.is_none() {
+            *self.file_version.write().unwrap() = listing_file_version();
+        }
+        match self.read_bars() {
+            Ok((bars, generated)) => {
+                self.install_bars(bars, generated);
+                info!("Successfully reloaded bar listing");
+            }
+            Err(err) => error!("{}", err),
+        }
+    }
+
+    /// Reload the directory of bars from disk if the listing file has changed since it was last
+    /// loaded, or tried, such as when the scraper points the symlink at a new listing.
+    ///
+    /// A listing which failed to load isn't tried again until it changes. Listings fetched from a
+    /// URL aren't watched.
+    pub fn reload_if_changed(&self) {
+        if self.bars_url.is_some() {
+            return;
+        }
+        let version = listing_file_version();
+        if version.is_some() && version != *self.file_version.read().unwrap() {
+            self.reload_bars();
         }
     }
 
-    /// Given a location, locate a random bar nearby that serves picklebacks.
+    /// Given a location, locate a bar within the search radius that serves picklebacks.
     ///
-    /// The returned tuple has the form (ID, Name, Comment), where comment is a randomly selected
-    /// comment for the bar mentioning picklebacks. This picks bars based on a crude weighting by
-    /// distance, closer bars will be returned more often. If there are no bars nearby, None is
-    /// returned.
+    /// By default this picks bars based on a crude weighting by distance, closer bars will be
+    /// returned more often. `LocateMode::Nearest` instead always picks the closest bar. The
+    /// suggestion includes a comment for the bar mentioning picklebacks, chosen as described by
+    /// `choose_comment` or else the generic fallback comment, along with up to `tip_count` distinct
+    /// tips starting with that comment, chosen the same way in order of preference. With `has_photo`, only bars with a photo are
+    /// considered, unless none nearby have one. Up to `alternates` other bars in range are included
+    /// too, as described by `choose_alternates`. If there are no bars nearby, None is returned.
+    ///
+    /// With a query jitter configured, weighted choices weigh bars by their distance from a point
+    /// moved up to that far from the given one, so that repeated queries from the same spot spread
+    /// across the bars around it. Which bars are in range is still decided from the given point,
+    /// so jitter never drops a close bar or brings in a distant one, and reported distances are
+    /// from the given point. The cost is that the closest bar is suggested less often than its
+    /// distance alone would justify.
+    pub fn locate_pickleback(
+        &self,
+        lat: f64,
+        lng: f64,
+        options: &LocateOptions,
+    ) -> Option<Suggestion> {
+        self.locate_pickleback_with_rng(lat, lng, options, &mut thread_rng())
+    }
+
+    /// Suggest a bar as `locate_pickleback` does, making every random choice with `rng`, so that a
+    /// seeded one gives the same suggestion each time.
     #[allow(clippy::blacklisted_name)]
-    pub fn locate_pickleback(&self, lat: f64, lng: f64) -> Option<(String, String, String)> {
+    pub fn locate_pickleback_with_rng<R: Rng>(
+        &self,
+        lat: f64,
+        lng: f64,
+        options: &LocateOptions,
+        rng: &mut R,
+    ) -> Option<Suggestion> {
+        let bars = self.bars.read().unwrap();
+        let grid = self.grid.read().unwrap();
+
+        let mut nearby = bars_within(&bars, &grid, lat, lng, options.radius_miles);
+        if options.has_photo {
+            nearby = prefer_photos(nearby);
+        }
+        let (bar, distance_miles) = match (options.mode, self.query_jitter_miles) {
+            (LocateMode::Weighted, Some(jitter_miles)) => {
+                let origin = Position::new(lat, lng);
+                let jittered = origin.jittered(jitter_miles, rng);
+                let reweighted: Vec<(&Bar, f64)> = nearby
+                    .iter()
+                    .map(|&(bar, _)| (bar, jittered.distance_miles(&bar.position)))
+                    .collect();
+                let (bar, _) = choose_weighted(&options.utility, &reweighted, rng)?;
+                (bar, origin.distance_miles(&bar.position))
+            }
+            (LocateMode::Weighted, None) => choose_weighted(&options.utility, &nearby, rng)?,
+            (LocateMode::Nearest, _) => nearby.iter().cloned().min_by(|a, b| {
+                a.1.partial_cmp(&b.1)
+                    .unwrap()
+                    .then_with(|| a.0.id.cmp(&b.0.id))
+            })?,
+        };
+
+        // The comment is the most preferred tip, and any more tips asked for are the next most
+        // preferred, so they are chosen the same way.
+        let ranked = rank_comments(
+            &bar.tips,
+            &options.languages,
+            self.min_comment_words,
+            self.comment_ranking,
+            self.comment_fallback,
+            rng,
+        );
+        let comment = ranked
+            .first()
+            .map_or_else(|| self.fallback_comment.clone(), |tip| tip.text.clone());
+        let mut tips: Vec<String> = Vec::new();
+        for tip in ranked {
+            if tips.len() >= options.tip_count {
+                break;
+            }
+            if !tips.contains(&tip.text) {
+                tips.push(tip.text.clone());
+            }
+        }
+
+        Some(Suggestion {
+            id: bar.id.clone(),
+            name: bar.name.clone(),
+            comment,
+            tips,
+            fsq_url: bar.fsq_url.clone(),
+            neighborhood: bar.neighborhood.clone(),
+            seasonal: bar.seasonal,
+            photo_url: bar.photo_url.clone(),
+            distance_miles,
+            alternates: choose_alternates(
+                &nearby,
+                bar,
+                options.alternates,
+                options.min_alternate_separation_miles,
+            )
+            .into_iter()
+            .map(|(alternate, distance_miles)| Alternate {
+                id: alternate.id.clone(),
+                name: alternate.name.clone(),
+                neighborhood: alternate.neighborhood.clone(),
+                distance_miles,
+            })
+            .collect(),
+        })
+    }
+
+    /// The `limit` bars closest to a location within `radius_miles`, closest first with ties
+    /// broken by id, each with a comment chosen as `locate_pickleback` chooses them. With
+    /// `has_photo`, only bars with a photo are listed, unless none nearby have one. Gives up if
+    /// `deadline` passes part way through.
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearby_picklebacks(
+        &self,
+        lat: f64,
+        lng: f64,
+        radius_miles: f64,
+        languages: &[String],
+        has_photo: bool,
+        limit: usize,
+        deadline: Deadline,
+    ) -> Result<Vec<NearbyBar>, Expired> {
         let bars = self.bars.read().unwrap();
+        let grid = self.grid.read().unwrap();
         let mut rng = thread_rng();
 
-        let mut total_utility: f64 = 0f64;
-        for bar in &*bars {
-            let distance: f64 = distance_latlong(lat, lng, bar.lat, bar.lng);
-            if distance > MAXIMUM_DITANCE_MILES {
-                continue;
+        let mut nearby = bars_within(&bars, &grid, lat, lng, radius_miles);
+        if has_photo {
+            nearby = prefer_photos(nearby);
+        }
+        deadline.check()?;
+        nearby.sort_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap()
+                .then_with(|| a.0.id.cmp(&b.0.id))
+        });
+        nearby
+            .into_iter()
+            .take(limit)
+            .enumerate()
+            .map(|(index, (bar, distance_miles))| {
+                deadline.check_every(index)?;
+                let comment = choose_comment(
+                    &bar.tips,
+                    languages,
+                    self.min_comment_words,
+                    self.comment_ranking,
+                    self.comment_fallback,
+                    &mut rng,
+                );
+                Ok(NearbyBar {
+                    id: bar.id.clone(),
+                    name: bar.name.clone(),
+                    neighborhood: bar.neighborhood.clone(),
+                    distance_miles,
+                    comment: comment
+                        .map_or_else(|| self.fallback_comment.clone(), |tip| tip.text.clone()),
+                })
+            })
+            .collect()
+    }
+
+    /// The `limit` bars with the highest quality score, best first, regardless of location. Bars
+    /// with the same score are in order of id, so the ranking doesn't change between requests.
+    pub fn top_bars(&self, limit: usize) -> Vec<RankedBar> {
+        let bars = self.bars.read().unwrap();
+        let mut ranked: Vec<&Bar> = bars.iter().collect();
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        ranked
+            .into_iter()
+            .take(limit)
+            .map(|bar| RankedBar {
+                id: bar.id.clone(),
+                name: bar.name.clone(),
+                lat: bar.lat,
+                lng: bar.lng,
+                score: bar.score,
+            })
+            .collect()
+    }
+
+    /// Number of bars in the listing.
+    pub fn bar_count(&self) -> usize {
+        self.bars.read().unwrap().len()
+    }
+
+    /// Every bar within the box with the given south west and north east corners, as `(lat, lng)`,
+    /// see `point_in_box`. Gives up if `deadline` passes part way through.
+    pub fn bars_in_box(
+        &self,
+        sw: (f64, f64),
+        ne: (f64, f64),
+        deadline: Deadline,
+    ) -> Result<Vec<BarSummary>, Expired> {
+        let bars = self.bars.read().unwrap();
+        let mut found = Vec::new();
+        for (index, bar) in bars.iter().enumerate() {
+            deadline.check_every(index)?;
+            if coordinates::point_in_box(bar.lat, bar.lng, sw, ne) {
+                found.push(BarSummary::from(bar));
             }
+        }
+        Ok(found)
+    }
+
+    /// Up to `limit` bars in listing order starting from the `offset`th, along with the number of
+    /// bars in the whole listing. Gives up if `deadline` passes part way through.
+    pub fn bars_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        deadline: Deadline,
+    ) -> Result<(Vec<BarSummary>, usize), Expired> {
+        let bars = self.bars.read().unwrap();
+        let page = bars
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .enumerate()
+            .map(|(index, bar)| {
+                deadline.check_every(index)?;
+                Ok(BarSummary::from(bar))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((page, bars.len()))
+    }
+
+    /// The sitemap of the loaded listing, or None if there is no site URL to link to.
+    pub fn sitemap(&self) -> Option<String> {
+        self.sitemap.read().unwrap().clone()
+    }
+
+    /// The Foursquare ids of every bar in the listing.
+    pub fn bar_ids(&self) -> Vec<String> {
+        self.bars
+            .read()
+            .unwrap()
+            .iter()
+            .map(|bar| bar.id.clone())
+            .collect()
+    }
+
+    /// Everything about the bar with the given Foursquare id, if it is in the listing.
+    pub fn get_bar(&self, id: &str) -> Option<BarDetails> {
+        let bars = self.bars.read().unwrap();
+        let index = *self.by_id.read().unwrap().get(id)?;
+        Some(BarDetails::from(&bars[index]))
+    }
+
+    /// Where the listing has bars, by neighborhood.
+    pub fn coverage(&self) -> Vec<NeighborhoodCoverage> {
+        self.coverage.read().unwrap().clone()
+    }
+
+    /// The bars in the loaded listing file's partition for `neighborhood`, named as in the
+    /// partition index or by its file name without `.json`, such as `lower-east-side`.
+    ///
+    /// Partitions are read from disk the first time they are asked for, and again once a new
+    /// listing is published. None if there is no such partition, including when the listing
+    /// wasn't partitioned with `--partition-by-neighborhood` or is fetched from a URL.
+    pub fn neighborhood_bars(&self, neighborhood: &str) -> Result<Option<Vec<BarSummary>>, String> {
+        let listing_file = match &*self.file_version.read().unwrap() {
+            Some((target, _)) => target.clone(),
+            None => return Ok(None),
+        };
+        self.partition_bars(&partition_dir(&listing_file), neighborhood)
+    }
+
+    /// The bars in the partition for `neighborhood` in `dir`, see `neighborhood_bars`.
+    fn partition_bars(
+        &self,
+        dir: &Path,
+        neighborhood: &str,
+    ) -> Result<Option<Vec<BarSummary>>, String> {
+        let mut partitions = self.partitions.lock().unwrap();
+        if partitions.dir.as_deref() != Some(dir) {
+            let index_path = dir.join(PARTITION_INDEX_FILE_NAME);
+            let index = match File::open(&index_path) {
+                Ok(file) => serde_json::from_reader(file).map_err(|err| {
+                    format!(
+                        "Couldn't parse partition index {} {:?}",
+                        index_path.display(),
+                        err
+                    )
+                })?,
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => {
+                    return Err(format!(
+                        "Couldn't open partition index {} {:?}",
+                        index_path.display(),
+                        err
+                    ))
+                }
+            };
+            *partitions = Partitions {
+                dir: Some(dir.to_path_buf()),
+                index,
+                bars: HashMap::new(),
+            };
+        }
 
-            total_utility += utility_from_distance(distance);
+        let file = match partitions.index.iter().find(|entry| {
+            entry.neighborhood.eq_ignore_ascii_case(neighborhood)
+                || entry.file.trim_end_matches(".json") == neighborhood
+        }) {
+            Some(entry) => entry.file.clone(),
+            None => return Ok(None),
+        };
+        if let Some(bars) = partitions.bars.get(&file) {
+            return Ok(Some(bars.clone()));
         }
 
-        if total_utility == 0.0 {
-            return None;
+        let path = dir.join(&file);
+        let mut bars: Vec<Bar> = File::open(&path)
+            .map_err(|err| format!("Couldn't open partition {} {:?}", path.display(), err))
+            .and_then(|file| {
+                serde_json::from_reader(file)
+                    .map_err(|err| format!("Couldn't parse partition {} {:?}", path.display(), err))
+            })?;
+        if self.verify_tip_matches {
+            drop_unverified_tips(&mut bars);
         }
+        let summaries: Vec<BarSummary> = bars.iter().map(BarSummary::from).collect();
+        partitions.bars.insert(file, summaries.clone());
+        Ok(Some(summaries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::Duration;
+
+    /// Where the user is in these tests.
+    const LAT: f64 = 40.7;
+    const LNG: f64 = -74.0;
+
+    /// A listing of the bars in `json`, as the scraper writes them.
+    fn listing(json: &str) -> BarListing {
+        let listing = BarListing::without_bars(&Config::default());
+        listing.replace_bars_from_json(json.as_bytes()).unwrap();
+        listing
+    }
+
+    /// The distance in miles between two (lat, lng) pairs as computed before positions were cached,
+    /// converting to radians and taking the cosines on every call.
+    fn uncached_distance_miles(lat1: f64, lng1: f64, lat2: f64, lng2: f64) -> f64 {
+        let d_lat: f64 = (lat2 - lat1).to_radians();
+        let d_lon: f64 = (lng2 - lng1).to_radians();
+        let a = (d_lat / 2.0).sin().powf(2.0)
+            + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powf(2.0);
+        let c = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+        3959.0 * c
+    }
+
+    /// Points spread over the area around Manhattan, as bars would be.
+    fn manhattan_points(count: usize) -> Vec<(f64, f64)> {
+        (0..count)
+            .map(|i| {
+                let step = i as f64 / count as f64;
+                (
+                    40.70 + 0.1 * step,
+                    -74.02 + 0.07 * ((i * 7919) % count) as f64 / count as f64,
+                )
+            })
+            .collect()
+    }
+
+    /// Three bars due north of the user, about a third, two thirds and one mile away.
+    fn three_bars() -> BarListing {
+        listing(
+            r#"[
+                {"id": "a", "name": "A", "lat": 40.705, "lng": -74.0, "tips": ["Pickleback!"]},
+                {"id": "b", "name": "B", "lat": 40.710, "lng": -74.0, "tips": ["Pickleback?"]},
+                {"id": "c", "name": "C", "lat": 40.7145, "lng": -74.0, "tips": ["Pickleback."]}
+            ]"#,
+        )
+    }
+
+    fn weighted_options() -> LocateOptions {
+        LocateOptions {
+            radius_miles: 3.0,
+            tip_count: 0,
+            languages: Vec::new(),
+            mode: LocateMode::Weighted,
+            has_photo: false,
+            alternates: 0,
+            min_alternate_separation_miles: None,
+            utility: Utility::default(),
+        }
+    }
+
+    /// The ids of 20 suggestions made with an RNG seeded with `seed`.
+    fn suggested_ids(listing: &BarListing, options: &LocateOptions, seed: u64) -> Vec<String> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..20)
+            .map(|_| {
+                listing
+                    .locate_pickleback_with_rng(LAT, LNG, options, &mut rng)
+                    .unwrap()
+                    .id
+            })
+            .collect()
+    }
 
-        let choice: f64 = rng.gen_range(0.0, total_utility);
+    #[test]
+    fn seeded_rng_suggests_the_same_bars() {
+        let listing = three_bars();
+        let options = weighted_options();
+        assert_eq!(
+            suggested_ids(&listing, &options, 7),
+            suggested_ids(&listing, &options, 7)
+        );
+    }
+
+    #[test]
+    fn seeded_rng_picks_bars_in_proportion_to_utility() {
+        let listing = three_bars();
+        let options = weighted_options();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut picks: HashMap<String, (usize, f64)> = HashMap::new();
+        let draws = 20_000;
+        for _ in 0..draws {
+            let suggestion = listing
+                .locate_pickleback_with_rng(LAT, LNG, &options, &mut rng)
+                .unwrap();
+            picks
+                .entry(suggestion.id)
+                .or_insert((0, suggestion.distance_miles))
+                .0 += 1;
+        }
+
+        let utilities: HashMap<&str, f64> = picks
+            .iter()
+            .map(|(id, &(_, distance_miles))| {
+                let utility = options
+                    .utility
+                    .scoring
+                    .utility_from_distance(distance_miles);
+                (id.as_str(), utility)
+            })
+            .collect();
+        let total_utility: f64 = utilities.values().sum();
+        assert_eq!(picks.len(), 3);
+        for (id, &(count, _)) in &picks {
+            let expected = utilities[id.as_str()] / total_utility;
+            let actual = count as f64 / draws as f64;
+            assert!(
+                (expected - actual).abs() < 0.02,
+                "bar {} picked {} of the time, expected {}",
+                id,
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn nearest_mode_ignores_the_rng() {
+        let listing = three_bars();
+        let options = LocateOptions {
+            mode: LocateMode::Nearest,
+            ..weighted_options()
+        };
+        for seed in 0..5 {
+            let ids = suggested_ids(&listing, &options, seed);
+            assert!(ids.iter().all(|id| id == "a"), "{:?}", ids);
+        }
+    }
+
+    /// A bar with 16 tips and one with a single tip, the same distance north and south of the user.
+    fn famous_and_offhand_bars() -> BarListing {
+        let famous_tips: Vec<String> = (0..16).map(|i| format!("Pickleback #{}", i)).collect();
+        listing(
+            &serde_json::json!([
+                {"id": "famous", "name": "F", "lat": 40.705, "lng": -74.0, "tips": famous_tips},
+                {"id": "offhand", "name": "O", "lat": 40.695, "lng": -74.0, "tips": ["Pickleback"]}
+            ])
+            .to_string(),
+        )
+    }
+
+    /// How many of 1000 suggestions with `popularity_weight` are of the bar with many tips.
+    fn famous_picks(popularity_weight: f64) -> usize {
+        let listing = famous_and_offhand_bars();
+        let options = LocateOptions {
+            utility: Utility {
+                popularity_weight,
+                ..Utility::default()
+            },
+            ..weighted_options()
+        };
+        let mut rng = StdRng::seed_from_u64(3);
+        (0..1000)
+            .filter(|_| {
+                listing
+                    .locate_pickleback_with_rng(LAT, LNG, &options, &mut rng)
+                    .unwrap()
+                    .id
+                    == "famous"
+            })
+            .count()
+    }
+
+    fn capped(max_bar_utility: Option<f64>) -> Utility {
+        Utility {
+            max_bar_utility,
+            ..Utility::default()
+        }
+    }
+
+    #[test]
+    fn bar_utility_is_clamped_to_the_maximum() {
+        let bar: Bar = serde_json::from_value(serde_json::json!({
+            "id": "a", "name": "A", "lat": LAT, "lng": LNG, "tips": ["Pickleback!"],
+        }))
+        .unwrap();
+        // Right next to the user the utility is 5000 / 0.96, and a mile away 5000 / 40.96.
+        assert!((bar_utility(&capped(None), &bar, 0.0) - 5208.33).abs() < 0.01);
+        assert_eq!(bar_utility(&capped(Some(1000.0)), &bar, 0.0), 1000.0);
+        assert_eq!(bar_utility(&capped(Some(1000.0)), &bar, 0.5), 1000.0);
+        assert_eq!(
+            bar_utility(&capped(Some(1000.0)), &bar, 1.0),
+            bar_utility(&capped(None), &bar, 1.0)
+        );
+
+        // Seasonal bars are weighed down from the clamped utility.
+        let seasonal = Bar {
+            seasonal: Some(true),
+            ..bar
+        };
+        let utility = Utility {
+            seasonal_weight: 0.5,
+            ..capped(Some(1000.0))
+        };
+        assert_eq!(bar_utility(&utility, &seasonal, 0.0), 500.0);
+    }
+
+    /// How many of 1000 suggestions are of a bar right by the user, rather than one a mile away.
+    fn picks_of_the_closest(max_bar_utility: Option<f64>) -> usize {
+        let listing = listing(&format!(
+            r#"[
+                {{"id": "here", "name": "Here", "lat": {}, "lng": {}, "tips": ["Pickleback!"]}},
+                {{"id": "away", "name": "Away", "lat": {}, "lng": {}, "tips": ["Pickleback!"]}}
+            ]"#,
+            LAT,
+            LNG,
+            LAT + 1.0 / 69.097,
+            LNG
+        ));
+        let options = LocateOptions {
+            utility: capped(max_bar_utility),
+            ..weighted_options()
+        };
+        let mut rng = StdRng::seed_from_u64(4);
+        (0..1000)
+            .filter(|_| {
+                listing
+                    .locate_pickleback_with_rng(LAT, LNG, &options, &mut rng)
+                    .unwrap()
+                    .id
+                    == "here"
+            })
+            .count()
+    }
+
+    #[test]
+    fn clamping_keeps_the_closest_bar_from_crowding_out_others() {
+        // Weighed about 43 times the other.
+        let picks = picks_of_the_closest(None);
+        assert!(picks > 960, "{} of 1000", picks);
+        // Capped at twice the other's utility, so picked two thirds of the time.
+        let picks = picks_of_the_closest(Some(2.0 * 5000.0 / 40.96));
+        assert!(picks > 620 && picks < 710, "{} of 1000", picks);
+    }
+
+    #[test]
+    fn bar_with_more_tips_is_preferred_at_equal_distance() {
+        // Weighed 16 ^ 0.5 = 4 times the other, so picked four fifths of the time.
+        let picks = famous_picks(0.5);
+        assert!(picks > 750 && picks < 850, "{} of 1000", picks);
+    }
 
-        let mut sweep_utility: f64 = 0.0;
-        for bar in &*bars {
-            let distance: f64 = distance_latlong(lat, lng, bar.lat, bar.lng);
-            if distance > MAXIMUM_DITANCE_MILES {
-                continue;
+    #[test]
+    fn tip_count_is_ignored_without_a_popularity_weight() {
+        let picks = famous_picks(0.0);
+        assert!(picks > 450 && picks < 550, "{} of 1000", picks);
+    }
+
+    fn tip(text: &str, matched: &[&str]) -> Tip {
+        Tip {
+            text: text.to_string(),
+            matched: matched.iter().map(|phrase| phrase.to_string()).collect(),
+            lang: None,
+        }
+    }
+
+    #[test]
+    fn on_topic_tips_are_more_relevant_than_tangential_ones() {
+        let on_topic = tip("Picklebacks! The best pickleback in town", &["pickleback"]);
+        let tangential = tip(
+            "Great burgers, friendly staff, a decent beer list and they do a pickleback too",
+            &["pickleback"],
+        );
+        assert!(on_topic.relevance() > tangential.relevance());
+        assert_eq!(on_topic.relevance(), 2.0 / 6.0);
+
+        let listing = BarListing::without_bars(&Config {
+            comment_ranking: CommentRanking::Relevance,
+            ..Config::default()
+        });
+        let json = serde_json::json!([{"id": "a", "name": "A", "lat": 40.7, "lng": -74.0, "tips": [
+            {"text": tangential.text, "matched": ["pickleback"]},
+            {"text": on_topic.text, "matched": ["pickleback"]}
+        ]}]);
+        listing
+            .replace_bars_from_json(json.to_string().as_bytes())
+            .unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..10 {
+            let suggestion = listing
+                .locate_pickleback_with_rng(LAT, LNG, &weighted_options(), &mut rng)
+                .unwrap();
+            assert_eq!(suggestion.comment, on_topic.text);
+        }
+    }
+
+    #[test]
+    fn relevance_counts_phrases_as_whole_words() {
+        // Only the last word is a pickleback, the others merely contain the phrase.
+        let tip_within_words = tip("Unpickleback picklebacker pickleback", &[]);
+        assert_eq!(tip_within_words.relevance(), 1.0 / 3.0);
+        // Plurals and hyphenated compounds count, as they do when scraping.
+        let plural = tip("Pickle backs and pickle-back shots", &["pickle back"]);
+        assert_eq!(plural.relevance(), 1.0 / 5.0);
+        let defaults = tip("Pickle backs and pickle-back shots", &[]);
+        assert_eq!(defaults.relevance(), 2.0 / 5.0);
+    }
+
+    /// A bar next to the user with tips in several languages, one of them too short to make a good
+    /// comment and one repeated, listed with `comment_fallback`.
+    /// The distinct comments chosen from `tips` over many tries, in order of text, or None where
+    /// no tip was chosen.
+    fn chosen_comments<'a>(
+        tips: &'a [Tip],
+        languages: &[&str],
+        min_words: usize,
+        fallback: CommentFallback,
+    ) -> Vec<Option<&'a str>> {
+        let languages: Vec<String> = languages.iter().map(|lang| lang.to_string()).collect();
+        let mut chosen: Vec<Option<&'a str>> = (0..100)
+            .map(|seed| {
+                let mut rng = StdRng::seed_from_u64(seed);
+                choose_comment(
+                    tips,
+                    &languages,
+                    min_words,
+                    CommentRanking::Random,
+                    fallback,
+                    &mut rng,
+                )
+                .map(|tip| tip.text.as_str())
+            })
+            .collect();
+        chosen.sort();
+        chosen.dedup();
+        chosen
+    }
+
+    #[test]
+    fn longer_tips_are_preferred_as_comments() {
+        let tips = [
+            tip("Pickleback!!", &[]),
+            tip("Get the pickleback here", &[]),
+            tip("pickle back", &[]),
+            tip("Picklebacks on tap, no joke", &[]),
+        ];
+        let long = vec![
+            Some("Get the pickleback here"),
+            Some("Picklebacks on tap, no joke"),
+        ];
+        assert_eq!(chosen_comments(&tips, &[], 4, CommentFallback::Relax), long);
+        assert_eq!(
+            chosen_comments(&tips, &[], 4, CommentFallback::Generic),
+            long
+        );
+        // A tip of exactly the minimum length is long enough.
+        assert_eq!(
+            chosen_comments(&tips, &[], 5, CommentFallback::Relax),
+            [Some("Picklebacks on tap, no joke")]
+        );
+    }
+
+    #[test]
+    fn any_tip_is_a_comment_without_a_minimum_length() {
+        let tips = [
+            tip("Pickleback!!", &[]),
+            tip("Get the pickleback here", &[]),
+        ];
+        assert_eq!(
+            chosen_comments(&tips, &[], 0, CommentFallback::Generic),
+            [Some("Get the pickleback here"), Some("Pickleback!!")]
+        );
+        assert_eq!(
+            chosen_comments(&tips, &[], 1, CommentFallback::Generic),
+            [Some("Get the pickleback here"), Some("Pickleback!!")]
+        );
+    }
+
+    fn tip_in(text: &str, lang: &str) -> Tip {
+        Tip {
+            lang: Some(lang.to_string()),
+            ..tip(text, &[])
+        }
+    }
+
+    #[test]
+    fn short_tips_are_relaxed_to_or_replaced_by_the_generic_comment() {
+        let tips = [tip("Pickleback!!", &[]), tip("pickle back", &[])];
+        assert_eq!(
+            chosen_comments(&tips, &[], 3, CommentFallback::Relax),
+            [Some("Pickleback!!"), Some("pickle back")]
+        );
+        assert_eq!(
+            chosen_comments(&tips, &[], 3, CommentFallback::Generic),
+            [None]
+        );
+        assert_eq!(chosen_comments(&[], &[], 3, CommentFallback::Relax), [None]);
+    }
+
+    #[test]
+    fn relaxing_drops_the_length_before_the_language() {
+        let tips = [
+            tip_in("Le pickleback est excellent ici", "fr"),
+            tip_in("Pickleback!", "en"),
+        ];
+        assert_eq!(
+            chosen_comments(&tips, &["en"], 3, CommentFallback::Relax),
+            [Some("Pickleback!")]
+        );
+        assert_eq!(
+            chosen_comments(&tips, &["de"], 3, CommentFallback::Relax),
+            [Some("Le pickleback est excellent ici")]
+        );
+        assert_eq!(
+            chosen_comments(&tips, &["en"], 3, CommentFallback::Generic),
+            [None]
+        );
+        assert_eq!(
+            chosen_comments(&tips, &["de"], 3, CommentFallback::Generic),
+            [None]
+        );
+    }
+
+    #[test]
+    fn bars_without_a_qualifying_tip_get_the_generic_comment() {
+        let listing = BarListing::without_bars(&Config {
+            comment_fallback: CommentFallback::Generic,
+            fallback_comment: "Ask for a pickleback.".to_string(),
+            ..Config::default()
+        });
+        let json = r#"[{"id": "a", "name": "A", "lat": 40.7, "lng": -74.0, "tips": [
+            {"text": "Pickleback!", "lang": "en"}
+        ]}]"#;
+        listing.replace_bars_from_json(json.as_bytes()).unwrap();
+        let (comment, tips) = suggested_tips(&listing, 3);
+        assert_eq!(comment, "Ask for a pickleback.");
+        assert!(tips.is_empty());
+    }
+
+    fn multilingual_bar(comment_fallback: CommentFallback) -> BarListing {
+        let listing = BarListing::without_bars(&Config {
+            comment_fallback,
+            ..Config::default()
+        });
+        let json = r#"[{"id": "a", "name": "A", "lat": 40.7, "lng": -74.0, "tips": [
+            {"text": "Le pickleback est excellent ici", "lang": "fr"},
+            {"text": "Pickleback!", "lang": "en"},
+            {"text": "Great picklebacks at the bar", "lang": "en"},
+            {"text": "Der Pickleback hier ist toll", "lang": "de"},
+            {"text": "Great picklebacks at the bar", "lang": "en"}
+        ]}]"#;
+        listing.replace_bars_from_json(json.as_bytes()).unwrap();
+        listing
+    }
+
+    /// The tips suggested with the bar in `listing`, asking for up to `tip_count` in German or
+    /// else English.
+    fn suggested_tips(listing: &BarListing, tip_count: usize) -> (String, Vec<String>) {
+        let options = LocateOptions {
+            tip_count,
+            languages: vec!["de".to_string(), "en".to_string()],
+            ..weighted_options()
+        };
+        let mut rng = StdRng::seed_from_u64(5);
+        let suggestion = listing
+            .locate_pickleback_with_rng(LAT, LNG, &options, &mut rng)
+            .unwrap();
+        (suggestion.comment, suggestion.tips)
+    }
+
+    #[test]
+    fn extra_tips_are_the_next_best_comments() {
+        let listing = multilingual_bar(CommentFallback::Relax);
+        let (comment, tips) = suggested_tips(&listing, 10);
+        assert_eq!(comment, "Der Pickleback hier ist toll");
+        assert_eq!(
+            tips,
+            vec![
+                "Der Pickleback hier ist toll",
+                "Great picklebacks at the bar",
+                "Pickleback!",
+                "Le pickleback est excellent ici",
+            ]
+        );
+        assert_eq!(
+            suggested_tips(&listing, 2).1,
+            vec![
+                "Der Pickleback hier ist toll",
+                "Great picklebacks at the bar"
+            ]
+        );
+        assert!(suggested_tips(&listing, 0).1.is_empty());
+    }
+
+    #[test]
+    fn extra_tips_skip_those_the_generic_fallback_rejects() {
+        let listing = multilingual_bar(CommentFallback::Generic);
+        let (comment, tips) = suggested_tips(&listing, 10);
+        assert_eq!(comment, "Der Pickleback hier ist toll");
+        assert_eq!(
+            tips,
+            vec![
+                "Der Pickleback hier ist toll",
+                "Great picklebacks at the bar"
+            ]
+        );
+    }
+
+    /// Bars in a line north of the user, at 0, 100, 300, 1200 and 2400 meters.
+    fn bars_heading_north() -> BarListing {
+        let bar = |id: &str, meters: f64| {
+            serde_json::json!({
+                "id": id,
+                "name": id,
+                "lat": LAT + meters / 111_195.0,
+                "lng": LNG,
+                "tips": ["Pickleback!"],
+            })
+        };
+        let json = serde_json::json!([
+            bar("here", 0.0),
+            bar("next-door", 100.0),
+            bar("down-the-block", 300.0),
+            bar("across-town", 1200.0),
+            bar("further", 2400.0),
+        ]);
+        listing(&json.to_string())
+    }
+
+    /// The ids of up to `count` alternates to the nearest bar, kept `min_separation_miles` apart.
+    fn alternate_ids(count: usize, min_separation_miles: Option<f64>) -> Vec<String> {
+        let options = LocateOptions {
+            mode: LocateMode::Nearest,
+            alternates: count,
+            min_alternate_separation_miles: min_separation_miles,
+            ..weighted_options()
+        };
+        let suggestion = bars_heading_north()
+            .locate_pickleback_with_rng(LAT, LNG, &options, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(suggestion.id, "here");
+        suggestion
+            .alternates
+            .into_iter()
+            .map(|alternate| alternate.id)
+            .collect()
+    }
+
+    #[test]
+    fn alternates_are_the_closest_without_a_separation() {
+        assert_eq!(alternate_ids(2, None), ["next-door", "down-the-block"]);
+    }
+
+    #[test]
+    fn alternates_are_kept_apart_by_the_minimum_separation() {
+        // Half a mile is about 800 meters.
+        assert_eq!(alternate_ids(2, Some(0.5)), ["across-town", "further"]);
+        // At a mile, about 1600 meters, across town is too close to here, so only further is spread
+        // out enough, and the closest bar makes up the number.
+        assert_eq!(alternate_ids(2, Some(1.0)), ["next-door", "further"]);
+    }
+
+    #[test]
+    fn closest_alternates_fill_in_when_too_few_are_spread_out() {
+        assert_eq!(
+            alternate_ids(3, Some(0.5)),
+            ["next-door", "across-town", "further"]
+        );
+    }
+
+    #[test]
+    fn jitter_stays_within_its_distance() {
+        let origin = Position::new(LAT, LNG);
+        let mut rng = StdRng::seed_from_u64(3);
+        let jittered: Vec<Position> = (0..1000).map(|_| origin.jittered(0.25, &mut rng)).collect();
+        let distances: Vec<f64> = jittered
+            .iter()
+            .map(|position| origin.distance_miles(position))
+            .collect();
+        assert!(distances.iter().all(|&distance| distance <= 0.25 + 1e-9));
+        // Spread across the disc rather than bunched at its center or edge, and in every direction.
+        assert!(distances.iter().any(|&distance| distance < 0.05));
+        assert!(distances.iter().any(|&distance| distance > 0.24));
+        assert!(jittered.iter().any(|position| position.lat > origin.lat));
+        assert!(jittered.iter().any(|position| position.lat < origin.lat));
+        assert!(jittered.iter().any(|position| position.lng > origin.lng));
+        assert!(jittered.iter().any(|position| position.lng < origin.lng));
+
+        let unmoved = origin.jittered(0.0, &mut rng);
+        assert_eq!((unmoved.lat, unmoved.lng), (origin.lat, origin.lng));
+    }
+
+    #[test]
+    fn jitter_never_loses_bars_within_the_radius() {
+        let listing = BarListing::without_bars(&Config {
+            query_jitter_meters: Some(400.0),
+            ..Config::default()
+        });
+        // A bar near the edge of a half mile radius, which jitter could move the user away from.
+        let json = serde_json::json!([{
+            "id": "edge",
+            "name": "Edge",
+            "lat": LAT + 0.45 / 69.09,
+            "lng": LNG,
+            "tips": ["Pickleback!"],
+        }]);
+        listing
+            .replace_bars_from_json(json.to_string().as_bytes())
+            .unwrap();
+        let options = LocateOptions {
+            radius_miles: 0.5,
+            ..weighted_options()
+        };
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let suggestion = listing
+                .locate_pickleback_with_rng(LAT, LNG, &options, &mut rng)
+                .unwrap();
+            // The distance given is from where the user really is.
+            assert!((suggestion.distance_miles - 0.45).abs() < 0.001);
+        }
+    }
+
+    #[test]
+    fn venue_urls_are_served_where_the_listing_has_them() {
+        let listing = listing(
+            r#"[
+            {"id": "a", "name": "A", "lat": 40.705, "lng": -74.0, "tips": ["Pickleback!"],
+             "fsq_url": "https://foursquare.com/v/a"},
+            {"id": "b", "name": "B", "lat": 40.710, "lng": -74.0, "tips": ["Pickleback?"]}
+        ]"#,
+        );
+        assert_eq!(
+            listing.get_bar("a").unwrap().fsq_url.as_deref(),
+            Some("https://foursquare.com/v/a")
+        );
+        // Listings written before the URL was recorded still load.
+        assert_eq!(listing.get_bar("b").unwrap().fsq_url, None);
+
+        let options = LocateOptions {
+            mode: LocateMode::Nearest,
+            ..weighted_options()
+        };
+        let suggestion = listing
+            .locate_pickleback_with_rng(40.705, -74.0, &options, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert_eq!(
+            suggestion.fsq_url.as_deref(),
+            Some("https://foursquare.com/v/a")
+        );
+    }
+
+    #[test]
+    fn coverage_counts_the_bars_in_each_neighborhood() {
+        let listing = listing(
+            r#"[
+            {"id": "a", "name": "A", "lat": 40.727, "lng": -73.985, "tips": ["Pickleback!"],
+             "neighborhood": "East Village"},
+            {"id": "b", "name": "B", "lat": 40.810, "lng": -73.950, "tips": ["Pickleback!"],
+             "neighborhood": "Harlem"},
+            {"id": "c", "name": "C", "lat": 40.722, "lng": -73.981, "tips": ["Pickleback!"],
+             "neighborhood": "East Village"}
+        ]"#,
+        );
+        let coverage: Vec<(String, usize, [f64; 4])> = listing
+            .coverage()
+            .into_iter()
+            .map(|entry| {
+                let bounds = [entry.min_lat, entry.min_lng, entry.max_lat, entry.max_lng];
+                (entry.neighborhood, entry.bars, bounds)
+            })
+            .collect();
+        assert_eq!(
+            coverage,
+            [
+                (
+                    "East Village".to_string(),
+                    2,
+                    [40.722, -73.985, 40.727, -73.981]
+                ),
+                ("Harlem".to_string(), 1, [40.810, -73.950, 40.810, -73.950]),
+            ]
+        );
+
+        // The coverage is recomputed when the listing is replaced.
+        listing
+            .replace_bars_from_json(
+                br#"[{"id": "d", "name": "D", "lat": 40.7, "lng": -74.0, "tips": ["Pickleback"]}]"#,
+            )
+            .unwrap();
+        let neighborhoods: Vec<(String, usize)> = listing
+            .coverage()
+            .into_iter()
+            .map(|entry| (entry.neighborhood, entry.bars))
+            .collect();
+        assert_eq!(neighborhoods, [(OTHER_NEIGHBORHOOD.to_string(), 1)]);
+    }
+
+    #[test]
+    fn top_bars_are_the_highest_scored_with_ties_in_order_of_id() {
+        let listing = listing(
+            r#"[
+                {"id": "d", "name": "D", "lat": 40.705, "lng": -74.0, "tips": ["P"], "score": 0.5},
+                {"id": "c", "name": "C", "lat": 40.710, "lng": -74.0, "tips": ["P"], "score": 1.5},
+                {"id": "a", "name": "A", "lat": 40.715, "lng": -74.0, "tips": ["P"]},
+                {"id": "b", "name": "B", "lat": 40.720, "lng": -74.0, "tips": ["P"], "score": 0.5}
+            ]"#,
+        );
+        let ids = |limit| {
+            listing
+                .top_bars(limit)
+                .into_iter()
+                .map(|bar| bar.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(10), vec!["c", "b", "d", "a"]);
+        assert_eq!(ids(2), vec!["c", "b"]);
+        assert_eq!(ids(0), Vec::<String>::new());
+    }
+
+    #[test]
+    fn list_endpoints_give_up_at_a_passed_deadline() {
+        let listing = three_bars();
+        let passed = Deadline::after(Duration::from_secs(0));
+        let box_sw = (40.0, -75.0);
+        let box_ne = (41.0, -73.0);
+        assert_eq!(
+            listing.bars_in_box(box_sw, box_ne, passed).err(),
+            Some(Expired)
+        );
+        assert_eq!(listing.bars_page(0, 10, passed).err(), Some(Expired));
+        assert!(listing
+            .nearby_picklebacks(LAT, LNG, 3.0, &[], false, 10, passed)
+            .is_err());
+
+        let none = Deadline::none();
+        assert_eq!(listing.bars_in_box(box_sw, box_ne, none).unwrap().len(), 3);
+        assert_eq!(listing.bars_page(0, 10, none).unwrap().1, 3);
+        assert_eq!(
+            listing
+                .nearby_picklebacks(LAT, LNG, 3.0, &[], false, 10, none)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn nearby_bars_can_be_limited_to_those_with_a_photo() {
+        let listing = listing(
+            r#"[
+                {"id": "a", "name": "A", "lat": 40.705, "lng": -74.0, "tips": ["Pickleback!"]},
+                {"id": "b", "name": "B", "lat": 40.710, "lng": -74.0, "tips": ["Pickleback?"],
+                 "photo_url": "https://fastly.4sqi.net/img/general/original/b.jpg"},
+                {"id": "c", "name": "C", "lat": 40.7145, "lng": -74.0, "tips": ["Pickleback."],
+                 "photo_url": "https://fastly.4sqi.net/img/general/original/c.jpg"},
+                {"id": "far", "name": "Far", "lat": 40.8, "lng": -74.0, "tips": ["Pickleback"]}
+            ]"#,
+        );
+        let ids = |radius_miles, has_photo| {
+            listing
+                .nearby_picklebacks(LAT, LNG, radius_miles, &[], has_photo, 10, Deadline::none())
+                .unwrap()
+                .into_iter()
+                .map(|bar| bar.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(3.0, false), vec!["a", "b", "c"]);
+        assert_eq!(ids(3.0, true), vec!["b", "c"]);
+        // Only the bar without a photo is in range, so it is listed anyway.
+        assert_eq!(ids(0.5, true), vec!["a"]);
+    }
+
+    /// A listing whose file has been tampered with: bar b's second tip and bar c's only tip claim
+    /// matches which don't appear in their text.
+    const TAMPERED_LISTING: &str = r#"[
+        {"id": "a", "name": "A", "lat": 40.705, "lng": -74.0,
+         "tips": [{"text": "Great Picklebacks here", "matched": ["pickleback"]}]},
+        {"id": "b", "name": "B", "lat": 40.710, "lng": -74.0,
+         "tips": [{"text": "Pickle back heaven", "matched": ["pickle back"]},
+                  {"text": "Best burgers in town", "matched": ["pickleback"]}]},
+        {"id": "c", "name": "C", "lat": 40.7145, "lng": -74.0,
+         "tips": [{"text": "Cheap beer", "matched": ["pickleback"]}]}
+    ]"#;
+
+    #[test]
+    fn tips_with_bogus_matches_are_dropped() {
+        let listing = listing(TAMPERED_LISTING);
+        assert_eq!(listing.bar_ids(), vec!["a", "b"]);
+        assert_eq!(
+            listing.get_bar("b").unwrap().tips,
+            vec!["Pickle back heaven"]
+        );
+    }
+
+    #[test]
+    fn tips_are_kept_when_verification_is_off() {
+        let listing = BarListing::without_bars(&Config {
+            verify_tip_matches: false,
+            ..Config::default()
+        });
+        listing
+            .replace_bars_from_json(TAMPERED_LISTING.as_bytes())
+            .unwrap();
+        assert_eq!(listing.bar_count(), 3);
+        assert_eq!(listing.get_bar("b").unwrap().tips.len(), 2);
+    }
+
+    #[test]
+    fn sitemap_is_built_when_the_listing_is_loaded() {
+        let listing = BarListing::without_bars(&Config {
+            site_url: Some("https://www.pickletrack.com".to_string()),
+            ..Config::default()
+        });
+        assert_eq!(listing.sitemap(), None);
+        listing
+            .replace_bars_from_json(
+                br#"[{"id": "a", "name": "A", "lat": 40.7, "lng": -74.0, "tips": ["Pickleback!"]}]"#,
+            )
+            .unwrap();
+        let sitemap = listing.sitemap().unwrap();
+        assert!(sitemap.contains("<loc>https://www.pickletrack.com/b/a</loc>"));
+
+        // A new listing replaces the sitemap.
+        listing
+            .replace_bars_from_json(
+                br#"[{"id": "b", "name": "B", "lat": 40.7, "lng": -74.0, "tips": ["Pickleback!"]}]"#,
+            )
+            .unwrap();
+        let sitemap = listing.sitemap().unwrap();
+        assert!(!sitemap.contains("/b/a<"));
+        assert!(sitemap.contains("<loc>https://www.pickletrack.com/b/b</loc>"));
+    }
+
+    #[test]
+    fn no_sitemap_without_a_site_url() {
+        assert_eq!(three_bars().sitemap(), None);
+    }
+
+    #[test]
+    fn partitions_are_read_on_demand() {
+        let dir =
+            std::env::temp_dir().join(format!("pickletrack-partitions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("index.json"),
+            r#"[
+                {"neighborhood": "Lower East Side", "file": "lower-east-side.json", "bars": 1},
+                {"neighborhood": "other", "file": "other.json", "bars": 1}
+            ]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("lower-east-side.json"),
+            r#"[{"id": "a", "name": "A", "lat": 40.72, "lng": -73.99, "tips": ["Pickleback!"]}]"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("other.json"),
+            r#"[{"id": "b", "name": "B", "lat": 40.7, "lng": -74.0, "tips": ["Pickleback?"]}]"#,
+        )
+        .unwrap();
+
+        let listing = BarListing::without_bars(&Config::default());
+        let ids = |neighborhood| {
+            listing
+                .partition_bars(&dir, neighborhood)
+                .unwrap()
+                .map(|bars| bars.into_iter().map(|bar| bar.id).collect::<Vec<_>>())
+        };
+        assert_eq!(ids("Lower East Side"), Some(vec!["a".to_string()]));
+        assert_eq!(ids("other"), Some(vec!["b".to_string()]));
+        assert_eq!(ids("Harlem"), None);
+
+        // Once read, a partition is served without going back to disk.
+        fs::remove_file(dir.join("lower-east-side.json")).unwrap();
+        assert_eq!(ids("lower-east-side"), Some(vec!["a".to_string()]));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            listing
+                .partition_bars(&dir.join("missing"), "other")
+                .unwrap()
+                .map(|bars| bars.len()),
+            None
+        );
+    }
+
+    #[test]
+    fn cached_positions_give_the_uncached_distance() {
+        let user = Position::new(LAT, LNG);
+        for (lat, lng) in manhattan_points(1000) {
+            let cached = user.distance_miles(&Position::new(lat, lng));
+            let uncached = uncached_distance_miles(LAT, LNG, lat, lng);
+            assert!(
+                (cached - uncached).abs() < 1e-9,
+                "{} != {}",
+                cached,
+                uncached
+            );
+        }
+    }
+
+    /// Times measuring the distance from many users to every bar, as requests do, with positions
+    /// cached as the listing does and without. Run with
+    /// `cargo test --release -- --ignored --nocapture cached_positions_are_faster`.
+    #[test]
+    #[ignore]
+    fn cached_positions_are_faster() {
+        use std::hint::black_box;
+        use std::time::Instant;
+
+        let bars = manhattan_points(10_000);
+        let users = manhattan_points(200);
+        let positions: Vec<Position> = bars
+            .iter()
+            .map(|&(lat, lng)| Position::new(lat, lng))
+            .collect();
+
+        let started = Instant::now();
+        for &(user_lat, user_lng) in &users {
+            for &(lat, lng) in &bars {
+                black_box(uncached_distance_miles(user_lat, user_lng, lat, lng));
             }
+        }
+        let uncached = started.elapsed();
 
-            let bar_utility = utility_from_distance(distance);
-            if sweep_utility + bar_utility > choice {
-                return Some((
-                    bar.id.clone(),
-                    bar.name.clone(),
-                    bar.tips.choose(&mut rng).unwrap().clone(),
-                ));
+        let started = Instant::now();
+        for &(user_lat, user_lng) in &users {
+            let user = Position::new(user_lat, user_lng);
+            for position in &positions {
+                black_box(user.distance_miles(position));
             }
-            sweep_utility += bar_utility;
         }
+        let cached = started.elapsed();
 
-        unreachable!();
+        println!(
+            "{} distances: uncached {:?}, cached {:?}",
+            users.len() * bars.len(),
+            uncached,
+            cached
+        );
+        assert!(cached < uncached);
     }
 }
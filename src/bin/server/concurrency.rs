@@ -0,0 +1,117 @@
+//! Middleware bounding how many requests are handled at once.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use actix_web::middleware::{Finished, Middleware, Started};
+use actix_web::{HttpRequest, HttpResponse, Result};
+use log::warn;
+
+/// Marks a request as counted against the limit, stored in the request extensions.
+struct Admitted;
+
+/// This middleware sheds load by answering requests with a 503 while too many others are in
+/// flight, so a flood of connections can't queue up unbounded work for CPU bound handlers such as
+/// `/locate`.
+///
+/// The count is shared by every worker, so the limit applies to the whole server.
+pub struct ConcurrencyLimit {
+    limit: usize,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl ConcurrencyLimit {
+    /// Create a limit shared through `in_flight`, which should be the same counter for every
+    /// worker.
+    pub fn new(limit: usize, in_flight: Arc<AtomicUsize>) -> Self {
+        Self { limit, in_flight }
+    }
+}
+
+impl<S> Middleware<S> for ConcurrencyLimit {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        if self.in_flight.fetch_add(1, Ordering::SeqCst) >= self.limit {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            warn!(
+                "Shedding request for {}, {} requests already in flight",
+                req.uri(),
+                self.limit
+            );
+            return Ok(Started::Response(
+                HttpResponse::ServiceUnavailable().body("Server busy"),
+            ));
+        }
+        req.extensions_mut().insert(Admitted);
+        Ok(Started::Done)
+    }
+
+    fn finish(&self, req: &HttpRequest<S>, _resp: &HttpResponse) -> Finished {
+        if req.extensions_mut().remove::<Admitted>().is_some() {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+        Finished::Done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    /// Start handling `req`, giving the status it is answered with straight away if it is shed.
+    fn start(limit: &ConcurrencyLimit, req: &HttpRequest<()>) -> Option<StatusCode> {
+        match limit.start(req).unwrap() {
+            Started::Done => None,
+            Started::Response(resp) => Some(resp.status()),
+            _ => panic!("Requests are admitted without waiting"),
+        }
+    }
+
+    fn finish(limit: &ConcurrencyLimit, req: &HttpRequest<()>) {
+        match limit.finish(req, &HttpResponse::Ok().finish()) {
+            Finished::Done => {}
+            _ => panic!("Requests are finished without waiting"),
+        }
+    }
+
+    #[test]
+    fn requests_beyond_the_limit_are_shed_until_a_slot_is_released() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let limit = ConcurrencyLimit::new(2, in_flight.clone());
+        let first = TestRequest::with_uri("/locate").finish();
+        let second = TestRequest::with_uri("/locate").finish();
+        let third = TestRequest::with_uri("/locate").finish();
+        assert_eq!(start(&limit, &first), None);
+        assert_eq!(start(&limit, &second), None);
+        assert_eq!(start(&limit, &third), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(in_flight.load(Ordering::SeqCst), 2);
+
+        // Finishing the shed request doesn't free a slot it never had.
+        finish(&limit, &third);
+        assert_eq!(in_flight.load(Ordering::SeqCst), 2);
+
+        finish(&limit, &first);
+        assert_eq!(in_flight.load(Ordering::SeqCst), 1);
+        let fourth = TestRequest::with_uri("/locate").finish();
+        assert_eq!(start(&limit, &fourth), None);
+        finish(&limit, &second);
+        finish(&limit, &fourth);
+        assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn workers_share_the_limit() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let one = ConcurrencyLimit::new(1, in_flight.clone());
+        let other = ConcurrencyLimit::new(1, in_flight.clone());
+        let first = TestRequest::default().finish();
+        assert_eq!(start(&one, &first), None);
+        let second = TestRequest::default().finish();
+        assert_eq!(
+            start(&other, &second),
+            Some(StatusCode::SERVICE_UNAVAILABLE)
+        );
+        finish(&one, &first);
+        assert_eq!(start(&other, &second), None);
+    }
+}
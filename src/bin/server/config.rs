@@ -0,0 +1,748 @@
+//! Server configuration, read from the command line as `--name value` pairs, or just `--name` for
+//! switches.
+use std::env;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::remote;
+
+/// Serialize a duration as a whole number of seconds, matching the flags which set it.
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+/// Serialize a secret without revealing it, only whether one is set.
+fn serialize_redacted<S: Serializer>(
+    secret: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(if secret.is_some() { "[redacted]" } else { "" })
+}
+
+/// Serialize a URL without its query, which may hold a signature.
+fn serialize_url<S: Serializer>(url: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    url.as_deref()
+        .map(remote::display_url)
+        .serialize(serializer)
+}
+
+/// Which redirects are applied to requests arriving through the SSL terminator.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectPolicy {
+    /// Redirect to HTTPS on the "www" host.
+    Www,
+    /// Redirect to HTTPS, leaving the host alone.
+    Https,
+    /// Serve every request as it arrives.
+    None,
+}
+
+impl RedirectPolicy {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "www" => Ok(RedirectPolicy::Www),
+            "https" => Ok(RedirectPolicy::Https),
+            "none" => Ok(RedirectPolicy::None),
+            _ => Err(format!(
+                "Unknown redirect policy \"{}\", expected \"www\", \"https\" or \"none\"",
+                value
+            )),
+        }
+    }
+}
+
+/// What `/locate` does with coordinates which look like latitude and longitude were swapped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwappedCoordinates {
+    /// Search where the coordinates say, which usually finds nothing.
+    Ignore,
+    /// Answer with a 400 pointing out the likely swap.
+    Reject,
+    /// Swap the coordinates back and search there.
+    Correct,
+}
+
+impl SwappedCoordinates {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "ignore" => Ok(SwappedCoordinates::Ignore),
+            "reject" => Ok(SwappedCoordinates::Reject),
+            "correct" => Ok(SwappedCoordinates::Correct),
+            _ => Err(format!(
+                "Unknown swapped coordinates policy \"{}\", expected \"ignore\", \"reject\" or \
+                 \"correct\"",
+                value
+            )),
+        }
+    }
+}
+
+/// How `/locate` chooses the comment for a bar among its tips.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentRanking {
+    /// Any tip, at random.
+    Random,
+    /// The tip most about picklebacks, choosing at random between equally relevant tips.
+    Relevance,
+}
+
+impl CommentRanking {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "random" => Ok(CommentRanking::Random),
+            "relevance" => Ok(CommentRanking::Relevance),
+            _ => Err(format!(
+                "Unknown comment ranking \"{}\", expected \"random\" or \"relevance\"",
+                value
+            )),
+        }
+    }
+}
+
+/// What `/locate` does when none of a bar's tips are both in a language the client prefers and at
+/// least the minimum comment length.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentFallback {
+    /// Relax the requirements until a tip qualifies, first the length and then the language.
+    Relax,
+    /// Show the generic fallback comment instead of a tip.
+    Generic,
+}
+
+impl CommentFallback {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "relax" => Ok(CommentFallback::Relax),
+            "generic" => Ok(CommentFallback::Generic),
+            _ => Err(format!(
+                "Unknown comment fallback \"{}\", expected \"relax\" or \"generic\"",
+                value
+            )),
+        }
+    }
+}
+
+/// What `/locate` does with coordinates more precise than allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExcessPrecision {
+    /// Answer with a 400.
+    Reject,
+    /// Round the coordinates to the allowed precision and search there.
+    Round,
+}
+
+impl ExcessPrecision {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "reject" => Ok(ExcessPrecision::Reject),
+            "round" => Ok(ExcessPrecision::Round),
+            _ => Err(format!(
+                "Unknown excess precision policy \"{}\", expected \"reject\" or \"round\"",
+                value
+            )),
+        }
+    }
+}
+
+/// The curve giving the weight of a bar from its distance, `5000 / (scale * distance^steepness +
+/// floor)`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ScoringConfig {
+    /// Power of the distance, so how sharply the weight falls off with it.
+    pub steepness: f64,
+    /// Factor on the distance term, so how soon the weight falls off.
+    pub scale: f64,
+    /// Added to the distance term so a bar at no distance has a finite weight, `5000 / floor`.
+    pub floor: f64,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            steepness: 4.0,
+            scale: 40.0,
+            floor: 0.96,
+        }
+    }
+}
+
+impl ScoringConfig {
+    /// A rough estimate for the "utility" score of a bar.
+    ///
+    /// This is a linear scoring of the likelihood the user would want to choose this bar. If three
+    /// bars are available, with scores [1, 2, 3], the first bar would be picked 1 in 6 times.
+    ///
+    /// The numerator is fixed, so that `--max-bar-utility` caps keep their meaning as the curve is
+    /// tuned.
+    pub fn utility_from_distance(&self, distance_miles: f64) -> f64 {
+        5000.0 / ((distance_miles.powf(self.steepness) * self.scale) + self.floor)
+    }
+}
+
+/// How `/locate` weighs bars by distance when choosing one at random.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Utility {
+    /// Weight of a bar by its distance, before the cap and seasonal factor below.
+    pub scoring: ScoringConfig,
+    /// Power of a bar's tip count its weight is multiplied by, 0 to ignore how many tips it has.
+    pub popularity_weight: f64,
+    /// Cap on the weight of a single bar when choosing which to suggest. Without one, a bar very
+    /// close to the user is picked almost every time.
+    pub max_bar_utility: Option<f64>,
+    /// Factor applied to the weight of bars which serve picklebacks only seasonally, above 0 and
+    /// at most 1.
+    pub seasonal_weight: f64,
+}
+
+impl Default for Utility {
+    fn default() -> Self {
+        Self {
+            scoring: ScoringConfig::default(),
+            popularity_weight: 0.0,
+            max_bar_utility: None,
+            seasonal_weight: 1.0,
+        }
+    }
+}
+
+/// Which of the utilities being compared a `/locate` request is served with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    /// The utility given by `--max-bar-utility` and `--seasonal-weight`.
+    Control,
+    /// The utility given by the `--experiment-` flags.
+    Experiment,
+}
+
+/// Configuration for the running server.
+///
+/// This serializes with secrets redacted, so it can be shown to operators.
+#[derive(Debug, Clone, Serialize)]
+pub struct Config {
+    /// Fetch the listing from this URL rather than reading it from disk, such as from object
+    /// storage.
+    #[serde(serialize_with = "serialize_url")]
+    pub bars_url: Option<String>,
+    /// Address the site is served at, such as `https://www.pickletrack.com`, which the links in
+    /// the sitemap start with. There is no sitemap without one.
+    pub site_url: Option<String>,
+    /// Re-check on load that each phrase a tip claims to match really appears in its text.
+    pub verify_tip_matches: bool,
+    /// Time after startup during which the readiness probe fails, even once bars are loaded.
+    #[serde(rename = "startup_grace_secs", serialize_with = "serialize_secs")]
+    pub startup_grace: Duration,
+    /// Requests taking longer than this are answered with a 503 instead of their response, and the
+    /// list endpoints give up once it has passed.
+    #[serde(rename = "request_timeout_secs", serialize_with = "serialize_secs")]
+    pub request_timeout: Duration,
+    /// Multiples of the default search radius tried in turn by `/locate` until a bar is found.
+    pub radius_escalation: Vec<f64>,
+    /// Ceiling on the search radius reached by escalation.
+    pub max_radius_miles: f64,
+    /// Number of worker threads serving requests.
+    ///
+    /// Handlers do their work synchronously on the worker thread, so a busy worker can't pick up
+    /// other requests. `/locate` only measures the bars in the grid cells around a point, but the
+    /// list endpoints still work through many bars. More workers than cores won't make that work
+    /// go faster, so this defaults to the available parallelism of the host.
+    pub workers: usize,
+    /// Requests beyond this many in flight at once are answered with a 503.
+    pub max_in_flight: usize,
+    /// Serve the static pages from copies built into the binary, rather than from disk.
+    pub embedded_assets: bool,
+    /// Tips with fewer words than this are only used as a bar's comment when it has no longer ones.
+    pub min_comment_words: usize,
+    pub comment_ranking: CommentRanking,
+    pub comment_fallback: CommentFallback,
+    /// Comment shown for a bar with `CommentFallback::Generic` when none of its tips qualify.
+    pub fallback_comment: String,
+    /// Attribution shown with the comment `/locate` returns, such as "via Foursquare".
+    pub attribution: Option<String>,
+    #[serde(flatten)]
+    pub utility: Utility,
+    /// Utility compared against `utility` on half of `/locate` requests, if an experiment is
+    /// running.
+    pub experiment_utility: Option<Utility>,
+    /// Least distance `/locate` keeps between a suggestion and its alternates, and between the
+    /// alternates, where there are enough bars that far apart.
+    pub min_alternate_separation_miles: Option<f64>,
+    /// Most distance `/locate` moves the query point before weighting bars by distance, so that
+    /// repeated queries from one spot don't all favour the same nearest bars.
+    pub query_jitter_meters: Option<f64>,
+    pub redirect: RedirectPolicy,
+    /// Origins whose pages may call `/locate` and `/locate/list`, or `*` for any. Empty allows
+    /// only same-origin pages.
+    pub cors_origins: Vec<String>,
+    /// Most requests to `/locate` and `/locate/list` each client may make a minute, if limited.
+    pub rate_limit_per_minute: Option<u32>,
+    pub swapped_coordinates: SwappedCoordinates,
+    /// Most decimal places `/locate` accepts in a coordinate. Real location fixes don't need many,
+    /// so long ones suggest a scraper walking a synthetic grid.
+    pub max_coordinate_decimals: Option<usize>,
+    pub excess_precision: ExcessPrecision,
+    /// Wrap `/locate` results with the query they answer and how long they took, unless the
+    /// request says otherwise with its `debug` parameter.
+    pub response_envelope: bool,
+    /// Run the scraper on a schedule and serve its listing from memory, rather than reloading the
+    /// listing from disk.
+    pub embedded_scrape: bool,
+    /// Time between embedded scrapes.
+    #[serde(
+        rename = "embedded_scrape_interval_secs",
+        serialize_with = "serialize_secs"
+    )]
+    pub embedded_scrape_interval: Duration,
+    /// Secret required to use the `/admin` endpoints, which are disabled when there is none.
+    #[serde(serialize_with = "serialize_redacted")]
+    pub admin_secret: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bars_url: None,
+            site_url: None,
+            verify_tip_matches: true,
+            startup_grace: Duration::from_secs(0),
+            request_timeout: Duration::from_secs(30),
+            radius_escalation: vec![1.0],
+            max_radius_miles: 9.0,
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            max_in_flight: 1024,
+            embedded_assets: false,
+            min_comment_words: 3,
+            comment_ranking: CommentRanking::Random,
+            comment_fallback: CommentFallback::Relax,
+            fallback_comment: "Known for its picklebacks.".to_string(),
+            attribution: None,
+            utility: Utility::default(),
+            experiment_utility: None,
+            min_alternate_separation_miles: None,
+            query_jitter_meters: None,
+            redirect: RedirectPolicy::Www,
+            cors_origins: Vec::new(),
+            rate_limit_per_minute: None,
+            swapped_coordinates: SwappedCoordinates::Ignore,
+            max_coordinate_decimals: None,
+            excess_precision: ExcessPrecision::Reject,
+            response_envelope: false,
+            embedded_scrape: false,
+            embedded_scrape_interval: Duration::from_secs(60 * 60 * 24),
+            admin_secret: None,
+        }
+    }
+}
+
+/// Take the value following a flag, failing if the flag was the last argument.
+fn flag_value(flag: &str, args: &mut dyn Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+/// Take the value following a flag and parse it as `true` or `false`.
+fn bool_flag_value(flag: &str, args: &mut dyn Iterator<Item = String>) -> Result<bool, String> {
+    match flag_value(flag, args)?.as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        value => Err(format!(
+            "Invalid value \"{}\" for {}, expected \"true\" or \"false\"",
+            value, flag
+        )),
+    }
+}
+
+/// Take the value following a flag and parse it as a number.
+fn numeric_flag_value<T: FromStr>(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<T, String> {
+    let value = flag_value(flag, args)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value \"{}\" for {}", value, flag))
+}
+
+/// Take the value following a flag and parse it as a cap on the utility of a bar.
+fn max_bar_utility_flag_value(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<f64, String> {
+    let max_bar_utility: f64 = numeric_flag_value(flag, args)?;
+    if max_bar_utility.is_nan() || max_bar_utility <= 0.0 {
+        return Err(format!("{} must be positive", flag));
+    }
+    Ok(max_bar_utility)
+}
+
+/// Take the value following a flag and parse it as a weight for seasonal bars.
+fn seasonal_weight_flag_value(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<f64, String> {
+    let seasonal_weight: f64 = numeric_flag_value(flag, args)?;
+    // A weight of 0 would leave nothing to suggest where only seasonal bars are near.
+    if seasonal_weight.is_nan() || seasonal_weight <= 0.0 || seasonal_weight > 1.0 {
+        return Err(format!("{} must be above 0 and at most 1", flag));
+    }
+    Ok(seasonal_weight)
+}
+
+/// Take the value following a flag and parse it as a number which is at least 0, or above it if
+/// `allow_zero` is false.
+fn scoring_flag_value(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+    allow_zero: bool,
+) -> Result<f64, String> {
+    let value: f64 = numeric_flag_value(flag, args)?;
+    if !value.is_finite() || value < 0.0 || (value == 0.0 && !allow_zero) {
+        return Err(if allow_zero {
+            format!("{} must not be negative", flag)
+        } else {
+            format!("{} must be positive", flag)
+        });
+    }
+    Ok(value)
+}
+
+/// Take the value following a flag and parse it as a comma separated list of numbers.
+fn numeric_list_flag_value<T: FromStr>(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<Vec<T>, String> {
+    let value = flag_value(flag, args)?;
+    value
+        .split(',')
+        .map(|item| {
+            item.trim()
+                .parse()
+                .map_err(|_| format!("Invalid value \"{}\" for {}", value, flag))
+        })
+        .collect()
+}
+
+/// Take the value following a flag and parse it as a comma separated list of origins, such as
+/// `https://example.com`, or `*`.
+fn cors_origins_flag_value(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<Vec<String>, String> {
+    let value = flag_value(flag, args)?;
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(|origin| {
+            // Browsers send origins without a path, so one with a trailing slash would never match.
+            if origin == "*" || (origin.contains("://") && !origin.ends_with('/')) {
+                Ok(origin.to_string())
+            } else {
+                Err(format!("Invalid origin \"{}\" for {}", origin, flag))
+            }
+        })
+        .collect()
+}
+
+impl Config {
+    /// Parse the configuration from command line arguments (excluding the program name).
+    ///
+    /// The admin secret is taken from the `ADMIN_SECRET` environment variable unless it is given
+    /// on the command line, where it would be visible to other users of the host.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut config = Self {
+            admin_secret: env::var("ADMIN_SECRET")
+                .ok()
+                .filter(|secret| !secret.is_empty()),
+            ..Self::default()
+        };
+        // The experiment differs from the control only where asked, so is built once the
+        // control is known.
+        let mut experiment_max_bar_utility = None;
+        let mut experiment_seasonal_weight = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bars-url" => {
+                    let url = flag_value(&arg, &mut args)?;
+                    if !url.starts_with("https://") && !url.starts_with("http://") {
+                        return Err(format!("{} must be an http:// or https:// URL", arg));
+                    }
+                    config.bars_url = Some(url);
+                }
+                "--site-url" => {
+                    let url = flag_value(&arg, &mut args)?;
+                    if !url.starts_with("https://") && !url.starts_with("http://") {
+                        return Err(format!("{} must be an http:// or https:// URL", arg));
+                    }
+                    config.site_url = Some(url.trim_end_matches('/').to_string());
+                }
+                "--verify-tip-matches" => {
+                    config.verify_tip_matches = bool_flag_value(&arg, &mut args)?;
+                }
+                "--startup-grace-secs" => {
+                    config.startup_grace =
+                        Duration::from_secs(numeric_flag_value(&arg, &mut args)?);
+                }
+                "--request-timeout-secs" => {
+                    let secs = numeric_flag_value(&arg, &mut args)?;
+                    // Every request would time out before its handler could start.
+                    if secs == 0 {
+                        return Err(format!("{} must be at least 1", arg));
+                    }
+                    config.request_timeout = Duration::from_secs(secs);
+                }
+                "--radius-escalation" => {
+                    config.radius_escalation = numeric_list_flag_value(&arg, &mut args)?;
+                    if config.radius_escalation.is_empty() {
+                        return Err(format!("{} needs at least one step", arg));
+                    }
+                    if config
+                        .radius_escalation
+                        .iter()
+                        .any(|step: &f64| !step.is_finite() || *step <= 0.0)
+                    {
+                        return Err(format!("{} steps must be positive", arg));
+                    }
+                }
+                "--max-radius-miles" => {
+                    config.max_radius_miles = scoring_flag_value(&arg, &mut args, false)?;
+                }
+                "--workers" => {
+                    config.workers = numeric_flag_value(&arg, &mut args)?;
+                    if config.workers == 0 {
+                        return Err(format!("{} must be at least 1", arg));
+                    }
+                }
+                "--max-in-flight" => {
+                    config.max_in_flight = numeric_flag_value(&arg, &mut args)?;
+                    if config.max_in_flight == 0 {
+                        return Err(format!("{} must be at least 1", arg));
+                    }
+                }
+                "--embedded-assets" => config.embedded_assets = true,
+                "--min-comment-words" => {
+                    config.min_comment_words = numeric_flag_value(&arg, &mut args)?;
+                }
+                "--comment-ranking" => {
+                    config.comment_ranking = CommentRanking::parse(&flag_value(&arg, &mut args)?)?
+                }
+                "--comment-fallback" => {
+                    config.comment_fallback = CommentFallback::parse(&flag_value(&arg, &mut args)?)?
+                }
+                "--fallback-comment" => config.fallback_comment = flag_value(&arg, &mut args)?,
+                "--attribution" => config.attribution = Some(flag_value(&arg, &mut args)?),
+                "--distance-steepness" => {
+                    config.utility.scoring.steepness = scoring_flag_value(&arg, &mut args, true)?;
+                }
+                "--distance-scale" => {
+                    config.utility.scoring.scale = scoring_flag_value(&arg, &mut args, true)?;
+                }
+                // A floor of 0 would give a bar at the user's location infinite weight.
+                "--distance-floor" => {
+                    config.utility.scoring.floor = scoring_flag_value(&arg, &mut args, false)?;
+                }
+                "--popularity-weight" => {
+                    config.utility.popularity_weight = scoring_flag_value(&arg, &mut args, true)?;
+                }
+                "--max-bar-utility" => {
+                    config.utility.max_bar_utility =
+                        Some(max_bar_utility_flag_value(&arg, &mut args)?);
+                }
+                "--seasonal-weight" => {
+                    config.utility.seasonal_weight = seasonal_weight_flag_value(&arg, &mut args)?;
+                }
+                "--experiment-max-bar-utility" => {
+                    experiment_max_bar_utility = Some(max_bar_utility_flag_value(&arg, &mut args)?);
+                }
+                "--experiment-seasonal-weight" => {
+                    experiment_seasonal_weight = Some(seasonal_weight_flag_value(&arg, &mut args)?);
+                }
+                "--min-alternate-separation-miles" => {
+                    let separation: f64 = numeric_flag_value(&arg, &mut args)?;
+                    if separation.is_nan() || separation <= 0.0 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                    config.min_alternate_separation_miles = Some(separation);
+                }
+                "--query-jitter-meters" => {
+                    let jitter: f64 = numeric_flag_value(&arg, &mut args)?;
+                    if !jitter.is_finite() || jitter <= 0.0 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                    config.query_jitter_meters = Some(jitter);
+                }
+                "--redirect" => {
+                    config.redirect = RedirectPolicy::parse(&flag_value(&arg, &mut args)?)?
+                }
+                "--cors-origins" => {
+                    config.cors_origins = cors_origins_flag_value(&arg, &mut args)?;
+                }
+                "--rate-limit-per-minute" => {
+                    let per_minute = numeric_flag_value(&arg, &mut args)?;
+                    if per_minute == 0 {
+                        return Err(format!("{} must be positive", arg));
+                    }
+                    config.rate_limit_per_minute = Some(per_minute);
+                }
+                "--swapped-coordinates" => {
+                    config.swapped_coordinates =
+                        SwappedCoordinates::parse(&flag_value(&arg, &mut args)?)?
+                }
+                "--max-coordinate-decimals" => {
+                    config.max_coordinate_decimals = Some(numeric_flag_value(&arg, &mut args)?);
+                }
+                "--excess-precision" => {
+                    config.excess_precision = ExcessPrecision::parse(&flag_value(&arg, &mut args)?)?
+                }
+                "--response-envelope" => config.response_envelope = true,
+                "--embedded-scrape" => config.embedded_scrape = true,
+                "--embedded-scrape-interval-hours" => {
+                    let hours: u64 = numeric_flag_value(&arg, &mut args)?;
+                    if hours == 0 {
+                        return Err(format!("{} must be at least 1", arg));
+                    }
+                    config.embedded_scrape_interval = Duration::from_secs(60 * 60 * hours);
+                }
+                "--admin-secret" => config.admin_secret = Some(flag_value(&arg, &mut args)?),
+                _ => return Err(format!("Unknown argument \"{}\"", arg)),
+            }
+        }
+        if experiment_max_bar_utility.is_some() || experiment_seasonal_weight.is_some() {
+            config.experiment_utility = Some(Utility {
+                scoring: config.utility.scoring,
+                popularity_weight: config.utility.popularity_weight,
+                max_bar_utility: experiment_max_bar_utility.or(config.utility.max_bar_utility),
+                seasonal_weight: experiment_seasonal_weight
+                    .unwrap_or(config.utility.seasonal_weight),
+            });
+        }
+        // The embedded scrape serves the listing it writes to disk, so would never use the URL.
+        if config.embedded_scrape && config.bars_url.is_some() {
+            return Err("--bars-url can't be used with --embedded-scrape".to_string());
+        }
+        Ok(config)
+    }
+
+    /// The utility `/locate` requests in `variant` are served with.
+    pub fn utility(&self, variant: Variant) -> Utility {
+        match variant {
+            Variant::Control => self.utility,
+            Variant::Experiment => self.experiment_utility.unwrap_or(self.utility),
+        }
+    }
+
+    /// The search radii `/locate` tries in turn, starting from `base_radius_miles`.
+    pub fn search_radii(&self, base_radius_miles: f64) -> Vec<f64> {
+        self.radius_escalation
+            .iter()
+            .map(|step| (step * base_radius_miles).min(self.max_radius_miles))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config, String> {
+        Config::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn max_radius_must_be_positive() {
+        assert_eq!(
+            parse(&["--max-radius-miles", "4.5"])
+                .unwrap()
+                .max_radius_miles,
+            4.5
+        );
+        for invalid in &["-1", "0", "NaN", "inf"] {
+            assert!(
+                parse(&["--max-radius-miles", invalid]).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn radius_escalation_steps_must_be_positive() {
+        assert_eq!(
+            parse(&["--radius-escalation", "1,2,3"])
+                .unwrap()
+                .radius_escalation,
+            vec![1.0, 2.0, 3.0]
+        );
+        for invalid in &["1,-2", "0", "1,NaN", "", "1,,2"] {
+            assert!(
+                parse(&["--radius-escalation", invalid]).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn search_radii_are_capped_at_the_maximum_radius() {
+        let config = parse(&["--radius-escalation", "1,2,3", "--max-radius-miles", "5"]).unwrap();
+        assert_eq!(config.search_radii(1.5), [1.5, 3.0, 4.5]);
+        assert_eq!(config.search_radii(2.0), [2.0, 4.0, 5.0]);
+        assert_eq!(config.search_radii(10.0), [5.0, 5.0, 5.0]);
+        // Without escalation only the requested radius is searched.
+        assert_eq!(Config::default().search_radii(3.0), [3.0]);
+    }
+
+    #[test]
+    fn request_timeout_must_not_be_zero() {
+        assert_eq!(
+            parse(&["--request-timeout-secs", "5"])
+                .unwrap()
+                .request_timeout,
+            Duration::from_secs(5)
+        );
+        assert!(parse(&["--request-timeout-secs", "0"]).is_err());
+        assert!(parse(&["--request-timeout-secs", "-1"]).is_err());
+    }
+
+    #[test]
+    fn site_url_must_be_http_and_loses_its_trailing_slash() {
+        assert_eq!(
+            parse(&["--site-url", "https://www.pickletrack.com/"])
+                .unwrap()
+                .site_url,
+            Some("https://www.pickletrack.com".to_string())
+        );
+        assert!(parse(&["--site-url", "www.pickletrack.com"]).is_err());
+    }
+
+    #[test]
+    fn secrets_are_redacted_when_shown() {
+        let config = Config {
+            admin_secret: Some("hunter2".to_string()),
+            bars_url: Some(
+                "https://storage.example.com/bars.json?X-Amz-Signature=0badc0ffee".to_string(),
+            ),
+            ..Config::default()
+        };
+        let shown = serde_json::to_value(&config).unwrap();
+        assert_eq!(shown["admin_secret"], "[redacted]");
+        assert_eq!(shown["bars_url"], "https://storage.example.com/bars.json");
+        let shown = shown.to_string();
+        assert!(!shown.contains("hunter2"));
+        assert!(!shown.contains("0badc0ffee"));
+    }
+
+    #[test]
+    fn missing_secret_is_shown_as_unset() {
+        let shown = serde_json::to_value(Config::default()).unwrap();
+        assert_eq!(shown["admin_secret"], "");
+    }
+}
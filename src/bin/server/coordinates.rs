@@ -0,0 +1,198 @@
+//! Checks on the coordinates `/locate` is given: rejecting ones which aren't on the globe, spotting
+//! latitude and longitude the wrong way round, a common client bug which otherwise just finds no
+//! bars, and precision no real location fix has, which suggests a scraper walking a grid. Also
+//! which points fall within a map viewport.
+use crate::barlisting::NeighborhoodCoverage;
+
+/// Approximate length of a degree of latitude.
+const MILES_PER_DEGREE: f64 = 69.0;
+
+/// Whether a point is within `margin_miles` of the area a neighborhood's bars span.
+fn is_near(area: &NeighborhoodCoverage, lat: f64, lng: f64, margin_miles: f64) -> bool {
+    let lat_margin = margin_miles / MILES_PER_DEGREE;
+    let center_lat = (area.min_lat + area.max_lat) / 2.0;
+    let lng_margin = lat_margin / center_lat.to_radians().cos().max(0.01);
+    lat >= area.min_lat - lat_margin
+        && lat <= area.max_lat + lat_margin
+        && lng >= area.min_lng - lng_margin
+        && lng <= area.max_lng + lng_margin
+}
+
+/// Whether a point looks like it has its coordinates swapped: it isn't within `margin_miles` of
+/// anywhere the listing has bars, but would be with latitude and longitude exchanged.
+pub fn looks_swapped(
+    coverage: &[NeighborhoodCoverage],
+    lat: f64,
+    lng: f64,
+    margin_miles: f64,
+) -> bool {
+    let is_covered = |lat, lng| {
+        coverage
+            .iter()
+            .any(|area| is_near(area, lat, lng, margin_miles))
+    };
+    !is_covered(lat, lng) && is_covered(lng, lat)
+}
+
+/// Check that a latitude and longitude are finite and in range, describing what is wrong if not.
+pub fn validate_latlng(lat: f64, lng: f64) -> Result<(), &'static str> {
+    if !lat.is_finite() || !lng.is_finite() {
+        Err("Coordinates must be finite numbers")
+    } else if !(-90.0..=90.0).contains(&lat) {
+        Err("Latitude must be between -90 and 90")
+    } else if !(-180.0..=180.0).contains(&lng) {
+        Err("Longitude must be between -180 and 180")
+    } else {
+        Ok(())
+    }
+}
+
+/// Whether a point is within the box with the given south west and north east corners, edges
+/// included. A box whose west edge is east of its east edge spans the antimeridian, so takes in the
+/// longitudes either side of it.
+pub fn point_in_box(lat: f64, lng: f64, sw: (f64, f64), ne: (f64, f64)) -> bool {
+    let (sw_lat, sw_lng) = sw;
+    let (ne_lat, ne_lng) = ne;
+    let lng_in_box = if sw_lng <= ne_lng {
+        lng >= sw_lng && lng <= ne_lng
+    } else {
+        lng >= sw_lng || lng <= ne_lng
+    };
+    lat >= sw_lat && lat <= ne_lat && lng_in_box
+}
+
+/// Number of decimal places in the shortest decimal form of `value`.
+pub fn decimal_places(value: f64) -> usize {
+    let formatted = value.to_string();
+    formatted
+        .find('.')
+        .map_or(0, |point| formatted.len() - point - 1)
+}
+
+/// Round `value` to `places` decimal places.
+pub fn round_to_places(value: f64, places: usize) -> f64 {
+    let scale = 10f64.powi(places as i32);
+    (value * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Coverage of a listing with bars only in lower Manhattan.
+    fn manhattan() -> Vec<NeighborhoodCoverage> {
+        vec![NeighborhoodCoverage {
+            neighborhood: "Manhattan".to_string(),
+            bars: 10,
+            min_lat: 40.70,
+            min_lng: -74.02,
+            max_lat: 40.75,
+            max_lng: -73.97,
+        }]
+    }
+
+    #[test]
+    fn swapped_coordinates_are_spotted() {
+        assert!(looks_swapped(&manhattan(), -74.0, 40.72, 3.0));
+    }
+
+    #[test]
+    fn covered_coordinates_are_not_swapped() {
+        assert!(!looks_swapped(&manhattan(), 40.72, -74.0, 3.0));
+    }
+
+    #[test]
+    fn coordinates_covered_neither_way_are_not_swapped() {
+        // Somewhere in London, far from any bar but no closer with the two exchanged.
+        assert!(!looks_swapped(&manhattan(), 51.5, -0.12, 3.0));
+    }
+
+    #[test]
+    fn swap_detection_allows_for_the_search_radius() {
+        // Swapped coordinates a few miles north of the covered area are within a wide radius of it.
+        assert!(looks_swapped(&manhattan(), -74.0, 40.80, 5.0));
+        assert!(!looks_swapped(&manhattan(), -74.0, 40.80, 1.0));
+    }
+
+    #[test]
+    fn nothing_looks_swapped_without_coverage() {
+        assert!(!looks_swapped(&[], -74.0, 40.72, 3.0));
+    }
+
+    #[test]
+    fn coordinates_at_the_limits_are_valid() {
+        assert_eq!(validate_latlng(90.0, 180.0), Ok(()));
+        assert_eq!(validate_latlng(-90.0, -180.0), Ok(()));
+        assert_eq!(validate_latlng(40.72, -74.0), Ok(()));
+    }
+
+    #[test]
+    fn coordinates_beyond_the_limits_are_invalid() {
+        assert!(validate_latlng(90.01, 0.0).is_err());
+        assert!(validate_latlng(-90.01, 0.0).is_err());
+        assert!(validate_latlng(0.0, 180.01).is_err());
+        assert!(validate_latlng(0.0, -180.01).is_err());
+    }
+
+    #[test]
+    fn coordinates_which_are_not_finite_are_invalid() {
+        for &bad in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(validate_latlng(bad, 0.0).is_err());
+            assert!(validate_latlng(0.0, bad).is_err());
+        }
+    }
+
+    #[test]
+    fn points_inside_a_box_are_in_it() {
+        let (sw, ne) = ((40.70, -74.02), (40.75, -73.97));
+        assert!(point_in_box(40.72, -74.0, sw, ne));
+        // Edges are included.
+        assert!(point_in_box(40.70, -74.02, sw, ne));
+        assert!(point_in_box(40.75, -73.97, sw, ne));
+    }
+
+    #[test]
+    fn points_outside_a_box_are_not_in_it() {
+        let (sw, ne) = ((40.70, -74.02), (40.75, -73.97));
+        assert!(!point_in_box(40.76, -74.0, sw, ne));
+        assert!(!point_in_box(40.69, -74.0, sw, ne));
+        assert!(!point_in_box(40.72, -74.03, sw, ne));
+        assert!(!point_in_box(40.72, -73.96, sw, ne));
+    }
+
+    #[test]
+    fn decimal_places_are_those_of_the_shortest_form() {
+        assert_eq!(decimal_places(40.0), 0);
+        assert_eq!(decimal_places(40.7), 1);
+        assert_eq!(decimal_places(-73.985_123), 6);
+        assert_eq!(decimal_places(0.000_000_1), 7);
+        // Arithmetic leaves the tell-tale precision of floating point.
+        assert_eq!(decimal_places(0.1 + 0.2), 17);
+    }
+
+    #[test]
+    fn rounded_coordinates_are_within_the_precision() {
+        assert_eq!(round_to_places(40.712_776, 4), 40.7128);
+        assert_eq!(round_to_places(-73.935_242, 4), -73.9352);
+        assert_eq!(round_to_places(40.7, 4), 40.7);
+        for step in 0..1000 {
+            let lat = 40.7 + step as f64 * 0.000_012_345_678_9;
+            assert!(decimal_places(round_to_places(lat, 5)) <= 5, "{}", lat);
+        }
+    }
+
+    #[test]
+    fn box_across_the_antimeridian_takes_in_both_sides() {
+        // Around Fiji, from 177 east to 178 west.
+        let (sw, ne) = ((-19.0, 177.0), (-16.0, -178.0));
+        assert!(point_in_box(-17.5, 179.5, sw, ne));
+        assert!(point_in_box(-17.5, -179.5, sw, ne));
+        assert!(point_in_box(-17.5, 180.0, sw, ne));
+        assert!(point_in_box(-17.5, -180.0, sw, ne));
+        // The longitudes between its edges the other way round the globe are outside it.
+        assert!(!point_in_box(-17.5, 0.0, sw, ne));
+        assert!(!point_in_box(-17.5, 176.0, sw, ne));
+        assert!(!point_in_box(-17.5, -177.0, sw, ne));
+        assert!(!point_in_box(-20.0, 179.5, sw, ne));
+    }
+}
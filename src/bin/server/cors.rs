@@ -0,0 +1,209 @@
+//! Middleware letting pages served from other origins call the locate endpoints.
+use actix_web::http::header::{
+    HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+    ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS,
+    ACCESS_CONTROL_REQUEST_METHOD, ORIGIN, VARY,
+};
+use actix_web::http::Method;
+use actix_web::middleware::{Middleware, Response, Started};
+use actix_web::{HttpRequest, HttpResponse, Result};
+
+/// Paths other origins may request.
+const CORS_PATHS: &[&str] = &["/locate", "/locate/list"];
+
+/// Seconds browsers may cache the answer to a preflight request.
+const PREFLIGHT_MAX_AGE_SECS: &str = "86400";
+
+/// The `Access-Control-Allow-Origin` value for a request from `origin`, if `allowed_origins` lets
+/// it through. A `*` entry allows any origin.
+fn allowed_origin(allowed_origins: &[String], origin: &HeaderValue) -> Option<HeaderValue> {
+    if allowed_origins.iter().any(|allowed| allowed == "*") {
+        return Some(HeaderValue::from_static("*"));
+    }
+    let origin_str = origin.to_str().ok()?;
+    if allowed_origins.iter().any(|allowed| allowed == origin_str) {
+        Some(origin.clone())
+    } else {
+        None
+    }
+}
+
+/// This middleware adds CORS headers to responses from the locate endpoints for requests from
+/// the allowed origins, and answers their preflight requests.
+///
+/// With no allowed origins nothing is added, so browsers only let same-origin pages read them.
+pub struct Cors {
+    allowed_origins: Vec<String>,
+}
+
+impl Cors {
+    pub fn new(allowed_origins: Vec<String>) -> Self {
+        Self { allowed_origins }
+    }
+
+    /// The `Access-Control-Allow-Origin` value for `req`, or None if it gets no CORS headers.
+    fn allow_origin<S>(&self, req: &HttpRequest<S>) -> Option<HeaderValue> {
+        if !CORS_PATHS.contains(&req.path()) {
+            return None;
+        }
+        allowed_origin(&self.allowed_origins, req.headers().get(ORIGIN)?)
+    }
+}
+
+impl<S> Middleware<S> for Cors {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        if req.method() != Method::OPTIONS
+            || !req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+        {
+            return Ok(Started::Done);
+        }
+        // Preflights from other origins fall through to the router, which doesn't allow OPTIONS.
+        let origin = match self.allow_origin(req) {
+            Some(origin) => origin,
+            None => return Ok(Started::Done),
+        };
+        let mut resp = HttpResponse::NoContent();
+        resp.header(ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+            .header(ACCESS_CONTROL_ALLOW_METHODS, "GET")
+            .header(ACCESS_CONTROL_MAX_AGE, PREFLIGHT_MAX_AGE_SECS)
+            .header(VARY, "Origin");
+        if let Some(headers) = req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS) {
+            resp.header(ACCESS_CONTROL_ALLOW_HEADERS, headers.clone());
+        }
+        Ok(Started::Response(resp.finish()))
+    }
+
+    fn response(&self, req: &HttpRequest<S>, mut resp: HttpResponse) -> Result<Response> {
+        if let Some(origin) = self.allow_origin(req) {
+            let headers = resp.headers_mut();
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+            headers.append(VARY, HeaderValue::from_static("Origin"));
+        }
+        Ok(Response::Done(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderName;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    const PAGE_ORIGIN: &str = "https://pickletrack.example";
+
+    fn allowing(allowed_origins: &[&str]) -> Cors {
+        Cors::new(
+            allowed_origins
+                .iter()
+                .map(|origin| origin.to_string())
+                .collect(),
+        )
+    }
+
+    /// The `Access-Control-Allow-Origin` header added to a response to `req`.
+    fn response_origin(cors: &Cors, req: TestRequest<()>) -> Option<String> {
+        let resp = match cors.response(&req.finish(), HttpResponse::Ok().finish()) {
+            Ok(Response::Done(resp)) => resp,
+            _ => panic!("CORS headers are added without waiting"),
+        };
+        resp.headers()
+            .get(ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|origin| origin.to_str().unwrap().to_string())
+    }
+
+    /// The response `cors` answers the preflight `req` with itself, or None if it is passed on.
+    fn preflight(cors: &Cors, req: TestRequest<()>) -> Option<HttpResponse> {
+        match cors.start(&req.finish()).unwrap() {
+            Started::Done => None,
+            Started::Response(resp) => Some(resp),
+            _ => panic!("Preflights are answered without waiting"),
+        }
+    }
+
+    fn locate_from(origin: &str) -> TestRequest<()> {
+        TestRequest::with_header(ORIGIN, origin).uri("/locate?lat=40.7&lng=-74.0")
+    }
+
+    #[test]
+    fn allowed_origins_may_read_the_locate_endpoints() {
+        let cors = allowing(&[PAGE_ORIGIN]);
+        assert_eq!(
+            response_origin(&cors, locate_from(PAGE_ORIGIN)),
+            Some(PAGE_ORIGIN.to_string())
+        );
+        assert_eq!(
+            response_origin(
+                &cors,
+                TestRequest::with_header(ORIGIN, PAGE_ORIGIN).uri("/locate/list?lat=40.7&lng=-74")
+            ),
+            Some(PAGE_ORIGIN.to_string())
+        );
+    }
+
+    #[test]
+    fn other_origins_and_paths_get_no_headers() {
+        let cors = allowing(&[PAGE_ORIGIN]);
+        assert_eq!(
+            response_origin(&cors, locate_from("https://evil.example")),
+            None
+        );
+        assert_eq!(
+            response_origin(
+                &cors,
+                TestRequest::with_header(ORIGIN, PAGE_ORIGIN).uri("/bars")
+            ),
+            None
+        );
+        assert_eq!(
+            response_origin(&cors, TestRequest::with_uri("/locate")),
+            None
+        );
+        assert_eq!(
+            response_origin(&allowing(&[]), locate_from(PAGE_ORIGIN)),
+            None
+        );
+    }
+
+    #[test]
+    fn wildcard_allows_any_origin() {
+        let cors = allowing(&["*"]);
+        assert_eq!(
+            response_origin(&cors, locate_from("https://anywhere.example")),
+            Some("*".to_string())
+        );
+    }
+
+    #[test]
+    fn preflights_from_allowed_origins_are_answered() {
+        let cors = allowing(&[PAGE_ORIGIN]);
+        let resp = preflight(
+            &cors,
+            locate_from(PAGE_ORIGIN)
+                .method(Method::OPTIONS)
+                .header(ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .header(ACCESS_CONTROL_REQUEST_HEADERS, "x-requested-with"),
+        )
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let header = |name: HeaderName| resp.headers().get(name).unwrap().to_str().unwrap();
+        assert_eq!(header(ACCESS_CONTROL_ALLOW_ORIGIN), PAGE_ORIGIN);
+        assert_eq!(header(ACCESS_CONTROL_ALLOW_METHODS), "GET");
+        assert_eq!(header(ACCESS_CONTROL_ALLOW_HEADERS), "x-requested-with");
+        assert_eq!(header(ACCESS_CONTROL_MAX_AGE), PREFLIGHT_MAX_AGE_SECS);
+    }
+
+    #[test]
+    fn other_requests_are_passed_on() {
+        let cors = allowing(&[PAGE_ORIGIN]);
+        // From an origin which isn't allowed.
+        let other_origin = locate_from("https://evil.example")
+            .method(Method::OPTIONS)
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "GET");
+        assert!(preflight(&cors, other_origin).is_none());
+        // Not a preflight, without the method it is for.
+        let plain_options = locate_from(PAGE_ORIGIN).method(Method::OPTIONS);
+        assert!(preflight(&cors, plain_options).is_none());
+        assert!(preflight(&cors, locate_from(PAGE_ORIGIN)).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+//! Deadlines by which a request must be answered, so that handlers working through many bars give
+//! up part way rather than tying up a worker.
+//!
+//! Handlers are synchronous, so nothing can interrupt them from outside. Instead the ones whose work
+//! grows with the request, such as the list endpoints, check their deadline as they go.
+use std::time::{Duration, Instant};
+
+/// Number of bars a handler works through between checks of its deadline, so checking doesn't
+/// cost more than the work it bounds.
+pub const CHECK_INTERVAL: usize = 64;
+
+/// When a request must be answered by, if ever.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deadline(Option<Instant>);
+
+/// A request ran past its deadline, so its handler gave up.
+#[derive(Debug, PartialEq)]
+pub struct Expired;
+
+impl Deadline {
+    /// A deadline which never passes.
+    pub fn none() -> Self {
+        Deadline(None)
+    }
+
+    /// A deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(Some(Instant::now() + timeout))
+    }
+
+    /// Fail if the deadline has passed.
+    pub fn check(&self) -> Result<(), Expired> {
+        match self.0 {
+            Some(deadline) if Instant::now() >= deadline => Err(Expired),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if the deadline has passed, only checking every `CHECK_INTERVAL`th time, such as with
+    /// the index of each item a handler works through.
+    pub fn check_every(&self, index: usize) -> Result<(), Expired> {
+        if index.is_multiple_of(CHECK_INTERVAL) {
+            self.check()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    /// A handler which takes a millisecond over each of `items`, giving up at `deadline`. Returns
+    /// how many items it got through.
+    fn slow_handler(items: usize, deadline: Deadline) -> Result<usize, Expired> {
+        for _ in 0..items {
+            deadline.check()?;
+            thread::sleep(Duration::from_millis(1));
+        }
+        Ok(items)
+    }
+
+    #[test]
+    fn slow_handler_gives_up_at_its_deadline() {
+        let started = Instant::now();
+        let result = slow_handler(10_000, Deadline::after(Duration::from_millis(20)));
+        assert_eq!(result, Err(Expired));
+        // Far sooner than the ten seconds it would take to finish.
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn handler_within_its_deadline_finishes() {
+        assert_eq!(
+            slow_handler(5, Deadline::after(Duration::from_secs(60))),
+            Ok(5)
+        );
+    }
+
+    #[test]
+    fn no_deadline_never_passes() {
+        assert_eq!(slow_handler(5, Deadline::none()), Ok(5));
+    }
+
+    #[test]
+    fn passed_deadline_is_only_checked_every_interval() {
+        let deadline = Deadline::after(Duration::from_secs(0));
+        assert_eq!(deadline.check_every(0), Err(Expired));
+        assert_eq!(deadline.check_every(1), Ok(()));
+        assert_eq!(deadline.check_every(CHECK_INTERVAL), Err(Expired));
+    }
+}
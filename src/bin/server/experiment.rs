@@ -0,0 +1,107 @@
+//! Comparing two utilities on live traffic, to tune how `/locate` weighs bars empirically.
+//!
+//! Each request is assigned a variant, served with that variant's utility, and tagged with it so
+//! that clients can report engagement against it. The server counts the requests served in each.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rand::random;
+use serde::Serialize;
+
+use crate::config::Variant;
+
+/// FNV-1a hash of `text`. Unlike the standard library's hasher, this is guaranteed not to change
+/// between releases, so clients keep their variant across server upgrades.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The variant a request is served with. A client which gives an id always gets the same variant,
+/// others get one at random.
+pub fn assign(client: Option<&str>) -> Variant {
+    let control = match client {
+        Some(client) => fnv1a(client).is_multiple_of(2),
+        None => random(),
+    };
+    if control {
+        Variant::Control
+    } else {
+        Variant::Experiment
+    }
+}
+
+/// Number of `/locate` requests served in each variant since startup.
+#[derive(Default)]
+pub struct VariantCounts {
+    control: AtomicUsize,
+    experiment: AtomicUsize,
+}
+
+#[derive(Serialize)]
+pub struct VariantCountsResult {
+    control: usize,
+    experiment: usize,
+}
+
+impl VariantCounts {
+    /// Count a request served in `variant`.
+    pub fn record(&self, variant: Variant) {
+        let count = match variant {
+            Variant::Control => &self.control,
+            Variant::Experiment => &self.experiment,
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> VariantCountsResult {
+        VariantCountsResult {
+            control: self.control.load(Ordering::Relaxed),
+            experiment: self.experiment.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_matches_the_published_fnv1a() {
+        // Test vectors from the FNV reference implementation.
+        assert_eq!(fnv1a(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a("foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn client_keeps_its_variant() {
+        for client in &["alice", "bob", "3f2b9c1e-7a44-4c1d-9e1b-2d5f0c8a6b71"] {
+            let variant = assign(Some(client));
+            assert!((0..100).all(|_| assign(Some(client)) == variant));
+        }
+    }
+
+    #[test]
+    fn clients_are_split_between_variants() {
+        let control = (0..1000)
+            .filter(|client| assign(Some(&client.to_string())) == Variant::Control)
+            .count();
+        assert!(
+            control > 400 && control < 600,
+            "{} of 1000 in control",
+            control
+        );
+    }
+
+    #[test]
+    fn requests_are_counted_by_variant() {
+        let counts = VariantCounts::default();
+        counts.record(Variant::Control);
+        counts.record(Variant::Experiment);
+        counts.record(Variant::Experiment);
+        let snapshot = counts.snapshot();
+        assert_eq!(snapshot.control, 1);
+        assert_eq!(snapshot.experiment, 2);
+    }
+}
@@ -0,0 +1,201 @@
+//! GeoJSON representations of bars, for map clients.
+//!
+//! Each bar is a Point feature. Its properties carry what a client needs to style the marker, such
+//! as how many tips mention picklebacks, so it doesn't need to make further requests.
+use serde::Serialize;
+
+use crate::barlisting::{BarDetails, BarSummary};
+
+#[derive(Serialize)]
+pub struct Point {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Longitude then latitude, as GeoJSON orders them.
+    coordinates: [f64; 2],
+}
+
+#[derive(Serialize)]
+pub struct BarProperties {
+    id: String,
+    name: String,
+    tip_count: usize,
+    score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rating: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    popularity: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fsq_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seasonal: Option<bool>,
+    /// Where known, and only for a single bar, so listings of every bar stay small.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    neighborhood: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo_url: Option<String>,
+    /// The text of every tip, only for a single bar.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tips: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct Feature {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    geometry: Point,
+    properties: BarProperties,
+}
+
+impl From<BarSummary> for Feature {
+    fn from(bar: BarSummary) -> Self {
+        Feature {
+            kind: "Feature",
+            geometry: Point {
+                kind: "Point",
+                coordinates: [bar.lng, bar.lat],
+            },
+            properties: BarProperties {
+                id: bar.id,
+                name: bar.name,
+                tip_count: bar.tip_count,
+                score: bar.score,
+                rating: bar.rating,
+                popularity: bar.popularity,
+                fsq_url: bar.fsq_url,
+                seasonal: bar.seasonal,
+                neighborhood: None,
+                photo_url: None,
+                tips: None,
+            },
+        }
+    }
+}
+
+impl From<BarDetails> for Feature {
+    fn from(bar: BarDetails) -> Self {
+        Feature {
+            kind: "Feature",
+            geometry: Point {
+                kind: "Point",
+                coordinates: [bar.lng, bar.lat],
+            },
+            properties: BarProperties {
+                id: bar.id,
+                name: bar.name,
+                tip_count: bar.tips.len(),
+                score: bar.score,
+                rating: bar.rating,
+                popularity: bar.popularity,
+                fsq_url: bar.fsq_url,
+                seasonal: bar.seasonal,
+                neighborhood: bar.neighborhood,
+                photo_url: bar.photo_url,
+                tips: Some(bar.tips),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct FeatureCollection {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    features: Vec<Feature>,
+    /// Number of bars in the whole listing, which may be more than `features` if it is one page.
+    total: usize,
+}
+
+impl FeatureCollection {
+    pub fn new(bars: Vec<BarSummary>, total: usize) -> Self {
+        FeatureCollection {
+            kind: "FeatureCollection",
+            features: bars.into_iter().map(Feature::from).collect(),
+            total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::barlisting::BarListing;
+    use crate::config::Config;
+    use crate::deadline::Deadline;
+    use serde_json::json;
+
+    /// A listing with a bar Foursquare told us everything about, and one it told us little about.
+    fn fixture() -> BarListing {
+        let listing = BarListing::without_bars(&Config::default());
+        listing
+            .replace_bars_from_json(
+                json!([
+                    {"id": "a", "name": "Sweet Afton", "lat": 40.76535, "lng": -73.918983,
+                     "tips": ["Pickleback!", "Picklebacks on tap"], "score": 1.25, "rating": 8.9,
+                     "popularity": 4200, "neighborhood": "Astoria", "seasonal": false,
+                     "fsq_url": "https://foursquare.com/v/sweet-afton/a",
+                     "photo_url": "https://fastly.4sqi.net/img/general/original/a.jpg"},
+                    {"id": "b", "name": "Bare", "lat": 40.7, "lng": -74.0, "tips": ["Pickleback"]}
+                ])
+                .to_string()
+                .as_bytes(),
+            )
+            .unwrap();
+        listing
+    }
+
+    #[test]
+    fn collection_has_a_point_per_bar_with_its_marker_properties() {
+        let (bars, total) = fixture().bars_page(0, 10, Deadline::none()).unwrap();
+        let collection = serde_json::to_value(FeatureCollection::new(bars, total)).unwrap();
+        assert_eq!(
+            collection,
+            json!({
+                "type": "FeatureCollection",
+                "features": [
+                    {
+                        "type": "Feature",
+                        "geometry": {"type": "Point", "coordinates": [-73.918983, 40.76535]},
+                        "properties": {
+                            "id": "a",
+                            "name": "Sweet Afton",
+                            "tip_count": 2,
+                            "score": 1.25,
+                            "rating": 8.9,
+                            "popularity": 4200,
+                            "fsq_url": "https://foursquare.com/v/sweet-afton/a",
+                            "seasonal": false,
+                        },
+                    },
+                    {
+                        "type": "Feature",
+                        "geometry": {"type": "Point", "coordinates": [-74.0, 40.7]},
+                        "properties": {"id": "b", "name": "Bare", "tip_count": 1, "score": 0.0},
+                    },
+                ],
+                "total": 2,
+            })
+        );
+    }
+
+    #[test]
+    fn single_bar_also_has_its_tips_and_details() {
+        let bar = fixture().get_bar("a").unwrap();
+        let feature = serde_json::to_value(Feature::from(bar)).unwrap();
+        assert_eq!(
+            feature["geometry"]["coordinates"],
+            json!([-73.918983, 40.76535])
+        );
+        let properties = &feature["properties"];
+        assert_eq!(properties["tip_count"], 2);
+        assert_eq!(properties["popularity"], 4200);
+        assert_eq!(properties["neighborhood"], "Astoria");
+        assert_eq!(
+            properties["tips"],
+            json!(["Pickleback!", "Picklebacks on tap"])
+        );
+        assert_eq!(
+            properties["photo_url"],
+            "https://fastly.4sqi.net/img/general/original/a.jpg"
+        );
+    }
+}
@@ -0,0 +1,330 @@
+//! Middleware upgrading plain HTTP requests to HTTPS.
+use actix_web::http::header::LOCATION;
+use actix_web::middleware::{Middleware, Started};
+use actix_web::{HttpRequest, HttpResponse, Result};
+
+use crate::config::RedirectPolicy;
+
+/// Most redirects the startup check follows from one request before calling it a loop.
+const MAX_REDIRECTS: usize = 5;
+
+/// Host the startup check makes requests for. Only whether it starts with "www." matters.
+const CHECK_HOST: &str = "pickletrack.example";
+
+/// Where a request reaching the SSL terminator with scheme `proto`, for `path` on `host`, is
+/// redirected to under `policy`, or None if it is served as it is.
+pub fn redirect_location(
+    policy: RedirectPolicy,
+    proto: &str,
+    host: &str,
+    path: &str,
+) -> Option<String> {
+    match policy {
+        RedirectPolicy::Www => {
+            if proto == "https" && host.starts_with("www.") {
+                None
+            } else if !host.starts_with("www.") {
+                Some(format!("https://www.{}{}", host, path))
+            } else {
+                Some(format!("https://{}{}", host, path))
+            }
+        }
+        RedirectPolicy::Https if proto != "https" => Some(format!("https://{}{}", host, path)),
+        RedirectPolicy::Https | RedirectPolicy::None => None,
+    }
+}
+
+/// Split a redirect location into its scheme, host and path.
+fn split_location(location: &str) -> Option<(&str, &str, &str)> {
+    let (proto, rest) = location.split_at(location.find("://")?);
+    let rest = &rest[3..];
+    match rest.find('/') {
+        Some(index) => Some((proto, &rest[..index], &rest[index..])),
+        None => Some((proto, rest, "/")),
+    }
+}
+
+/// Check that `policy` serves its canonical URL without redirecting, and that following its
+/// redirects from any mix of scheme and host settles there rather than looping.
+///
+/// Only the redirect logic is checked, against synthetic requests, so this catches mistakes in it
+/// but not a terminator which misreports the scheme.
+pub fn check_redirects(policy: RedirectPolicy) -> Result<(), String> {
+    check_redirects_with(policy, |proto, host, path| {
+        redirect_location(policy, proto, host, path)
+    })
+}
+
+/// Check the redirects `redirect` makes as `check_redirects` does, taking `policy` to say which URL
+/// is canonical.
+fn check_redirects_with(
+    policy: RedirectPolicy,
+    redirect: impl Fn(&str, &str, &str) -> Option<String>,
+) -> Result<(), String> {
+    let www_host = format!("www.{}", CHECK_HOST);
+    let canonical = match policy {
+        RedirectPolicy::Www => Some(("https", www_host.as_str())),
+        RedirectPolicy::Https => Some(("https", CHECK_HOST)),
+        RedirectPolicy::None => None,
+    };
+    if let Some((proto, host)) = canonical {
+        if let Some(location) = redirect(proto, host, "/") {
+            return Err(format!(
+                "Redirect policy {:?} redirects its canonical URL {}://{}/ to {}",
+                policy, proto, host, location
+            ));
+        }
+    }
+
+    for &proto in &["http", "https"] {
+        for &host in &[CHECK_HOST, www_host.as_str()] {
+            let mut chain = vec![format!("{}://{}/locate?lat=0", proto, host)];
+            while let Some(location) = split_location(chain.last().unwrap())
+                .and_then(|(proto, host, path)| redirect(proto, host, path))
+            {
+                let looped = chain.contains(&location) || chain.len() > MAX_REDIRECTS;
+                chain.push(location);
+                if looped {
+                    return Err(format!(
+                        "Redirect policy {:?} loops: {}",
+                        policy,
+                        chain.join(" -> ")
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Start a response to a request which reached the SSL terminator, redirecting it as `policy`
+/// says.
+///
+/// The terminator reports the original scheme in the X-Forwarded-Proto header. Requests without it
+/// didn't come through a terminator, so they are let through as they are. Ones with it but without
+/// a readable Host header can't be redirected anywhere, so are answered with a 400.
+fn start_redirecting<S>(policy: RedirectPolicy, req: &HttpRequest<S>) -> Result<Started> {
+    let proto = match req
+        .headers()
+        .get("x-forwarded-proto")
+        .and_then(|proto| proto.to_str().ok())
+    {
+        Some(proto) => proto,
+        None => return Ok(Started::Done),
+    };
+    let host = match req
+        .headers()
+        .get("host")
+        .and_then(|host| host.to_str().ok())
+    {
+        Some(host) => host,
+        None => {
+            return Ok(Started::Response(
+                HttpResponse::BadRequest().body("Missing or invalid Host header"),
+            ))
+        }
+    };
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+
+    match redirect_location(policy, proto, host, path) {
+        Some(location) => Ok(Started::Response(
+            HttpResponse::PermanentRedirect()
+                .header(LOCATION, location)
+                .finish(),
+        )),
+        None => Ok(Started::Done),
+    }
+}
+
+/// This middleware rewrites all requests to be HTTPS and against "www" (AWS cannot terminate
+/// SSL for apex domains due to DNS limitations).
+pub struct AWSHTTPSWWWOnlyMiddleware;
+
+impl<S> Middleware<S> for AWSHTTPSWWWOnlyMiddleware {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        start_redirecting(RedirectPolicy::Www, req)
+    }
+}
+
+/// This middleware redirects requests which reached the SSL terminator over plain HTTP to the same
+/// URL over HTTPS, leaving the host alone.
+pub struct RequireHttps;
+
+impl<S> Middleware<S> for RequireHttps {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        start_redirecting(RedirectPolicy::Https, req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+
+    /// The response `policy` starts for `req`, or None if the request is let through.
+    fn redirect(policy: RedirectPolicy, req: TestRequest<()>) -> Option<HttpResponse> {
+        match start_redirecting(policy, &req.finish()).unwrap() {
+            Started::Done => None,
+            Started::Response(resp) => Some(resp),
+            _ => panic!("Redirects are decided without waiting"),
+        }
+    }
+
+    fn location(resp: &HttpResponse) -> &str {
+        resp.headers().get(LOCATION).unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn every_policy_passes_its_check() {
+        for &policy in &[
+            RedirectPolicy::Www,
+            RedirectPolicy::Https,
+            RedirectPolicy::None,
+        ] {
+            assert_eq!(check_redirects(policy), Ok(()));
+        }
+    }
+
+    #[test]
+    fn redirect_loop_is_caught() {
+        // Sends the apex to www, but also www back to the apex.
+        let err = check_redirects_with(RedirectPolicy::None, |_, host, path| {
+            Some(match host.strip_prefix("www.") {
+                Some(apex) => format!("https://{}{}", apex, path),
+                None => format!("https://www.{}{}", host, path),
+            })
+        })
+        .unwrap_err();
+        assert!(err.contains("loops"), "{}", err);
+        assert!(
+            err.contains("https://www.pickletrack.example/locate?lat=0"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn redirected_canonical_url_is_caught() {
+        // Upgrades to HTTPS, but also sends HTTPS requests to the www host, which isn't canonical
+        // for the HTTPS-only policy.
+        let err = check_redirects_with(RedirectPolicy::Https, |proto, host, path| {
+            if proto == "https" && host.starts_with("www.") {
+                None
+            } else {
+                Some(format!(
+                    "https://www.{}{}",
+                    host.trim_start_matches("www."),
+                    path
+                ))
+            }
+        })
+        .unwrap_err();
+        assert!(err.contains("redirects its canonical URL"), "{}", err);
+    }
+
+    #[test]
+    fn https_only_upgrades_without_changing_the_host() {
+        assert_eq!(
+            redirect_location(RedirectPolicy::Https, "http", "example.com", "/about?x=1"),
+            Some("https://example.com/about?x=1".to_string())
+        );
+        assert_eq!(
+            redirect_location(RedirectPolicy::Https, "http", "www.example.com", "/"),
+            Some("https://www.example.com/".to_string())
+        );
+    }
+
+    #[test]
+    fn https_only_serves_https_on_any_host() {
+        assert_eq!(
+            redirect_location(RedirectPolicy::Https, "https", "example.com", "/"),
+            None
+        );
+        assert_eq!(
+            redirect_location(RedirectPolicy::Https, "https", "www.example.com", "/"),
+            None
+        );
+        assert_eq!(check_redirects(RedirectPolicy::Https), Ok(()));
+    }
+
+    #[test]
+    fn https_only_middleware_redirects_plain_requests() {
+        let resp = redirect(
+            RedirectPolicy::Https,
+            TestRequest::with_header("x-forwarded-proto", "http")
+                .header("host", "example.com")
+                .uri("/locate?lat=40.7&lng=-74.0"),
+        )
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            location(&resp),
+            "https://example.com/locate?lat=40.7&lng=-74.0"
+        );
+
+        assert!(redirect(
+            RedirectPolicy::Https,
+            TestRequest::with_header("x-forwarded-proto", "https").header("host", "example.com"),
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn proxied_request_without_host_is_a_bad_request() {
+        for &policy in &[RedirectPolicy::Www, RedirectPolicy::Https] {
+            let resp = redirect(
+                policy,
+                TestRequest::with_header("x-forwarded-proto", "http").uri("/about"),
+            )
+            .unwrap();
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn proxied_request_with_unreadable_host_is_a_bad_request() {
+        let resp = redirect(
+            RedirectPolicy::Www,
+            TestRequest::with_header("x-forwarded-proto", "http")
+                .header("host", &b"caf\xe9.example"[..])
+                .uri("/about"),
+        )
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn www_redirects_keep_the_path_and_query() {
+        // Without the www prefix, whatever the scheme.
+        assert_eq!(
+            redirect_location(RedirectPolicy::Www, "https", "example.com", "/about?x=1"),
+            Some("https://www.example.com/about?x=1".to_string())
+        );
+        // With it, over plain HTTP.
+        assert_eq!(
+            redirect_location(RedirectPolicy::Www, "http", "www.example.com", "/about?x=1"),
+            Some("https://www.example.com/about?x=1".to_string())
+        );
+        assert_eq!(check_redirects(RedirectPolicy::Www), Ok(()));
+    }
+
+    #[test]
+    fn www_middleware_keeps_the_path_and_query() {
+        for &host in &["example.com", "www.example.com"] {
+            let resp = redirect(
+                RedirectPolicy::Www,
+                TestRequest::with_header("x-forwarded-proto", "http")
+                    .header("host", host)
+                    .uri("/about?x=1"),
+            )
+            .unwrap();
+            assert_eq!(location(&resp), "https://www.example.com/about?x=1");
+        }
+    }
+
+    #[test]
+    fn request_without_host_or_proxy_is_let_through() {
+        assert!(redirect(RedirectPolicy::Www, TestRequest::default().uri("/about")).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+//! Matching tips against the languages a user prefers to read.
+
+/// Parse an `Accept-Language` style preference list, such as `fr-CH, fr;q=0.9, en;q=0.8, *;q=0.5`,
+/// into lowercase language tags ordered from most to least preferred.
+///
+/// Parsing is lenient, since clients get this wrong in all sorts of ways. Empty entries are ignored,
+/// a missing or malformed quality value counts as 1, and entries with a quality of 0 are dropped.
+pub fn parse_language_preferences(header: &str) -> Vec<String> {
+    let mut preferences: Vec<(String, f64)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            if tag.is_empty() {
+                return None;
+            }
+            let quality = parts
+                .filter_map(|parameter| {
+                    let parameter = parameter.trim();
+                    if parameter.starts_with("q=") || parameter.starts_with("Q=") {
+                        parameter[2..].trim().parse::<f64>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .filter(|quality| quality.is_finite())
+                .map_or(1.0, |quality| quality.clamp(0.0, 1.0));
+            if quality == 0.0 {
+                None
+            } else {
+                Some((tag, quality))
+            }
+        })
+        .collect();
+
+    // The sort is stable, so entries of equal quality keep the order the client gave them in.
+    preferences.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    preferences.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Whether a tip written in `lang` satisfies the preferred language `tag`.
+///
+/// Only the primary subtag is compared, so a preference for `en-US` accepts tips Foursquare marked
+/// as `en`. The wildcard `*` accepts any tip, including those with no known language.
+pub fn language_matches(tag: &str, lang: Option<&str>) -> bool {
+    if tag == "*" {
+        return true;
+    }
+    let primary = |tag: &str| tag.split('-').next().unwrap_or("").to_lowercase();
+    match lang {
+        Some(lang) => primary(tag) == primary(lang),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferences_are_ordered_by_quality() {
+        assert_eq!(
+            parse_language_preferences("en;q=0.8, fr-CH, *;q=0.5, fr;q=0.9"),
+            vec!["fr-ch", "fr", "en", "*"]
+        );
+    }
+
+    #[test]
+    fn equally_preferred_languages_keep_their_order() {
+        assert_eq!(
+            parse_language_preferences("de, es;q=0.7, it;q=0.7, pt"),
+            vec!["de", "pt", "es", "it"]
+        );
+    }
+
+    #[test]
+    fn malformed_entries_are_tolerated() {
+        // Bad quality values count as 1, out of range ones are clamped, and q=0 rules the language
+        // out.
+        assert_eq!(
+            parse_language_preferences(" , en;q=abc, ja;Q=7, ko;q=0, zh;q=NaN, ,fr;q=0.1"),
+            vec!["en", "ja", "zh", "fr"]
+        );
+        assert!(parse_language_preferences("").is_empty());
+    }
+
+    #[test]
+    fn regional_preferences_match_the_language() {
+        assert!(language_matches("en-us", Some("en")));
+        assert!(language_matches("en", Some("EN-GB")));
+        assert!(!language_matches("en", Some("fr")));
+        assert!(!language_matches("en", None));
+    }
+
+    #[test]
+    fn wildcard_falls_back_to_any_tip() {
+        assert!(language_matches("*", Some("ko")));
+        assert!(language_matches("*", None));
+    }
+}
@@ -9,66 +9,241 @@
 //! we place the server behind an SSL terminator on AWS. If the user attempts to load via HTTP, we
 //! see this in the X-Forwarded-Proto header and redirect them to HTTPS.
 mod barlisting;
-use barlisting::BarListing;
+mod concurrency;
+mod config;
+mod coordinates;
+mod cors;
+mod deadline;
+mod experiment;
+mod geojson;
+mod https;
+mod language;
+mod metrics;
+mod pages;
+mod ratelimit;
+mod remote;
+mod scrape;
+mod timeout;
+mod units;
+mod watch;
+use barlisting::{BarListing, LocateMode, LocateOptions, BARS_FILE_PATH, MAXIMUM_DITANCE_MILES};
+use concurrency::ConcurrencyLimit;
+use config::{Config, ExcessPrecision, RedirectPolicy, SwappedCoordinates, Variant};
+use cors::Cors;
+use deadline::Expired;
+use experiment::VariantCounts;
+use geojson::{Feature, FeatureCollection};
+use https::{AWSHTTPSWWWOnlyMiddleware, RequireHttps};
+use language::parse_language_preferences;
+use metrics::Metrics;
+use ratelimit::{RateLimit, RateLimiter};
+use timeout::RequestTimeout;
+use units::{DistanceUnit, Miles};
 
 use actix_web::fs::NamedFile;
-use actix_web::http::header::LOCATION;
+use actix_web::http::header::{ACCEPT_LANGUAGE, AUTHORIZATION};
 use actix_web::http::Method;
-use actix_web::middleware::Started::{Done, Response};
-use actix_web::middleware::{Logger, Middleware, Started};
-use actix_web::{server, App, HttpRequest, HttpResponse, Json, Query, Result, State};
+use actix_web::middleware::Logger;
+use actix_web::{server, App, Either, HttpRequest, HttpResponse, Json, Query, Result, State};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use tokio::prelude::*;
 use tokio::timer::Interval;
 
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// Address the server listens on.
+const BIND_ADDRESS: &str = "0.0.0.0:1025";
 
 const INDEX_HTML_PATH: &str = "static/index.html";
 const ABOUT_HTML_PATH: &str = "static/about.html";
 
-/// This middleware rewrites all requests to be HTTPS and against "www" (AWS cannot terminate
-/// SSL for apex domains due to DNS limitations).
-struct AWSHTTPSWWWOnlyMiddleware;
-impl<S> Middleware<S> for AWSHTTPSWWWOnlyMiddleware {
-    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
-        if !req.headers().contains_key("x-forwarded-proto") {
-            // Not running behind AWS a HTTPS proxy. Just allow
-            // everything without redirection.
-            return Ok(Done);
-        }
+/// Copies of the static pages built into the binary, served with `--embedded-assets`.
+const EMBEDDED_INDEX_HTML: &str = include_str!("../../../static/index.html");
+const EMBEDDED_ABOUT_HTML: &str = include_str!("../../../static/about.html");
 
-        let host: &str = req.headers().get("host").unwrap().to_str().unwrap();
-        if req.headers().get("x-forwarded-proto").unwrap() == "https" && host.starts_with("www.") {
-            return Ok(Done);
-        }
+/// Maximum number of tips a client may request alongside a suggested bar.
+const MAXIMUM_RESPONSE_TIPS: usize = 10;
 
-        let mut www_uri: String = "https://".into();
-        if !host.starts_with("www.") {
-            www_uri.push_str("www.");
-            www_uri.push_str(host);
-        } else {
-            www_uri.push_str(host);
-            www_uri.push_str(req.uri().path_and_query().unwrap().as_str());
-        }
+/// Maximum number of alternates a client may request alongside a suggested bar.
+const MAXIMUM_RESPONSE_ALTERNATES: usize = 5;
+
+/// Number of bars returned by `/top` when the client doesn't ask for a specific number.
+const DEFAULT_TOP_BARS: usize = 10;
+
+/// How long after startup to try loading the listing again, if it couldn't be loaded at startup.
+const LISTING_RETRY_DELAY: Duration = Duration::from_secs(60 * 5);
+
+/// How often the listing file is checked for a new listing, see `BarListing::reload_if_changed`,
+/// where its directory can't be watched for changes.
+const LISTING_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum number of bars a client may request from `/top`.
+const MAXIMUM_TOP_BARS: usize = 100;
 
-        Ok(Response(
-            HttpResponse::PermanentRedirect()
-                .header(LOCATION, www_uri)
-                .finish(),
+/// Number of bars returned by `/bars` when the client asks for a page without saying how big.
+const DEFAULT_BARS_PAGE: usize = 100;
+
+/// Maximum number of bars a client may request in a page of `/bars`.
+const MAXIMUM_BARS_PAGE: usize = 1000;
+
+/// Number of bars returned by `/locate/list` when the client doesn't ask for a specific number.
+const DEFAULT_NEARBY_BARS: usize = 10;
+
+/// Maximum number of bars a client may request from `/locate/list`.
+const MAXIMUM_NEARBY_BARS: usize = 50;
+
+/// State shared by the handlers of each server worker.
+struct AppState {
+    listing: Arc<BarListing>,
+    config: Arc<Config>,
+    /// When the server started, for the readiness grace period.
+    started: Instant,
+    variant_counts: Arc<VariantCounts>,
+    metrics: Arc<Metrics>,
+}
+
+/// Serve a static page, either from the copy built into the binary or from disk.
+fn static_page(
+    req: &HttpRequest<AppState>,
+    path: &str,
+    embedded: &'static str,
+) -> Result<Either<HttpResponse, NamedFile>> {
+    if req.state().config.embedded_assets {
+        Ok(Either::A(
+            HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(embedded),
         ))
+    } else {
+        Ok(Either::B(NamedFile::open(path)?))
     }
 }
 
 /// Request the index page.
-fn index(_: &HttpRequest<Arc<BarListing>>) -> Result<NamedFile> {
-    Ok(NamedFile::open(INDEX_HTML_PATH)?)
+fn index(req: &HttpRequest<AppState>) -> Result<Either<HttpResponse, NamedFile>> {
+    static_page(req, INDEX_HTML_PATH, EMBEDDED_INDEX_HTML)
 }
 
 /// Request the about page.
-fn about(_: &HttpRequest<Arc<BarListing>>) -> Result<NamedFile> {
-    Ok(NamedFile::open(ABOUT_HTML_PATH)?)
+fn about(req: &HttpRequest<AppState>) -> Result<Either<HttpResponse, NamedFile>> {
+    static_page(req, ABOUT_HTML_PATH, EMBEDDED_ABOUT_HTML)
+}
+
+/// Browsers request a favicon on every page load. We don't have one, so answer with an empty
+/// response rather than a 404.
+fn favicon(_: &HttpRequest<AppState>) -> HttpResponse {
+    debug!("Favicon requested");
+    HttpResponse::NoContent().finish()
+}
+
+#[derive(Serialize)]
+struct NotFoundResult {
+    error: &'static str,
+    path: String,
+}
+
+/// Respond to any request that doesn't match a route.
+fn not_found(req: &HttpRequest<AppState>) -> HttpResponse {
+    info!("No route for {} {}", req.method(), req.path());
+    HttpResponse::NotFound().json(NotFoundResult {
+        error: "Not found",
+        path: req.path().to_string(),
+    })
+}
+
+/// Liveness probe. This passes as long as the server is able to handle requests at all.
+fn healthz(_: &HttpRequest<AppState>) -> HttpResponse {
+    HttpResponse::Ok().body("ok")
+}
+
+/// Readiness probe. This only passes once a bar listing has been loaded and the configured startup
+/// grace period has elapsed, so orchestration doesn't route traffic to an instance which can't
+/// suggest bars yet.
+fn readyz(req: &HttpRequest<AppState>) -> HttpResponse {
+    let state = req.state();
+    if state.listing.is_loaded() && state.started.elapsed() >= state.config.startup_grace {
+        HttpResponse::Ok().body("ready")
+    } else {
+        HttpResponse::ServiceUnavailable().body("not ready")
+    }
+}
+
+/// Operational metrics in the Prometheus text format.
+fn metrics(req: &HttpRequest<AppState>) -> HttpResponse {
+    let state = req.state();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.metrics.render(state.listing.bar_count()))
+}
+
+/// Compare two strings in time independent of where they differ, so response times don't leak
+/// how much of a guessed secret was right.
+fn secrets_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |difference, (x, y)| difference | (x ^ y))
+            == 0
+}
+
+/// Check a request to an `/admin` endpoint carries the admin secret as a bearer token, returning
+/// the response to send instead if it doesn't.
+///
+/// When no admin secret is configured the admin endpoints don't exist, as far as clients can tell.
+fn authorize_admin(req: &HttpRequest<AppState>) -> Option<HttpResponse> {
+    let secret = match req.state().config.admin_secret {
+        Some(ref secret) => secret,
+        None => return Some(not_found(req)),
+    };
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    match token {
+        Some(token) if secrets_match(token, secret) => None,
+        _ => {
+            warn!("Unauthorized request for {}", req.path());
+            Some(HttpResponse::Unauthorized().finish())
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AdminConfigResult<'a> {
+    bind_address: &'static str,
+    listing_path: &'static str,
+    max_distance_miles: f64,
+    max_response_tips: usize,
+    /// The parsed command line, with secrets redacted.
+    config: &'a Config,
+}
+
+/// Show the effective configuration of the running server, for debugging.
+fn admin_config(req: &HttpRequest<AppState>) -> HttpResponse {
+    if let Some(response) = authorize_admin(req) {
+        return response;
+    }
+    HttpResponse::Ok().json(AdminConfigResult {
+        bind_address: BIND_ADDRESS,
+        listing_path: BARS_FILE_PATH,
+        max_distance_miles: MAXIMUM_DITANCE_MILES,
+        max_response_tips: MAXIMUM_RESPONSE_TIPS,
+        config: &req.state().config,
+    })
+}
+
+/// Show how many `/locate` requests each experiment variant has served since startup.
+fn admin_experiment(req: &HttpRequest<AppState>) -> HttpResponse {
+    if let Some(response) = authorize_admin(req) {
+        return response;
+    }
+    HttpResponse::Ok().json(req.state().variant_counts.snapshot())
 }
 
 #[derive(Serialize)]
@@ -76,40 +251,675 @@ struct LocateQueryResult {
     id: String,
     name: String,
     comment: String,
+    /// Where the comment and tips came from, if the server is configured to say.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attribution: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tips: Vec<String>,
+    /// The bar's page on Foursquare, if the listing has it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fsq_url: Option<String>,
+    /// Area the bar is in, such as the borough, if the listing has it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    neighborhood: Option<String>,
+    /// Whether the bar seems to serve picklebacks only seasonally, if the listing classifies it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seasonal: Option<bool>,
+    /// The bar's featured photo, if the listing has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo_url: Option<String>,
+    /// Distance from the user to the bar, absent if no bar was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f64>,
+    /// Distance from the user to the bar in miles, whatever the units, rounded to one decimal
+    /// place for display. Absent if no bar was found.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance_miles: Option<f64>,
+    /// The search radius the bar was found within, or the widest radius searched if none was.
+    radius: f64,
+    /// Other bars in range, closest first, if asked for.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    alternates: Vec<AlternateResult>,
+    /// The unit of every distance in the result.
+    units: DistanceUnit,
+    /// The utility the bar was chosen with, when an experiment is comparing two.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<Variant>,
+}
+
+#[derive(Serialize)]
+struct AlternateResult {
+    id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    neighborhood: Option<String>,
+    distance: f64,
 }
 
 #[derive(Deserialize)]
-struct LatLng {
+struct LocateQuery {
+    lat: f64,
+    lng: f64,
+    /// Number of distinct tips to return for the bar, in addition to the single comment.
+    #[serde(default)]
+    tips: usize,
+    /// Number of other bars in range to return as alternates.
+    #[serde(default)]
+    alternates: usize,
+    #[serde(default)]
+    units: DistanceUnit,
+    /// Search radius in `units`, in place of the default three miles. It is still widened by
+    /// escalation, and capped at `--max-radius-miles`.
+    radius: Option<f64>,
+    /// Preferred languages for the comment, in `Accept-Language` syntax. This overrides the
+    /// header, for clients that can't set it.
+    lang: Option<String>,
+    #[serde(default)]
+    mode: LocateMode,
+    /// Only suggest bars with a photo where there are any, as `has_photo=1` or `has_photo=true`.
+    has_photo: Option<String>,
+    /// Wrap the result in a `LocateEnvelope`, as `debug=1` or `debug=true`.
+    debug: Option<String>,
+    /// Opaque id of the client, which keeps it in the same experiment variant across requests.
+    client: Option<String>,
+}
+
+/// Whether a switch passed as a query parameter is on.
+fn is_enabled(value: &str) -> bool {
+    value == "1" || value == "true"
+}
+
+#[derive(Serialize)]
+struct ExcessPrecisionResult {
+    error: &'static str,
+    max_decimals: usize,
+}
+
+#[derive(Serialize)]
+struct InvalidCoordinatesResult {
+    error: &'static str,
+    lat: f64,
+    lng: f64,
+}
+
+/// Answer coordinates which aren't on the globe with a 400, rather than searching with distances
+/// which make no sense.
+fn check_coordinates(lat: f64, lng: f64) -> Result<(), HttpResponse> {
+    coordinates::validate_latlng(lat, lng).map_err(|error| {
+        HttpResponse::BadRequest().json(InvalidCoordinatesResult { error, lat, lng })
+    })
+}
+
+#[derive(Serialize)]
+struct InvalidRadiusResult {
+    error: &'static str,
+    max_radius: f64,
+}
+
+#[derive(Serialize)]
+struct SwappedCoordinatesResult {
+    error: &'static str,
     lat: f64,
     lng: f64,
 }
 
-fn locate(state: State<Arc<BarListing>>, latlng: Query<LatLng>) -> Json<LocateQueryResult> {
-    if let Some((id, name, comment)) = state.locate_pickleback(latlng.lat, latlng.lng) {
-        Json(LocateQueryResult {
-            id,
-            name,
-            comment,
+/// The query `/locate` searched with, after corrections and limits were applied.
+#[derive(Serialize)]
+struct InterpretedLocateQuery {
+    lat: f64,
+    lng: f64,
+    /// The widest search radius allowed.
+    radius: f64,
+    tips: usize,
+    alternates: usize,
+    units: DistanceUnit,
+    mode: LocateMode,
+    languages: Vec<String>,
+    has_photo: bool,
+}
+
+/// A `/locate` result along with what was searched for and how long it took, to help debug clients.
+#[derive(Serialize)]
+struct LocateEnvelope {
+    query: InterpretedLocateQuery,
+    result: LocateQueryResult,
+    took_ms: f64,
+}
+
+/// The search radius a client asked for with `radius`, in `units`, in miles, or the default if it
+/// didn't ask. A radius which isn't a positive number is answered with a 400.
+fn requested_radius_miles(
+    radius: Option<f64>,
+    units: DistanceUnit,
+    config: &Config,
+) -> Result<f64, HttpResponse> {
+    match radius {
+        Some(radius) if radius.is_finite() && radius > 0.0 => Ok(units.to_miles(radius).0),
+        Some(_) => Err(HttpResponse::BadRequest().json(InvalidRadiusResult {
+            error: "The search radius must be a positive number",
+            max_radius: units.convert(Miles(config.max_radius_miles)),
+        })),
+        None => Ok(MAXIMUM_DITANCE_MILES),
+    }
+}
+
+/// The languages the client would like comments in, most preferred first, from `lang` if it gave
+/// it, and otherwise the `Accept-Language` header.
+fn preferred_languages(req: &HttpRequest<AppState>, lang: Option<&str>) -> Vec<String> {
+    match lang {
+        Some(lang) => parse_language_preferences(lang),
+        None => req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|header| header.to_str().ok())
+            .map(parse_language_preferences)
+            .unwrap_or_default(),
+    }
+}
+
+/// Suggest a nearby bar. If there is nothing within the default radius, the radius is widened
+/// according to the configured escalation steps.
+///
+/// The comment is picked from tips in the languages the client prefers, where the bar has any.
+fn locate(
+    req: HttpRequest<AppState>,
+    query: Query<LocateQuery>,
+) -> Either<Json<LocateQueryResult>, HttpResponse> {
+    let started = Instant::now();
+    let state = req.state();
+    state.metrics.record_locate_request();
+    let base_radius_miles = match requested_radius_miles(query.radius, query.units, &state.config) {
+        Ok(radius_miles) => radius_miles,
+        Err(response) => return Either::B(response),
+    };
+    let radii = state.config.search_radii(base_radius_miles);
+    let max_radius_miles = radii.iter().cloned().fold(0.0, f64::max);
+    let (mut lat, mut lng) = (query.lat, query.lng);
+    if let Some(max_decimals) = state.config.max_coordinate_decimals {
+        if coordinates::decimal_places(lat) > max_decimals
+            || coordinates::decimal_places(lng) > max_decimals
+        {
+            match state.config.excess_precision {
+                ExcessPrecision::Reject => {
+                    return Either::B(HttpResponse::BadRequest().json(ExcessPrecisionResult {
+                        error: "Coordinates have more decimal places than allowed",
+                        max_decimals,
+                    }));
+                }
+                ExcessPrecision::Round => {
+                    lat = coordinates::round_to_places(lat, max_decimals);
+                    lng = coordinates::round_to_places(lng, max_decimals);
+                }
+            }
+        }
+    }
+    if state.config.swapped_coordinates != SwappedCoordinates::Ignore
+        && coordinates::looks_swapped(&state.listing.coverage(), lat, lng, max_radius_miles)
+    {
+        if state.config.swapped_coordinates == SwappedCoordinates::Reject {
+            return Either::B(HttpResponse::BadRequest().json(SwappedCoordinatesResult {
+                error: "No bars near these coordinates, but there are with lat and lng swapped",
+                lat,
+                lng,
+            }));
+        }
+        info!(
+            "Correcting swapped coordinates {}, {} from {}",
+            lat,
+            lng,
+            req.connection_info().remote().unwrap_or("unknown client")
+        );
+        ::std::mem::swap(&mut lat, &mut lng);
+    }
+    // Checked after correcting swapped coordinates, so a longitude beyond 90 given as the latitude
+    // is corrected rather than rejected.
+    if let Err(response) = check_coordinates(lat, lng) {
+        return Either::B(response);
+    }
+
+    let languages = preferred_languages(&req, query.lang.as_deref());
+    let variant = state.config.experiment_utility.map(|_| {
+        let variant = experiment::assign(query.client.as_deref());
+        state.variant_counts.record(variant);
+        variant
+    });
+    let mut options = LocateOptions {
+        radius_miles: 0.0,
+        tip_count: query.tips.min(MAXIMUM_RESPONSE_TIPS),
+        languages,
+        mode: query.mode,
+        has_photo: query.has_photo.as_deref().is_some_and(is_enabled),
+        alternates: query.alternates.min(MAXIMUM_RESPONSE_ALTERNATES),
+        min_alternate_separation_miles: state.config.min_alternate_separation_miles,
+        utility: state.config.utility(variant.unwrap_or(Variant::Control)),
+    };
+    let deadline = timeout::deadline(&req);
+    let result = radii
+        .iter()
+        .find_map(|&radius_miles| {
+            if let Err(expired) = deadline.check() {
+                return Some(Err(expired));
+            }
+            options.radius_miles = radius_miles;
+            let suggestion = state.listing.locate_pickleback(lat, lng, &options)?;
+            Some(Ok(LocateQueryResult {
+                id: suggestion.id,
+                name: suggestion.name,
+                comment: suggestion.comment,
+                attribution: state.config.attribution.clone(),
+                tips: suggestion.tips,
+                fsq_url: suggestion.fsq_url,
+                neighborhood: suggestion.neighborhood,
+                seasonal: suggestion.seasonal,
+                photo_url: suggestion.photo_url,
+                distance: Some(query.units.convert(Miles(suggestion.distance_miles))),
+                distance_miles: Some(coordinates::round_to_places(suggestion.distance_miles, 1)),
+                radius: query.units.convert(Miles(radius_miles)),
+                alternates: suggestion
+                    .alternates
+                    .into_iter()
+                    .map(|alternate| AlternateResult {
+                        id: alternate.id,
+                        name: alternate.name,
+                        neighborhood: alternate.neighborhood,
+                        distance: query.units.convert(Miles(alternate.distance_miles)),
+                    })
+                    .collect(),
+                units: query.units,
+                variant,
+            }))
         })
-    } else {
-        Json(LocateQueryResult {
+        .transpose();
+    let result = match result {
+        Ok(result) => result.unwrap_or_else(|| LocateQueryResult {
             id: "".into(),
             name: "".into(),
             comment: "".into(),
-        })
+            attribution: None,
+            tips: Vec::new(),
+            fsq_url: None,
+            neighborhood: None,
+            seasonal: None,
+            photo_url: None,
+            distance: None,
+            distance_miles: None,
+            radius: query.units.convert(Miles(max_radius_miles)),
+            alternates: Vec::new(),
+            units: query.units,
+            variant,
+        }),
+        Err(Expired) => return Either::B(timeout::timed_out(&req)),
+    };
+    state
+        .metrics
+        .record_locate_answer(started.elapsed(), !result.id.is_empty());
+
+    let debug = match query.debug.as_deref() {
+        Some(value) => is_enabled(value),
+        None => state.config.response_envelope,
+    };
+    if !debug {
+        return Either::A(Json(result));
+    }
+    Either::B(HttpResponse::Ok().json(LocateEnvelope {
+        query: InterpretedLocateQuery {
+            lat,
+            lng,
+            radius: query.units.convert(Miles(max_radius_miles)),
+            tips: options.tip_count,
+            alternates: options.alternates,
+            units: query.units,
+            mode: options.mode,
+            languages: options.languages,
+            has_photo: options.has_photo,
+        },
+        result,
+        took_ms: started.elapsed().as_secs_f64() * 1000.0,
+    }))
+}
+
+#[derive(Deserialize)]
+struct LocateListQuery {
+    lat: f64,
+    lng: f64,
+    limit: Option<usize>,
+    #[serde(default)]
+    units: DistanceUnit,
+    /// Search radius in `units`, as for `/locate`, but never widened.
+    radius: Option<f64>,
+    lang: Option<String>,
+    /// Only list bars with a photo where there are any, as for `/locate`.
+    has_photo: Option<String>,
+}
+
+#[derive(Serialize)]
+struct NearbyResult {
+    id: String,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    neighborhood: Option<String>,
+    distance: f64,
+    comment: String,
+}
+
+#[derive(Serialize)]
+struct LocateListResult {
+    bars: Vec<NearbyResult>,
+    /// The search radius the bars were found within.
+    radius: f64,
+    /// The unit of every distance in the result.
+    units: DistanceUnit,
+}
+
+/// List the bars closest to the user, closest first, each with a comment, for clients offering a
+/// choice rather than a single suggestion.
+fn locate_list(req: HttpRequest<AppState>, query: Query<LocateListQuery>) -> HttpResponse {
+    let state = req.state();
+    if let Err(response) = check_coordinates(query.lat, query.lng) {
+        return response;
+    }
+    let radius_miles = match requested_radius_miles(query.radius, query.units, &state.config) {
+        Ok(radius_miles) => radius_miles.min(state.config.max_radius_miles),
+        Err(response) => return response,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_NEARBY_BARS)
+        .min(MAXIMUM_NEARBY_BARS);
+    let languages = preferred_languages(&req, query.lang.as_deref());
+    let deadline = timeout::deadline(&req);
+    let nearby = match state.listing.nearby_picklebacks(
+        query.lat,
+        query.lng,
+        radius_miles,
+        &languages,
+        query.has_photo.as_deref().is_some_and(is_enabled),
+        limit,
+        deadline,
+    ) {
+        Ok(nearby) => nearby,
+        Err(Expired) => return timeout::timed_out(&req),
+    };
+    HttpResponse::Ok().json(LocateListResult {
+        bars: nearby
+            .into_iter()
+            .map(|bar| NearbyResult {
+                id: bar.id,
+                name: bar.name,
+                neighborhood: bar.neighborhood,
+                distance: query.units.convert(Miles(bar.distance_miles)),
+                comment: bar.comment,
+            })
+            .collect(),
+        radius: query.units.convert(Miles(radius_miles)),
+        units: query.units,
+    })
+}
+
+#[derive(Serialize)]
+struct TopQueryResult {
+    id: String,
+    name: String,
+    lat: f64,
+    lng: f64,
+    score: f64,
+}
+
+#[derive(Deserialize)]
+struct TopQuery {
+    limit: Option<usize>,
+}
+
+/// List the bars with the highest quality score, regardless of the user's location.
+fn top(state: State<AppState>, query: Query<TopQuery>) -> Json<Vec<TopQueryResult>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TOP_BARS)
+        .min(MAXIMUM_TOP_BARS);
+    Json(
+        state
+            .listing
+            .top_bars(limit)
+            .into_iter()
+            .map(|bar| TopQueryResult {
+                id: bar.id,
+                name: bar.name,
+                lat: bar.lat,
+                lng: bar.lng,
+                score: bar.score,
+            })
+            .collect(),
+    )
+}
+
+#[derive(Deserialize)]
+struct BarsQuery {
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+/// Every bar in the listing as GeoJSON, or a page of them if the client gives an offset or limit.
+fn bars(req: HttpRequest<AppState>, query: Query<BarsQuery>) -> HttpResponse {
+    let limit = match (query.offset, query.limit) {
+        (None, None) => usize::MAX,
+        (_, limit) => limit.unwrap_or(DEFAULT_BARS_PAGE).min(MAXIMUM_BARS_PAGE),
+    };
+    let offset = query.offset.unwrap_or(0);
+    let deadline = timeout::deadline(&req);
+    let (bars, total) = match req.state().listing.bars_page(offset, limit, deadline) {
+        Ok(page) => page,
+        Err(Expired) => return timeout::timed_out(&req),
+    };
+    HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .json(FeatureCollection::new(bars, total))
+}
+
+#[derive(Deserialize)]
+struct BoxQuery {
+    sw_lat: f64,
+    sw_lng: f64,
+    ne_lat: f64,
+    ne_lng: f64,
+}
+
+#[derive(Serialize)]
+struct InvalidBoxResult {
+    error: &'static str,
+}
+
+/// Every bar within a map viewport, as GeoJSON. A box whose west edge is east of its east edge
+/// spans the antimeridian.
+fn bars_in_box(req: HttpRequest<AppState>, query: Query<BoxQuery>) -> HttpResponse {
+    if let Err(response) = check_coordinates(query.sw_lat, query.sw_lng)
+        .and_then(|()| check_coordinates(query.ne_lat, query.ne_lng))
+    {
+        return response;
+    }
+    if query.sw_lat > query.ne_lat {
+        return HttpResponse::BadRequest().json(InvalidBoxResult {
+            error: "The south west corner must not be north of the north east corner",
+        });
+    }
+    let bars = match req.state().listing.bars_in_box(
+        (query.sw_lat, query.sw_lng),
+        (query.ne_lat, query.ne_lng),
+        timeout::deadline(&req),
+    ) {
+        Ok(bars) => bars,
+        Err(Expired) => return timeout::timed_out(&req),
+    };
+    let total = bars.len();
+    HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .json(FeatureCollection::new(bars, total))
+}
+
+/// A single bar by its Foursquare id, as a GeoJSON feature with all its tips.
+fn bar(req: &HttpRequest<AppState>) -> HttpResponse {
+    let id = req.match_info().get("id").unwrap_or("");
+    match req.state().listing.get_bar(id) {
+        Some(bar) => HttpResponse::Ok()
+            .content_type("application/geo+json")
+            .json(Feature::from(bar)),
+        None => not_found(req),
+    }
+}
+
+/// The bars in one neighborhood as GeoJSON, for clients loading the map by area. Only listings
+/// scraped with `--partition-by-neighborhood` have any.
+fn neighborhood_bars(req: &HttpRequest<AppState>) -> HttpResponse {
+    let neighborhood = req.match_info().get("neighborhood").unwrap_or("");
+    match req.state().listing.neighborhood_bars(neighborhood) {
+        Ok(Some(bars)) => {
+            let total = bars.len();
+            HttpResponse::Ok()
+                .content_type("application/geo+json")
+                .json(FeatureCollection::new(bars, total))
+        }
+        Ok(None) => not_found(req),
+        Err(err) => {
+            error!("{}", err);
+            HttpResponse::InternalServerError().body("Couldn't read neighborhood")
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct CoverageResult {
+    neighborhood: String,
+    bars: usize,
+    /// The area spanned by the neighborhood's bars, as GeoJSON orders a bounding box: west, south,
+    /// east, north.
+    bbox: [f64; 4],
+}
+
+/// Where the listing has bars, so a client can gray out areas we can't suggest anything in.
+fn coverage(state: State<AppState>) -> Json<Vec<CoverageResult>> {
+    Json(
+        state
+            .listing
+            .coverage()
+            .into_iter()
+            .map(|entry| CoverageResult {
+                neighborhood: entry.neighborhood,
+                bars: entry.bars,
+                bbox: [entry.min_lng, entry.min_lat, entry.max_lng, entry.max_lat],
+            })
+            .collect(),
+    )
+}
+
+/// A page for a single bar, by its Foursquare id.
+fn bar_page(req: &HttpRequest<AppState>) -> HttpResponse {
+    let id = req.match_info().get("id").unwrap_or("");
+    match req.state().listing.get_bar(id) {
+        Some(bar) => HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(pages::bar_page(&bar)),
+        None => not_found(req),
+    }
+}
+
+/// A sitemap of every page, including one for each bar, for search engines. It is built when the
+/// listing is loaded, and only if the site URL is configured.
+fn sitemap(req: &HttpRequest<AppState>) -> HttpResponse {
+    match req.state().listing.sitemap() {
+        Some(sitemap) => HttpResponse::Ok()
+            .content_type("application/xml")
+            .body(sitemap),
+        None => not_found(req),
     }
 }
 
 fn main() {
     env_logger::init();
 
-    let state = Arc::new(BarListing::new());
-    let cloned = state.clone();
+    let config = match Config::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = https::check_redirects(config.redirect) {
+        eprintln!("{}", err);
+        std::process::exit(2);
+    }
+
+    let listing = if config.embedded_scrape {
+        // The last listing written to disk, if any, is served until the first scrape finishes.
+        let listing = BarListing::without_bars(&config);
+        if Path::new(BARS_FILE_PATH).exists() {
+            listing.reload_bars();
+        }
+        Arc::new(listing)
+    } else {
+        Arc::new(BarListing::new(&config))
+    };
+    let embedded_scrape = config.embedded_scrape;
+    let (first_refresh, refresh_interval) = if embedded_scrape {
+        let interval = config.embedded_scrape_interval;
+        if listing.is_loaded() {
+            (Instant::now() + interval, interval)
+        } else {
+            (Instant::now(), interval)
+        }
+    } else {
+        let day = Duration::from_secs(60 * 60 * 24);
+        // Without a listing the server can only serve its pages, so it doesn't wait a day to try
+        // again.
+        let first_reload = if listing.is_loaded() {
+            day
+        } else {
+            LISTING_RETRY_DELAY
+        };
+        (Instant::now() + first_reload, day)
+    };
+    let workers = config.workers;
+    let config = Arc::new(config);
+    let started = Instant::now();
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let variant_counts = Arc::new(VariantCounts::default());
+    let metrics = Arc::new(Metrics::default());
+    let rate_limiter = config
+        .rate_limit_per_minute
+        .map(|per_minute| Arc::new(RateLimiter::new(per_minute)));
+    let cloned = listing.clone();
+
+    // A new listing from the scraper is picked up within seconds, with the daily reload as a
+    // fallback.
+    if !embedded_scrape && config.bars_url.is_none() {
+        let watched = listing.clone();
+        thread::spawn(move || {
+            if let Err(err) = watch::watch_listing(&watched) {
+                error!(
+                    "Couldn't watch for new listings, checking every {:?} instead {:?}",
+                    LISTING_POLL_INTERVAL, err
+                );
+            }
+            let task = Interval::new(
+                Instant::now() + LISTING_POLL_INTERVAL,
+                LISTING_POLL_INTERVAL,
+            )
+            .for_each(move |_| {
+                watched.reload_if_changed();
+                Ok(())
+            })
+            .map_err(|e| panic!("{:?}", e));
+
+            tokio::run(task);
+        });
+    }
 
     thread::spawn(move || {
-        let task = Interval::new_interval(Duration::from_secs(60 * 60 * 24))
+        let task = Interval::new(first_refresh, refresh_interval)
             .for_each(move |_| {
-                cloned.reload_bars();
+                if embedded_scrape {
+                    scrape::scrape_into(&cloned);
+                } else {
+                    cloned.reload_bars();
+                }
                 Ok(())
             })
             .map_err(|e| panic!("{:?}", e));
@@ -117,20 +927,401 @@ fn main() {
         tokio::run(task);
     });
 
-    server::new(move || {
-        let cloned = state.clone();
-        App::with_state(cloned)
-            .middleware(AWSHTTPSWWWOnlyMiddleware)
-            .middleware(Logger::default())
+    let server = server::new(move || {
+        let app = App::with_state(AppState {
+            listing: listing.clone(),
+            config: config.clone(),
+            started,
+            variant_counts: variant_counts.clone(),
+            metrics: metrics.clone(),
+        });
+        let app = match config.redirect {
+            RedirectPolicy::Www => app.middleware(AWSHTTPSWWWOnlyMiddleware),
+            RedirectPolicy::Https => app.middleware(RequireHttps),
+            RedirectPolicy::None => app,
+        };
+        let app = app.middleware(Logger::default());
+        let app = match &rate_limiter {
+            Some(limiter) => app.middleware(RateLimit::new(limiter.clone())),
+            None => app,
+        };
+        app.middleware(Cors::new(config.cors_origins.clone()))
+            .middleware(ConcurrencyLimit::new(
+                config.max_in_flight,
+                in_flight.clone(),
+            ))
+            .middleware(RequestTimeout::new(config.request_timeout))
             .resource("/", |r| r.method(Method::GET).f(index))
             // Oops. We used to have a bad permanent redirect to // so we need to preserve this
             // for long enough until client caches expire.
             .resource("//", |r| r.method(Method::GET).f(index))
             .resource("/about", |r| r.method(Method::GET).f(about))
+            .resource("/favicon.ico", |r| r.method(Method::GET).f(favicon))
+            .resource("/healthz", |r| r.method(Method::GET).f(healthz))
+            .resource("/readyz", |r| r.method(Method::GET).f(readyz))
+            .resource("/metrics", |r| r.method(Method::GET).f(metrics))
             .resource("/locate", |r| r.method(Method::GET).with(locate))
+            .resource("/locate/list", |r| r.method(Method::GET).with(locate_list))
+            .resource("/top", |r| r.method(Method::GET).with(top))
+            .resource("/bars", |r| r.method(Method::GET).with(bars))
+            // Before `/bars/{id}`, which would otherwise take "bbox" as an id.
+            .resource("/bars/bbox", |r| r.method(Method::GET).with(bars_in_box))
+            .resource("/bars/{id}", |r| r.method(Method::GET).f(bar))
+            .resource("/bars/neighborhood/{neighborhood}", |r| {
+                r.method(Method::GET).f(neighborhood_bars)
+            })
+            .resource("/coverage", |r| r.method(Method::GET).with(coverage))
+            .resource("/b/{id}", |r| r.method(Method::GET).f(bar_page))
+            .resource("/sitemap.xml", |r| r.method(Method::GET).f(sitemap))
+            .resource("/admin/config", |r| r.method(Method::GET).f(admin_config))
+            .resource("/admin/experiment", |r| {
+                r.method(Method::GET).f(admin_experiment)
+            })
+            .default_resource(|r| r.f(not_found))
             .finish()
     })
-    .bind("0.0.0.0:1025")
-    .unwrap()
-    .run();
+    .workers(workers)
+    .bind(BIND_ADDRESS)
+    .unwrap();
+
+    info!("Serving with {} workers", workers);
+    server.run();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use actix_web::{Body, FromRequest};
+
+    /// A listing of two bars in Manhattan, only one of which has a neighborhood.
+    const LISTING: &str = r#"[
+        {"id": "a", "name": "A", "lat": 40.705, "lng": -74.0, "tips": ["Pickleback!"],
+         "neighborhood": "Financial District"},
+        {"id": "b", "name": "B", "lat": 40.710, "lng": -74.0, "tips": ["Pickleback?"]}
+    ]"#;
+
+    /// The state of a server with `config` which started `uptime` ago, serving the bars in
+    /// `listing` if it has loaded any.
+    fn app_state(config: Config, uptime: Duration, listing: Option<&str>) -> AppState {
+        let bars = BarListing::without_bars(&config);
+        if let Some(json) = listing {
+            bars.replace_bars_from_json(json.as_bytes()).unwrap();
+        }
+        AppState {
+            listing: Arc::new(bars),
+            config: Arc::new(config),
+            started: Instant::now() - uptime,
+            variant_counts: Arc::new(VariantCounts::default()),
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+
+    /// The response to `/locate` with `query` on a server with `config` serving `LISTING`.
+    fn locate_response(
+        config: Config,
+        query: &str,
+    ) -> Either<Json<LocateQueryResult>, HttpResponse> {
+        let state = app_state(config, Duration::from_secs(0), Some(LISTING));
+        let req = TestRequest::with_state(state)
+            .uri(&format!("/locate?{}", query))
+            .finish();
+        let query = Query::<LocateQuery>::extract(&req).unwrap();
+        locate(req, query)
+    }
+
+    /// The result of `/locate` with `query`, as `locate_response`.
+    fn located(config: Config, query: &str) -> LocateQueryResult {
+        match locate_response(config, query) {
+            Either::A(result) => result.into_inner(),
+            Either::B(resp) => panic!("unexpected {} response", resp.status()),
+        }
+    }
+
+    fn escalating(max_radius_miles: f64) -> Config {
+        Config {
+            radius_escalation: vec![1.0, 2.0, 3.0],
+            max_radius_miles,
+            ..Config::default()
+        }
+    }
+
+    /// The body of a response built in memory.
+    fn body(resp: &HttpResponse) -> &[u8] {
+        match resp.body() {
+            Body::Binary(binary) => binary.as_ref(),
+            _ => panic!("expected a body in memory"),
+        }
+    }
+
+    fn grace_period() -> Config {
+        Config {
+            startup_grace: Duration::from_secs(60),
+            ..Config::default()
+        }
+    }
+
+    fn readiness(state: AppState) -> StatusCode {
+        readyz(&TestRequest::with_state(state).finish()).status()
+    }
+
+    #[test]
+    fn locate_stops_at_the_first_radius_finding_a_bar() {
+        // A is about a third of a mile away, and B two thirds.
+        let result = located(escalating(9.0), "lat=40.7&lng=-74.0&radius=0.5");
+        assert_eq!((result.id.as_str(), result.radius), ("a", 0.5));
+
+        // A is just over a mile away, so the third radius is the first to reach it. B is in range
+        // too, by then, but further.
+        let result = located(
+            escalating(9.0),
+            "lat=40.69&lng=-74.0&radius=0.5&mode=nearest",
+        );
+        assert_eq!((result.id.as_str(), result.radius), ("a", 1.5));
+    }
+
+    #[test]
+    fn locate_escalation_stops_at_the_maximum_radius() {
+        let result = located(escalating(1.2), "lat=40.69&lng=-74.0&radius=0.5");
+        assert_eq!((result.id.as_str(), result.radius), ("", 1.2));
+    }
+
+    #[test]
+    fn locate_gives_the_neighborhood_of_the_bar() {
+        let result = located(Config::default(), "lat=40.705&lng=-74.0&mode=nearest");
+        assert_eq!(result.id, "a");
+        assert_eq!(result.neighborhood.as_deref(), Some("Financial District"));
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["neighborhood"], "Financial District");
+    }
+
+    #[test]
+    fn locate_leaves_out_a_missing_neighborhood() {
+        let result = located(Config::default(), "lat=40.71&lng=-74.0&mode=nearest");
+        assert_eq!(result.id, "b");
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("neighborhood").is_none(), "{}", json);
+    }
+
+    /// The envelope `/locate` wraps its result in, as `locate_response`.
+    fn enveloped(config: Config, query: &str) -> serde_json::Value {
+        match locate_response(config, query) {
+            Either::A(_) => panic!("expected an envelope"),
+            Either::B(resp) => {
+                assert_eq!(resp.status(), StatusCode::OK);
+                serde_json::from_slice(body(&resp)).unwrap()
+            }
+        }
+    }
+
+    #[test]
+    fn debug_locate_results_are_enveloped_with_the_query_and_timing() {
+        let envelope = enveloped(
+            Config::default(),
+            "lat=40.705&lng=-74.0&tips=50&mode=nearest&debug=1",
+        );
+        let mut fields: Vec<&str> = envelope
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort();
+        assert_eq!(fields, ["query", "result", "took_ms"]);
+        // The query as interpreted, with the tips asked for capped.
+        let query = &envelope["query"];
+        assert_eq!(
+            (query["lat"].as_f64(), query["lng"].as_f64()),
+            (Some(40.705), Some(-74.0))
+        );
+        assert_eq!(query["radius"], MAXIMUM_DITANCE_MILES);
+        assert_eq!(query["tips"], MAXIMUM_RESPONSE_TIPS);
+        assert_eq!(query["mode"], "nearest");
+        assert_eq!(envelope["result"]["id"], "a");
+        let took_ms = envelope["took_ms"].as_f64().unwrap();
+        assert!(took_ms >= 0.0 && took_ms < 10_000.0, "{}", took_ms);
+    }
+
+    #[test]
+    fn envelopes_can_be_on_by_default() {
+        let config = || Config {
+            response_envelope: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            enveloped(config(), "lat=40.705&lng=-74.0&mode=nearest")["result"]["name"],
+            "A"
+        );
+        match locate_response(config(), "lat=40.705&lng=-74.0&mode=nearest&debug=0") {
+            Either::A(result) => assert_eq!(result.into_inner().name, "A"),
+            Either::B(_) => panic!("expected the bare result"),
+        }
+        assert!(matches!(
+            locate_response(Config::default(), "lat=40.705&lng=-74.0&mode=nearest"),
+            Either::A(_)
+        ));
+    }
+
+    fn max_decimals(places: usize, excess_precision: ExcessPrecision) -> Config {
+        Config {
+            max_coordinate_decimals: Some(places),
+            excess_precision,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn coordinates_within_the_precision_are_accepted() {
+        let config = max_decimals(4, ExcessPrecision::Reject);
+        let result = located(config, "lat=40.7051&lng=-74.0&mode=nearest");
+        assert_eq!(result.id, "a");
+    }
+
+    #[test]
+    fn overly_precise_coordinates_can_be_rejected() {
+        let config = || max_decimals(4, ExcessPrecision::Reject);
+        for query in &["lat=40.70512&lng=-74.0", "lat=40.7051&lng=-74.00001"] {
+            match locate_response(config(), query) {
+                Either::A(_) => panic!("expected {} to be rejected", query),
+                Either::B(resp) => {
+                    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+                    let result: serde_json::Value = serde_json::from_slice(body(&resp)).unwrap();
+                    assert_eq!(result["max_decimals"], 4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn overly_precise_coordinates_can_be_rounded() {
+        let config = max_decimals(4, ExcessPrecision::Round);
+        let envelope = enveloped(
+            config,
+            "lat=40.705123456&lng=-74.000049&mode=nearest&debug=1",
+        );
+        assert_eq!(envelope["query"]["lat"], 40.7051);
+        assert_eq!(envelope["query"]["lng"], -74.0);
+        assert_eq!(envelope["result"]["id"], "a");
+    }
+
+    #[test]
+    fn attribution_is_given_with_the_comment_when_configured() {
+        let config = Config {
+            attribution: Some("via Foursquare".to_string()),
+            ..Config::default()
+        };
+        let result = located(config, "lat=40.705&lng=-74.0&mode=nearest");
+        assert_eq!(result.comment, "Pickleback!");
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["attribution"], "via Foursquare");
+
+        let result = located(Config::default(), "lat=40.705&lng=-74.0&mode=nearest");
+        let json = serde_json::to_value(&result).unwrap();
+        assert!(json.get("attribution").is_none(), "{}", json);
+    }
+
+    #[test]
+    fn attribution_is_left_out_when_no_bar_is_found() {
+        let config = Config {
+            attribution: Some("via Foursquare".to_string()),
+            ..Config::default()
+        };
+        let result = located(config, "lat=40.0&lng=-74.0");
+        assert_eq!(result.id, "");
+        assert_eq!(result.attribution, None);
+    }
+
+    #[test]
+    fn coverage_lists_each_neighborhood_with_its_bounding_box() {
+        let state = app_state(Config::default(), Duration::from_secs(0), Some(LISTING));
+        let req = TestRequest::with_state(state).uri("/coverage").finish();
+        let result = coverage(State::extract(&req)).into_inner();
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!([
+                {
+                    "neighborhood": "Financial District",
+                    "bars": 1,
+                    "bbox": [-74.0, 40.705, -74.0, 40.705]
+                },
+                {"neighborhood": "other", "bars": 1, "bbox": [-74.0, 40.71, -74.0, 40.71]}
+            ])
+        );
+    }
+
+    #[test]
+    fn embedded_about_page_is_served() {
+        let config = Config {
+            embedded_assets: true,
+            ..Config::default()
+        };
+        let state = app_state(config, Duration::from_secs(0), None);
+        match about(&TestRequest::with_state(state).finish()).unwrap() {
+            Either::A(resp) => {
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert_eq!(
+                    resp.headers().get("content-type").unwrap(),
+                    "text/html; charset=utf-8"
+                );
+                assert_eq!(body(&resp), EMBEDDED_ABOUT_HTML.as_bytes());
+            }
+            Either::B(_) => panic!("expected the embedded page"),
+        }
+    }
+
+    #[test]
+    fn about_page_is_served_from_disk_by_default() {
+        let state = app_state(Config::default(), Duration::from_secs(0), None);
+        match about(&TestRequest::with_state(state).finish()).unwrap() {
+            Either::A(_) => panic!("expected the page on disk"),
+            Either::B(file) => assert_eq!(file.path(), Path::new(ABOUT_HTML_PATH)),
+        }
+    }
+
+    #[test]
+    fn favicon_requests_get_an_empty_response() {
+        let state = app_state(Config::default(), Duration::from_secs(0), None);
+        let resp = favicon(&TestRequest::with_state(state).uri("/favicon.ico").finish());
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[test]
+    fn unknown_paths_get_a_json_404() {
+        let state = app_state(Config::default(), Duration::from_secs(0), None);
+        let resp = not_found(&TestRequest::with_state(state).uri("/nowhere?x=1").finish());
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let result: serde_json::Value = serde_json::from_slice(body(&resp)).unwrap();
+        assert_eq!(
+            result,
+            serde_json::json!({"error": "Not found", "path": "/nowhere"})
+        );
+    }
+
+    #[test]
+    fn server_is_live_before_it_is_ready() {
+        let state = app_state(grace_period(), Duration::from_secs(0), None);
+        let resp = healthz(&TestRequest::with_state(state).finish());
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn server_is_not_ready_before_the_listing_loads() {
+        let state = app_state(grace_period(), Duration::from_secs(120), None);
+        assert_eq!(readiness(state), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn server_is_not_ready_during_the_grace_period() {
+        let state = app_state(grace_period(), Duration::from_secs(30), Some(LISTING));
+        assert_eq!(readiness(state), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn server_is_ready_once_loaded_after_the_grace_period() {
+        let state = app_state(grace_period(), Duration::from_secs(61), Some(LISTING));
+        assert_eq!(readiness(state), StatusCode::OK);
+        let state = app_state(Config::default(), Duration::from_secs(0), Some(LISTING));
+        assert_eq!(readiness(state), StatusCode::OK);
+    }
 }
@@ -0,0 +1,178 @@
+//! Operational metrics, served in the Prometheus text format for monitoring to scrape.
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Upper bounds in seconds of the buckets `/locate` latencies are counted in.
+const LATENCY_BUCKETS_SECS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Counts of observations at or below each of `LATENCY_BUCKETS_SECS`, plus their total.
+struct Histogram {
+    /// Observations in each bucket alone, not cumulative, followed by the ones above every bucket.
+    buckets: Vec<AtomicU64>,
+    /// Sum of every observation in microseconds, so it can be kept in an integer.
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: (0..=LATENCY_BUCKETS_SECS.len())
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS_SECS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(LATENCY_BUCKETS_SECS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Write the histogram as `name`, with cumulative buckets as Prometheus expects.
+    fn render(&self, out: &mut String, name: &str) {
+        let mut count = 0;
+        for (bucket, bound) in self.buckets.iter().zip(LATENCY_BUCKETS_SECS) {
+            count += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        count += self.buckets[LATENCY_BUCKETS_SECS.len()].load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum_secs = self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "{}_sum {}", name, sum_secs);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// Metrics about `/locate` since startup, shared by every worker.
+pub struct Metrics {
+    locate_requests: AtomicU64,
+    locate_no_bar: AtomicU64,
+    locate_latency: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            locate_requests: AtomicU64::new(0),
+            locate_no_bar: AtomicU64::new(0),
+            locate_latency: Histogram::new(),
+        }
+    }
+}
+
+impl Metrics {
+    /// Count a `/locate` request, including ones rejected as invalid.
+    pub fn record_locate_request(&self) {
+        self.locate_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a `/locate` request which was answered after `elapsed`, `found` saying whether it
+    /// found a bar.
+    pub fn record_locate_answer(&self, elapsed: Duration, found: bool) {
+        if !found {
+            self.locate_no_bar.fetch_add(1, Ordering::Relaxed);
+        }
+        self.locate_latency.observe(elapsed);
+    }
+
+    /// The metrics in the Prometheus text exposition format, along with the number of bars in the
+    /// loaded listing.
+    pub fn render(&self, bars_loaded: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "# HELP pickletrack_locate_requests_total Requests to /locate, including invalid ones."
+        );
+        let _ = writeln!(out, "# TYPE pickletrack_locate_requests_total counter");
+        let _ = writeln!(
+            out,
+            "pickletrack_locate_requests_total {}",
+            self.locate_requests.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP pickletrack_locate_no_bar_total Answered /locate requests with no bar nearby."
+        );
+        let _ = writeln!(out, "# TYPE pickletrack_locate_no_bar_total counter");
+        let _ = writeln!(
+            out,
+            "pickletrack_locate_no_bar_total {}",
+            self.locate_no_bar.load(Ordering::Relaxed)
+        );
+        let _ = writeln!(
+            out,
+            "# HELP pickletrack_locate_duration_seconds Time taken to answer /locate requests."
+        );
+        let _ = writeln!(out, "# TYPE pickletrack_locate_duration_seconds histogram");
+        self.locate_latency
+            .render(&mut out, "pickletrack_locate_duration_seconds");
+        let _ = writeln!(
+            out,
+            "# HELP pickletrack_bars_loaded Bars in the loaded listing."
+        );
+        let _ = writeln!(out, "# TYPE pickletrack_bars_loaded gauge");
+        let _ = writeln!(out, "pickletrack_bars_loaded {}", bars_loaded);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The value of the sample `name` in `rendered`.
+    fn sample(rendered: &str, name: &str) -> String {
+        rendered
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no sample {} in\n{}", name, rendered))
+            .to_string()
+    }
+
+    #[test]
+    fn counters_start_at_zero() {
+        let rendered = Metrics::default().render(0);
+        assert_eq!(sample(&rendered, "pickletrack_locate_requests_total"), "0");
+        assert_eq!(sample(&rendered, "pickletrack_locate_no_bar_total"), "0");
+        assert_eq!(
+            sample(&rendered, "pickletrack_locate_duration_seconds_count"),
+            "0"
+        );
+    }
+
+    #[test]
+    fn counters_increment_with_each_request() {
+        let metrics = Metrics::default();
+        for _ in 0..3 {
+            metrics.record_locate_request();
+        }
+        metrics.record_locate_answer(Duration::from_micros(800), true);
+        metrics.record_locate_answer(Duration::from_millis(20), false);
+        metrics.record_locate_answer(Duration::from_secs(5), true);
+
+        let rendered = metrics.render(42);
+        assert_eq!(sample(&rendered, "pickletrack_locate_requests_total"), "3");
+        assert_eq!(sample(&rendered, "pickletrack_locate_no_bar_total"), "1");
+        assert_eq!(sample(&rendered, "pickletrack_bars_loaded"), "42");
+
+        // Buckets are cumulative, and the slowest request is only counted in the last.
+        let histogram = "pickletrack_locate_duration_seconds";
+        let bucket = |le| sample(&rendered, &format!("{}_bucket{{le=\"{}\"}}", histogram, le));
+        assert_eq!(bucket("0.001"), "1");
+        assert_eq!(bucket("0.01"), "1");
+        assert_eq!(bucket("0.025"), "2");
+        assert_eq!(bucket("2.5"), "2");
+        assert_eq!(bucket("+Inf"), "3");
+        assert_eq!(sample(&rendered, &format!("{}_count", histogram)), "3");
+        assert_eq!(sample(&rendered, &format!("{}_sum", histogram)), "5.0208");
+    }
+}
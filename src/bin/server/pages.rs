@@ -0,0 +1,143 @@
+//! Pages rendered from the listing: one for each bar, and a sitemap listing them for search
+//! engines.
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+
+use crate::barlisting::BarDetails;
+
+/// Escape the characters which are special in HTML and XML text and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The path of a bar's page.
+pub fn bar_page_path(id: &str) -> String {
+    format!("/b/{}", id)
+}
+
+/// A page describing a bar and what people say about its picklebacks.
+pub fn bar_page(bar: &BarDetails) -> String {
+    let mut page = String::new();
+    page.push_str("<!doctype html>\n<html>\n\t<head>\n\t\t<meta charset=\"utf-8\"/>\n");
+    page.push_str(&format!(
+        "\t\t<title>Pickletrack - {}</title>\n",
+        escape(&bar.name)
+    ));
+    page.push_str(
+        "\t\t<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\t</head>\n",
+    );
+    page.push_str("\t<body>\n");
+    page.push_str(&format!("\t\t<h1>{}</h1>\n", escape(&bar.name)));
+    if let Some(ref neighborhood) = bar.neighborhood {
+        page.push_str(&format!("\t\t<p>{}</p>\n", escape(neighborhood)));
+    }
+    page.push_str("\t\t<ul>\n");
+    for tip in &bar.tips {
+        page.push_str(&format!("\t\t\t<li>{}</li>\n", escape(tip)));
+    }
+    page.push_str("\t\t</ul>\n");
+    if let Some(ref fsq_url) = bar.fsq_url {
+        page.push_str(&format!(
+            "\t\t<p><a href=\"{}\">See it on Foursquare</a></p>\n",
+            escape(fsq_url)
+        ));
+    }
+    page.push_str("\t\t<p><a href=\"/\">Find a pickleback near you</a></p>\n");
+    page.push_str("\t</body>\n</html>\n");
+    page
+}
+
+/// A sitemap of the static pages and every bar's page, for the site at `base_url`.
+///
+/// Bar pages are marked as last modified when the listing was generated, if that is known.
+pub fn sitemap(base_url: &str, bar_ids: &[String], generated: Option<SystemTime>) -> String {
+    let lastmod = generated.map(|generated| {
+        format!(
+            "<lastmod>{}</lastmod>",
+            DateTime::<Utc>::from(generated).format("%Y-%m-%d")
+        )
+    });
+
+    let mut sitemap = String::new();
+    sitemap.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    sitemap.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for path in &["/", "/about"] {
+        sitemap.push_str(&format!(
+            "  <url><loc>{}{}</loc></url>\n",
+            escape(base_url),
+            path
+        ));
+    }
+    for id in bar_ids {
+        sitemap.push_str(&format!(
+            "  <url><loc>{}{}</loc>{}</url>\n",
+            escape(base_url),
+            escape(&bar_page_path(id)),
+            lastmod.as_ref().map_or("", String::as_str)
+        ));
+    }
+    sitemap.push_str("</urlset>\n");
+    sitemap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// The addresses of the pages in `sitemap`, each with its last modified date if it has one.
+    fn entries(sitemap: &str) -> Vec<(&str, Option<&str>)> {
+        sitemap
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("<url><loc>"))
+            .map(|entry| {
+                let (loc, rest) = entry.split_at(entry.find("</loc>").unwrap());
+                let lastmod = rest
+                    .strip_prefix("</loc><lastmod>")
+                    .map(|rest| &rest[..rest.find("</lastmod>").unwrap()]);
+                (loc, lastmod)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sitemap_has_an_entry_per_bar() {
+        let generated = UNIX_EPOCH + Duration::from_secs(1_554_076_800);
+        let ids = vec!["4a9b0e2c".to_string(), "5b1a&7".to_string()];
+        let sitemap = sitemap("https://www.pickletrack.com", &ids, Some(generated));
+        assert!(sitemap.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert_eq!(
+            entries(&sitemap),
+            vec![
+                ("https://www.pickletrack.com/", None),
+                ("https://www.pickletrack.com/about", None),
+                ("https://www.pickletrack.com/b/4a9b0e2c", Some("2019-04-01")),
+                (
+                    "https://www.pickletrack.com/b/5b1a&amp;7",
+                    Some("2019-04-01")
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn sitemap_without_a_generation_date_has_no_lastmod() {
+        let sitemap = sitemap("https://www.pickletrack.com", &["a".to_string()], None);
+        assert_eq!(
+            entries(&sitemap).last(),
+            Some(&("https://www.pickletrack.com/b/a", None))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+//! Middleware limiting how often each client may call the locate endpoints.
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::{Middleware, Started};
+use actix_web::{HttpRequest, HttpResponse, Result};
+use log::warn;
+
+/// Paths whose requests are rate limited.
+const LIMITED_PATHS: &[&str] = &["/locate", "/locate/list"];
+
+/// How often buckets of clients which have stopped making requests are dropped.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Requests a client may make right away, refilled continuously.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets for every client seen recently, shared by every worker.
+pub struct RateLimiter {
+    /// Most requests a client may make in a burst, and how many are refilled each minute.
+    per_minute: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    last_pruned: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: f64::from(per_minute),
+            buckets: Mutex::new(HashMap::new()),
+            last_pruned: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Take a token from `client`'s bucket at `now`. If it is empty, returns how long until the
+    /// next token.
+    fn acquire(&self, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        self.prune(now);
+        let per_second = self.per_minute / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.per_minute,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(self.per_minute);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+
+    /// Drop the buckets which have refilled completely, since they're the same as a new one, at
+    /// most once every `PRUNE_INTERVAL`.
+    fn prune(&self, now: Instant) {
+        let mut last_pruned = self.last_pruned.lock().unwrap();
+        if now.duration_since(*last_pruned) < PRUNE_INTERVAL {
+            return;
+        }
+        *last_pruned = now;
+        drop(last_pruned);
+
+        let per_second = self.per_minute / 60.0;
+        self.buckets.lock().unwrap().retain(|_, bucket| {
+            let refilled = now.duration_since(bucket.updated).as_secs_f64() * per_second;
+            bucket.tokens + refilled < self.per_minute
+        });
+    }
+}
+
+/// The address of the client which made `req`.
+///
+/// Behind the load balancer every connection comes from it, so the client is taken from the last
+/// address in X-Forwarded-For, which the load balancer appends. Earlier addresses are whatever the
+/// client sent, so can't be trusted. Requests without the header are taken to come straight from
+/// the client.
+fn client_ip<S>(req: &HttpRequest<S>) -> Option<IpAddr> {
+    let forwarded = req
+        .headers()
+        .get("x-forwarded-for")
+        .and_then(|forwarded| forwarded.to_str().ok())
+        .and_then(|forwarded| forwarded.rsplit(',').next())
+        .and_then(|client| client.trim().parse().ok());
+    forwarded.or_else(|| req.peer_addr().map(|addr| addr.ip()))
+}
+
+/// This middleware answers requests to the locate endpoints with a 429 once their client has used
+/// up its allowance, so they can't be used to cheaply enumerate every bar.
+pub struct RateLimit {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimit {
+    /// Create a limit using the buckets in `limiter`, which should be the same for every worker.
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        Self { limiter }
+    }
+}
+
+impl<S> Middleware<S> for RateLimit {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        if !LIMITED_PATHS.contains(&req.path()) {
+            return Ok(Started::Done);
+        }
+        let client = match client_ip(req) {
+            Some(client) => client,
+            None => return Ok(Started::Done),
+        };
+        match self.limiter.acquire(client, Instant::now()) {
+            Ok(()) => Ok(Started::Done),
+            Err(retry_after) => {
+                warn!("Rate limiting {} requesting {}", client, req.uri());
+                Ok(Started::Response(
+                    HttpResponse::TooManyRequests()
+                        .header(RETRY_AFTER, retry_after.as_secs_f64().ceil().to_string())
+                        .body("Too many requests"),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn each_client_is_limited_separately() {
+        let limiter = RateLimiter::new(3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire(ip("203.0.113.7"), now), Ok(()));
+        }
+        // A token comes back every 20 seconds.
+        assert_eq!(
+            limiter.acquire(ip("203.0.113.7"), now),
+            Err(Duration::from_secs(20))
+        );
+        assert_eq!(limiter.acquire(ip("198.51.100.1"), now), Ok(()));
+    }
+
+    #[test]
+    fn allowance_refills_over_the_window() {
+        let limiter = RateLimiter::new(6);
+        let client = ip("203.0.113.7");
+        let start = Instant::now();
+        for _ in 0..6 {
+            assert_eq!(limiter.acquire(client, start), Ok(()));
+        }
+        let later = |secs| start + Duration::from_secs(secs);
+        assert!(limiter.acquire(client, later(5)).is_err());
+        // One token back after ten seconds.
+        assert_eq!(limiter.acquire(client, later(10)), Ok(()));
+        assert!(limiter.acquire(client, later(10)).is_err());
+
+        // After a whole minute the full burst is allowed again, but no more.
+        for _ in 0..6 {
+            assert_eq!(limiter.acquire(client, later(120)), Ok(()));
+        }
+        assert!(limiter.acquire(client, later(120)).is_err());
+    }
+
+    #[test]
+    fn refilled_buckets_are_pruned() {
+        // A token comes back every 30 seconds.
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+        limiter.acquire(ip("203.0.113.7"), start).unwrap();
+        limiter.acquire(ip("198.51.100.1"), later(50)).unwrap();
+        limiter.acquire(ip("198.51.100.1"), later(50)).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+
+        // By the time buckets are next pruned, the first client's bucket is full again but the
+        // second client's isn't.
+        limiter.acquire(ip("192.0.2.1"), later(61)).unwrap();
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.contains_key(&ip("203.0.113.7")));
+        assert!(buckets.contains_key(&ip("198.51.100.1")));
+        assert!(buckets.contains_key(&ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn client_is_the_last_forwarded_address() {
+        let req = TestRequest::with_header("x-forwarded-for", "10.0.0.1, 203.0.113.7").finish();
+        assert_eq!(client_ip(&req), Some(ip("203.0.113.7")));
+        let garbled = TestRequest::with_header("x-forwarded-for", "10.0.0.1, nonsense").finish();
+        assert_eq!(client_ip(&garbled), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Fetching the bar listing over HTTP, for deployments which keep it in object storage rather than
+//! on the server's disk.
+use std::io::Read;
+use std::time::{Duration, SystemTime};
+
+use chrono::DateTime;
+use reqwest::header::LAST_MODIFIED;
+use reqwest::Client;
+
+/// Time allowed for fetching the listing, including reading the whole body.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A listing URL fit for logs and `/admin/config`, without its query. Object storage URLs are
+/// often signed, with the signature in the query.
+pub fn display_url(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+/// Fetch the listing at `url`, returning its body and when it was last modified, if the server
+/// says.
+pub fn fetch_listing(url: &str) -> Result<(Vec<u8>, Option<SystemTime>), String> {
+    // reqwest's own error descriptions include the URL, signature and all, so only their cause is
+    // reported.
+    let describe = |err: reqwest::Error| {
+        format!(
+            "Couldn't fetch bar listing from {}: {}",
+            display_url(url),
+            err.get_ref()
+                .map_or_else(|| "request failed".to_string(), |inner| inner.to_string())
+        )
+    };
+    let client = Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(describe)?;
+    let mut response = client.get(url).send().map_err(describe)?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Couldn't fetch bar listing from {}: responded {}",
+            display_url(url),
+            response.status()
+        ));
+    }
+    let modified = response
+        .headers()
+        .get(LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(SystemTime::from);
+    let mut body = Vec::new();
+    response.read_to_end(&mut body).map_err(|err| {
+        format!(
+            "Couldn't read bar listing from {}: {}",
+            display_url(url),
+            err
+        )
+    })?;
+    Ok((body, modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Serve a single request on a local port with `response`, returning the URL to request, with
+    /// a signature in its query as object storage URLs have.
+    fn serve_once(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            // Read the request up to the blank line ending its headers.
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{}/bars.json?signature=secret", address)
+    }
+
+    #[test]
+    fn listing_is_fetched_with_its_modification_time() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\n\
+             Last-Modified: Tue, 15 Nov 1994 12:45:26 GMT\r\n\
+             Content-Length: 2\r\n\
+             Connection: close\r\n\r\n[]",
+        );
+        let (body, modified) = fetch_listing(&url).unwrap();
+        assert_eq!(body, b"[]");
+        assert_eq!(
+            modified,
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784_903_526))
+        );
+    }
+
+    #[test]
+    fn error_status_is_reported_without_the_query() {
+        let url =
+            serve_once("HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let err = fetch_listing(&url).unwrap_err();
+        assert!(err.contains("responded 403 Forbidden"), "{}", err);
+        assert!(err.contains("/bars.json"), "{}", err);
+        assert!(!err.contains("secret"), "{}", err);
+    }
+
+    #[test]
+    fn unreachable_server_is_reported_without_the_query() {
+        // Nothing listens on the port once the listener is dropped.
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{}/bars.json?signature=secret", address);
+        let err = fetch_listing(&url).unwrap_err();
+        assert!(
+            err.starts_with("Couldn't fetch bar listing from"),
+            "{}",
+            err
+        );
+        assert!(!err.contains("secret"), "{}", err);
+    }
+}
@@ -0,0 +1,66 @@
+//! Running the scraper from the server, for single box deployments which would rather not run a
+//! separate scrape job and coordinate with it through the listing symlink.
+//!
+//! The scrape runs in-process through the scraper library, and the listing it produces replaces the
+//! served one in memory, without being written to disk.
+use log::{error, info};
+use pickletrack::{Bar, ScrapeOptions};
+
+use crate::barlisting::BarListing;
+
+/// Run a scrape with the scraper's default options and serve the resulting listing in place of the
+/// current one.
+///
+/// The scrape uses the server's environment, including the Foursquare credentials. If it fails,
+/// this is logged and the current listing is kept.
+pub fn scrape_into(listing: &BarListing) {
+    info!("Running embedded scrape");
+    match pickletrack::scrape_listing(&ScrapeOptions::default()) {
+        Ok(bars) => serve_scraped(listing, &bars),
+        Err(err) => error!("Embedded scrape failed: {}", err),
+    }
+}
+
+/// Serve `bars`, as scraped, in place of the current listing.
+///
+/// The server reads listings into its own form, so they are handed over as the JSON the scraper
+/// would have written.
+fn serve_scraped(listing: &BarListing, bars: &[Bar]) {
+    let replaced = serde_json::to_vec(bars)
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|json| {
+            listing
+                .replace_bars_from_json(&json)
+                .map_err(|err| format!("{:?}", err))
+        });
+    match replaced {
+        Ok(count) => info!("Embedded scrape replaced the listing with {} bars", count),
+        Err(err) => error!("Couldn't serve embedded scrape output {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn scraped_bars_replace_the_served_listing() {
+        let listing = BarListing::without_bars(&Config::default());
+        assert!(!listing.is_loaded());
+
+        let bars: Vec<Bar> = serde_json::from_str(
+            r#"[
+                {"id": "a", "name": "A", "lat": 40.7, "lng": -74.0, "tips": ["Pickleback!"]},
+                {"id": "b", "name": "B", "lat": 40.8, "lng": -73.9, "tips": ["Pickleback?"]}
+            ]"#,
+        )
+        .unwrap();
+        serve_scraped(&listing, &bars);
+        assert!(listing.is_loaded());
+        assert_eq!(listing.bar_ids(), vec!["a", "b"]);
+
+        serve_scraped(&listing, &bars[1..]);
+        assert_eq!(listing.bar_ids(), vec!["b"]);
+    }
+}
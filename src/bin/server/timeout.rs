@@ -0,0 +1,130 @@
+//! Middleware bounding how long a request may take.
+use std::time::{Duration, Instant};
+
+use actix_web::middleware::{Middleware, Response, Started};
+use actix_web::{HttpRequest, HttpResponse, Result};
+use log::warn;
+
+use crate::deadline::Deadline;
+
+/// When a request started being handled, stored in the request extensions.
+struct RequestStarted(Instant);
+
+/// The deadline `req` must be answered by, which handlers working through many bars should check
+/// as they go. Requests the middleware didn't see have none.
+pub fn deadline<S>(req: &HttpRequest<S>) -> Deadline {
+    req.extensions()
+        .get::<Deadline>()
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// The response to a request whose handler gave up at its deadline.
+pub fn timed_out<S>(req: &HttpRequest<S>) -> HttpResponse {
+    warn!("Request for {} gave up at its deadline", req.uri());
+    HttpResponse::ServiceUnavailable().body("Request timed out")
+}
+
+/// This middleware gives every request a deadline the timeout after it starts, and replaces the
+/// response of any request which took longer than that with a 503, logging the slow path.
+///
+/// Our handlers are synchronous, so they can't be interrupted from outside. The ones whose work
+/// grows with the request check their deadline as they go and answer with `timed_out` once it has
+/// passed. Replacing slow responses catches any others, which run to completion before their result
+/// is discarded.
+pub struct RequestTimeout {
+    timeout: Duration,
+}
+
+impl RequestTimeout {
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl<S> Middleware<S> for RequestTimeout {
+    fn start(&self, req: &HttpRequest<S>) -> Result<Started> {
+        req.extensions_mut().insert(RequestStarted(Instant::now()));
+        req.extensions_mut().insert(Deadline::after(self.timeout));
+        Ok(Started::Done)
+    }
+
+    fn response(&self, req: &HttpRequest<S>, resp: HttpResponse) -> Result<Response> {
+        let elapsed = match req.extensions().get::<RequestStarted>() {
+            Some(RequestStarted(started)) => started.elapsed(),
+            None => return Ok(Response::Done(resp)),
+        };
+
+        if elapsed <= self.timeout {
+            return Ok(Response::Done(resp));
+        }
+
+        warn!(
+            "Request for {} took {:?}, exceeding the {:?} timeout",
+            req.uri(),
+            elapsed,
+            self.timeout
+        );
+        Ok(Response::Done(
+            HttpResponse::ServiceUnavailable().body("Request timed out"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::TestRequest;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_millis(50);
+
+    /// Handle `req` through the middleware with `handler`, giving the status of the response.
+    fn handle(
+        req: &HttpRequest<()>,
+        handler: impl Fn(&HttpRequest<()>) -> HttpResponse,
+    ) -> StatusCode {
+        let timeout = RequestTimeout::new(TIMEOUT);
+        match timeout.start(req).unwrap() {
+            Started::Done => {}
+            _ => panic!("Requests start without waiting"),
+        }
+        match timeout.response(req, handler(req)).unwrap() {
+            Response::Done(resp) => resp.status(),
+            _ => panic!("Responses are checked without waiting"),
+        }
+    }
+
+    #[test]
+    fn slow_responses_are_replaced_with_a_503() {
+        let req = TestRequest::with_uri("/coverage").finish();
+        let status = handle(&req, |_| {
+            thread::sleep(TIMEOUT * 2);
+            HttpResponse::Ok().finish()
+        });
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn quick_responses_are_kept() {
+        let req = TestRequest::with_uri("/coverage").finish();
+        let status = handle(&req, |_| HttpResponse::Ok().finish());
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn handlers_can_give_up_at_the_deadline() {
+        let req = TestRequest::with_uri("/locate/list").finish();
+        assert!(deadline(&req).check().is_ok());
+        let status = handle(&req, |req| {
+            assert!(deadline(req).check().is_ok());
+            thread::sleep(TIMEOUT * 2);
+            match deadline(req).check() {
+                Ok(()) => HttpResponse::Ok().finish(),
+                Err(_) => timed_out(req),
+            }
+        });
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,82 @@
+//! Units of distance used in API responses.
+//!
+//! Distances are computed internally in miles. They are only converted to the unit the client
+//! asked for when formatting a response.
+use serde::{Deserialize, Serialize};
+
+const METERS_PER_MILE: f64 = 1609.344;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Miles(pub f64);
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Meters(pub f64);
+
+impl From<Miles> for Meters {
+    fn from(miles: Miles) -> Self {
+        Meters(miles.0 * METERS_PER_MILE)
+    }
+}
+
+impl From<Meters> for Miles {
+    fn from(meters: Meters) -> Self {
+        Miles(meters.0 / METERS_PER_MILE)
+    }
+}
+
+/// A unit a client can ask for distances to be reported in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum DistanceUnit {
+    #[default]
+    #[serde(rename = "mi")]
+    Miles,
+    #[serde(rename = "km")]
+    Kilometers,
+}
+
+impl DistanceUnit {
+    /// Read a distance given in this unit.
+    pub fn to_miles(self, distance: f64) -> Miles {
+        match self {
+            DistanceUnit::Miles => Miles(distance),
+            DistanceUnit::Kilometers => Miles::from(Meters(distance * 1000.0)),
+        }
+    }
+
+    /// Express a distance in this unit.
+    pub fn convert(self, distance: Miles) -> f64 {
+        match self {
+            DistanceUnit::Miles => distance.0,
+            DistanceUnit::Kilometers => Meters::from(distance).0 / 1000.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distances_are_converted_to_kilometers_and_back() {
+        // A mile is exactly 1.609344 km.
+        assert!((DistanceUnit::Kilometers.convert(Miles(5.0)) - 8.04672).abs() < 1e-9);
+        assert!((DistanceUnit::Kilometers.to_miles(8.04672).0 - 5.0).abs() < 1e-9);
+        // A marathon is 42.195 km, or 26 miles 385 yards to the nearest yard.
+        let marathon = Miles(26.0 + 385.0 / 1760.0);
+        assert!((DistanceUnit::Kilometers.convert(marathon) - 42.195).abs() < 0.001);
+        assert_eq!(DistanceUnit::Miles.convert(marathon), marathon.0);
+        assert_eq!(DistanceUnit::Miles.to_miles(3.0), Miles(3.0));
+    }
+
+    #[test]
+    fn units_are_named_as_clients_give_them() {
+        assert_eq!(
+            serde_json::from_str::<DistanceUnit>("\"km\"").unwrap(),
+            DistanceUnit::Kilometers
+        );
+        assert_eq!(
+            serde_json::to_string(&DistanceUnit::default()).unwrap(),
+            "\"mi\""
+        );
+    }
+}
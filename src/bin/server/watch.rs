@@ -0,0 +1,97 @@
+//! Watching for new listings, so one the scraper publishes is served within moments rather than at
+//! the next daily reload.
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use log::{info, warn};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::barlisting::{BarListing, BARS_FILE_PATH};
+
+/// How long changes are gathered before acting on them, so a listing is read once it has been
+/// written rather than part way through.
+const DEBOUNCE_DELAY: Duration = Duration::from_secs(2);
+
+/// Reload `listing` whenever the scraper publishes a new one. This blocks, so is run on a thread of
+/// its own, and only returns if the directory can't be watched.
+///
+/// The directory holding the listing symlink is watched rather than the symlink or its target: the
+/// scraper publishes by swapping in a new symlink, after which a watch on the old one would follow
+/// a listing which is no longer current. Any change in the directory prompts
+/// `BarListing::reload_if_changed`, which only reloads once the symlink points somewhere new or its
+/// target has been modified.
+pub fn watch_listing(listing: &BarListing) -> notify::Result<()> {
+    let dir = Path::new(BARS_FILE_PATH)
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+    info!("Watching {} for new listings", dir.display());
+    watch_dir(dir, || listing.reload_if_changed())
+}
+
+/// Call `on_change` after each burst of changes to the entries of `dir`, until the watch fails.
+fn watch_dir(dir: &Path, mut on_change: impl FnMut()) -> notify::Result<()> {
+    let (sender, receiver) = channel();
+    let mut watcher: RecommendedWatcher = Watcher::new(sender, DEBOUNCE_DELAY)?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    for event in receiver {
+        match event {
+            // Followed by the debounced event once the change settles.
+            DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => {}
+            DebouncedEvent::Error(err, path) => warn!("Error watching {:?} {:?}", path, err),
+            _ => on_change(),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread;
+
+    /// A directory to watch, named for the test using it, and the changes seen in it.
+    fn watched_dir(name: &str) -> (std::path::PathBuf, Receiver<()>) {
+        let dir = std::env::temp_dir().join(format!("pickletrack-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let watched = dir.clone();
+        thread::spawn(move || watch_dir(&watched, || sender.send(()).unwrap()).unwrap());
+        // Give the watch time to start before anything changes.
+        thread::sleep(Duration::from_millis(500));
+        (dir, receiver)
+    }
+
+    fn changed(receiver: &Receiver<()>) -> bool {
+        receiver.recv_timeout(Duration::from_secs(10)).is_ok()
+    }
+
+    #[test]
+    fn new_listing_is_noticed() {
+        let (dir, receiver) = watched_dir("watch-write");
+        fs::write(dir.join("20190401.json"), "[]").unwrap();
+        assert!(changed(&receiver));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn swapped_symlink_is_noticed() {
+        use std::os::unix::fs::symlink;
+
+        let (dir, receiver) = watched_dir("watch-swap");
+        fs::write(dir.join("old.json"), "[]").unwrap();
+        fs::write(dir.join("new.json"), "[]").unwrap();
+        symlink(dir.join("old.json"), dir.join("current.json")).unwrap();
+        assert!(changed(&receiver));
+        while receiver.try_recv().is_ok() {}
+
+        // Published as the scraper does, by renaming a new symlink over the current one.
+        symlink(dir.join("new.json"), dir.join("current.json.new")).unwrap();
+        fs::rename(dir.join("current.json.new"), dir.join("current.json")).unwrap();
+        assert!(changed(&receiver));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+//! Polygon boundaries, such as a borough outline, used to restrict the venues we keep.
+//!
+//! Boundaries are read from GeoJSON. A file may hold a `Polygon` or `MultiPolygon` geometry, a
+//! `Feature` wrapping one, or a `FeatureCollection` of them, in which case the boundary is the union
+//! of every polygon in the collection.
+use std::fs::File;
+use std::io;
+
+use serde_json::Value;
+
+use crate::{BoundingBox, LatLong};
+
+/// A single polygon, made up of an outer ring and any number of holes.
+struct Polygon {
+    exterior: Vec<LatLong>,
+    holes: Vec<Vec<LatLong>>,
+}
+
+pub struct Boundary {
+    polygons: Vec<Polygon>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Parse a GeoJSON linear ring, a list of `[longitude, latitude]` positions.
+fn parse_ring(value: &Value) -> io::Result<Vec<LatLong>> {
+    let positions = value
+        .as_array()
+        .ok_or_else(|| invalid("Expected an array of positions"))?;
+    positions
+        .iter()
+        .map(|position| match position.as_array().map(Vec::as_slice) {
+            Some([longitude, latitude, ..]) => match (longitude.as_f64(), latitude.as_f64()) {
+                (Some(longitude), Some(latitude)) => Ok(LatLong {
+                    latitude,
+                    longitude,
+                }),
+                _ => Err(invalid("Expected numeric coordinates")),
+            },
+            _ => Err(invalid("Expected a [longitude, latitude] position")),
+        })
+        .collect()
+}
+
+/// Parse the coordinates of a GeoJSON `Polygon`, a list of rings where the first is the exterior.
+fn parse_polygon(value: &Value) -> io::Result<Polygon> {
+    let rings = value
+        .as_array()
+        .ok_or_else(|| invalid("Expected an array of rings"))?;
+    let mut rings = rings
+        .iter()
+        .map(parse_ring)
+        .collect::<io::Result<Vec<_>>>()?;
+    if rings.is_empty() {
+        return Err(invalid("Polygon has no exterior ring"));
+    }
+    let exterior = rings.remove(0);
+    if exterior.len() < 3 {
+        return Err(invalid("Polygon exterior ring has too few positions"));
+    }
+    Ok(Polygon {
+        exterior,
+        holes: rings,
+    })
+}
+
+/// Collect every polygon in a GeoJSON object.
+fn collect_polygons(value: &Value, polygons: &mut Vec<Polygon>) -> io::Result<()> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            let features = value["features"]
+                .as_array()
+                .ok_or_else(|| invalid("FeatureCollection has no features"))?;
+            for feature in features {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        Some("Feature") => collect_polygons(&value["geometry"], polygons)?,
+        Some("Polygon") => polygons.push(parse_polygon(&value["coordinates"])?),
+        Some("MultiPolygon") => {
+            let members = value["coordinates"]
+                .as_array()
+                .ok_or_else(|| invalid("MultiPolygon has no coordinates"))?;
+            for member in members {
+                polygons.push(parse_polygon(member)?);
+            }
+        }
+        _ => {
+            return Err(invalid(
+                "Expected a GeoJSON polygon, feature or feature collection",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Whether a point lies inside a ring, by counting how many edges a ray cast eastward crosses.
+fn ring_contains(ring: &[LatLong], point: &LatLong) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (a, b) = (&ring[i], &ring[j]);
+        if (a.latitude > point.latitude) != (b.latitude > point.latitude) {
+            let crossing_longitude = a.longitude
+                + (point.latitude - a.latitude) / (b.latitude - a.latitude)
+                    * (b.longitude - a.longitude);
+            if point.longitude < crossing_longitude {
+                inside = !inside;
+            }
+        }
+        j = i;
+    }
+    inside
+}
+
+impl Boundary {
+    /// Load a boundary from a GeoJSON file.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let value: Value = serde_json::from_reader(File::open(path)?)?;
+        let mut polygons = Vec::new();
+        collect_polygons(&value, &mut polygons)?;
+        if polygons.is_empty() {
+            return Err(invalid("Boundary contains no polygons"));
+        }
+        Ok(Self { polygons })
+    }
+
+    /// Whether a point lies within the boundary.
+    pub fn contains(&self, point: &LatLong) -> bool {
+        self.polygons.iter().any(|polygon| {
+            ring_contains(&polygon.exterior, point)
+                && !polygon.holes.iter().any(|hole| ring_contains(hole, point))
+        })
+    }
+
+    /// The smallest box containing the whole boundary.
+    pub fn bounding_box(&self) -> BoundingBox {
+        let mut bounds = BoundingBox {
+            sw: LatLong {
+                latitude: f64::INFINITY,
+                longitude: f64::INFINITY,
+            },
+            ne: LatLong {
+                latitude: f64::NEG_INFINITY,
+                longitude: f64::NEG_INFINITY,
+            },
+        };
+        for point in self.polygons.iter().flat_map(|polygon| &polygon.exterior) {
+            bounds.sw.latitude = bounds.sw.latitude.min(point.latitude);
+            bounds.sw.longitude = bounds.sw.longitude.min(point.longitude);
+            bounds.ne.latitude = bounds.ne.latitude.max(point.latitude);
+            bounds.ne.longitude = bounds.ne.longitude.max(point.longitude);
+        }
+        bounds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A boundary loaded from `geojson`, by way of a file as the scraper reads it.
+    fn boundary(name: &str, geojson: serde_json::Value) -> Boundary {
+        let path = std::env::temp_dir().join(format!(
+            "pickletrack-boundary-{}-{}.geojson",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, geojson.to_string()).unwrap();
+        let boundary = Boundary::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        boundary.unwrap()
+    }
+
+    fn point(latitude: f64, longitude: f64) -> LatLong {
+        LatLong {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// A right triangle with its right angle at the south west, whose long edge runs from 40.7,
+    /// -73.9 to 40.8, -74.0.
+    fn triangle() -> Boundary {
+        boundary(
+            "triangle",
+            serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[-74.0, 40.7], [-73.9, 40.7], [-74.0, 40.8], [-74.0, 40.7]]],
+                },
+            }),
+        )
+    }
+
+    #[test]
+    fn venues_outside_the_polygon_are_excluded() {
+        let triangle = triangle();
+        assert!(triangle.contains(&point(40.72, -73.98)));
+        // Within the bounding box, but beyond the long edge.
+        assert!(!triangle.contains(&point(40.78, -73.92)));
+        // Nowhere near.
+        assert!(!triangle.contains(&point(51.5, -0.12)));
+    }
+
+    #[test]
+    fn venues_either_side_of_an_edge_or_vertex_are_told_apart() {
+        let triangle = triangle();
+        // The long edge is at -73.95 halfway up.
+        assert!(triangle.contains(&point(40.75, -73.9501)));
+        assert!(!triangle.contains(&point(40.75, -73.9499)));
+        // Around the sharp corner at the south east.
+        assert!(triangle.contains(&point(40.7001, -73.9002)));
+        assert!(!triangle.contains(&point(40.7001, -73.8999)));
+        assert!(!triangle.contains(&point(40.6999, -73.9001)));
+    }
+
+    #[test]
+    fn holes_are_excluded_and_collections_are_combined() {
+        let boundary = boundary(
+            "collection",
+            serde_json::json!({
+                "type": "FeatureCollection",
+                "features": [
+                    {"type": "Feature", "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [
+                            [[-74.0, 40.7], [-73.9, 40.7], [-73.9, 40.8], [-74.0, 40.8]],
+                            [[-73.96, 40.74], [-73.94, 40.74], [-73.94, 40.76], [-73.96, 40.76]],
+                        ],
+                    }},
+                    {"type": "Feature", "geometry": {
+                        "type": "MultiPolygon",
+                        "coordinates": [[[[-73.8, 40.6], [-73.7, 40.6], [-73.7, 40.65]]]],
+                    }},
+                ],
+            }),
+        );
+        assert!(boundary.contains(&point(40.71, -73.99)));
+        assert!(!boundary.contains(&point(40.75, -73.95)));
+        assert!(boundary.contains(&point(40.61, -73.72)));
+        assert!(!boundary.contains(&point(40.68, -73.85)));
+
+        let bounds = boundary.bounding_box();
+        assert_eq!((bounds.sw.latitude, bounds.sw.longitude), (40.6, -74.0));
+        assert_eq!((bounds.ne.latitude, bounds.ne.longitude), (40.8, -73.7));
+    }
+}
@@ -0,0 +1,66 @@
+//! Venue search responses kept on disk, so re-running a scrape during development doesn't repeat
+//! the same searches against Foursquare.
+//!
+//! Responses are stored as the raw body Foursquare sent, keyed by the request URL without secrets,
+//! so that changes to how they are decoded don't invalidate the cache.
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::client::redact_url;
+
+/// Directory venue search responses are cached in.
+pub const CACHE_DIR: &str = ".cache/venue-search";
+
+/// FNV-1a hash of `text`. Unlike the standard library's hasher, this is guaranteed not to change
+/// between releases, so the cache survives rebuilding the scraper.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub struct ResponseCache {
+    dir: PathBuf,
+    /// Age after which a cached response is ignored and fetched again.
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(dir: &str, ttl: Duration) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            ttl,
+        }
+    }
+
+    /// The file the response to `uri` is cached in. Secrets are left out of the key, so the cache
+    /// is shared between credentials and never records them.
+    fn path(&self, uri: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}.json", fnv1a(&redact_url(uri))))
+    }
+
+    /// The cached response to `uri`, unless there is none or it has expired.
+    pub fn get(&self, uri: &str) -> Option<String> {
+        let path = self.path(uri);
+        let age = fs::metadata(&path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > self.ttl {
+            return None;
+        }
+        fs::read_to_string(&path).ok()
+    }
+
+    /// Cache `body` as the response to `uri`.
+    ///
+    /// It is written alongside and renamed into place, so a scrape dying mid-write leaves no partial
+    /// response behind.
+    pub fn put(&self, uri: &str, body: &str) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(uri);
+        let staging = path.with_extension("json.new");
+        fs::write(&staging, body)?;
+        fs::rename(&staging, &path)
+    }
+}
@@ -0,0 +1,39 @@
+//! Saving the progress of a search, so a scrape which dies partway can pick up where it left off
+//! with `--resume` rather than searching the region again.
+//!
+//! The checkpoint is written while searching and removed once the scrape finishes.
+use std::fs;
+use std::io;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Name of the file in a region's data directory where the checkpoint of its scrape is kept.
+pub const CHECKPOINT_FILE_NAME: &str = "scrape_checkpoint.json";
+
+/// Read the checkpoint at `path`, if there is one.
+pub fn load<T: DeserializeOwned>(path: &str) -> io::Result<Option<T>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Write a checkpoint to `path`.
+///
+/// It is written alongside and renamed into place, so a scrape dying mid-write leaves the previous
+/// checkpoint intact.
+pub fn save<T: Serialize>(path: &str, checkpoint: &T) -> io::Result<()> {
+    let staging = format!("{}.new", path);
+    serde_json::to_writer(fs::File::create(&staging)?, checkpoint)?;
+    fs::rename(&staging, path)
+}
+
+/// Remove the checkpoint at `path`, if there is one.
+pub fn remove(path: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
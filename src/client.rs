@@ -0,0 +1,567 @@
+//! The HTTP client shared by every request the scraper makes to Foursquare.
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use reqwest::header::{AUTHORIZATION, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+use crate::cache::{ResponseCache, CACHE_DIR};
+use crate::error::ScrapeError;
+use crate::options::ScrapeOptions;
+use crate::schema::{self, Schema};
+
+pub struct ApiClient {
+    client: reqwest::Client,
+    /// Upper bound of the random pause inserted before each request.
+    max_jitter: Duration,
+    /// Number of requests issued so far.
+    requests: AtomicUsize,
+    /// Most times a request is retried after Foursquare asks us to slow down.
+    max_retries: usize,
+    /// Number of retries made so far.
+    retries: AtomicUsize,
+    /// When Foursquare last asked us to back off, the time until which no thread sends requests.
+    paused_until: Mutex<Option<Instant>>,
+    /// Fail on responses missing fields we expect, rather than reading them as absent.
+    strict_schema: bool,
+    /// Where venue search responses are cached, unless caching is disabled with `--no-cache`.
+    cache: Option<ResponseCache>,
+}
+
+/// Query parameters whose values are never included in diagnostics.
+const SECRET_QUERY_PARAMETERS: &[&str] = &["client_secret", "oauth_token"];
+
+/// Delay before the first retry without a `Retry-After` header, doubling with each retry after.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Longest delay between retries without a `Retry-After` header.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 10);
+
+/// How much of a response body is included in diagnostics.
+const BODY_SNIPPET_CHARS: usize = 200;
+
+/// A response body that couldn't be decoded, with enough context to see which request it was and
+/// what came back.
+#[derive(Debug)]
+pub struct DecodeError {
+    /// The request URL, with secrets redacted.
+    pub url: String,
+    pub status: StatusCode,
+    /// The start of the response body.
+    pub snippet: String,
+    pub source: serde_json::Error,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Couldn't decode the response to {} ({}): {}. The response began: {}",
+            self.url, self.status, self.source, self.snippet
+        )
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Replace the values of secret query parameters in `url` with a placeholder.
+pub fn redact_url(url: &str) -> String {
+    let (base, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => return url.to_string(),
+    };
+    let query: Vec<String> = query
+        .split('&')
+        .map(|parameter| match parameter.find('=') {
+            Some(index) if SECRET_QUERY_PARAMETERS.contains(&&parameter[..index]) => {
+                format!("{}=REDACTED", &parameter[..index])
+            }
+            _ => parameter.to_string(),
+        })
+        .collect();
+    format!("{}?{}", base, query.join("&"))
+}
+
+/// Decode a JSON response body, attaching the request and response to the error if it fails.
+pub(crate) fn decode<T: DeserializeOwned>(
+    url: &str,
+    status: StatusCode,
+    body: &str,
+) -> Result<T, DecodeError> {
+    serde_json::from_str(body).map_err(|source| {
+        let mut snippet: String = body.chars().take(BODY_SNIPPET_CHARS).collect();
+        if snippet.len() < body.len() {
+            snippet.push_str("...");
+        }
+        DecodeError {
+            url: redact_url(url),
+            status,
+            snippet,
+            source,
+        }
+    })
+}
+
+/// The delay a `Retry-After` header asks for, given either as a number of seconds or as a date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    // A date in the past means we may retry at once.
+    Some(
+        date.signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// The delay before retry number `retry`, counting from zero, when Foursquare doesn't say how long
+/// to wait. This doubles with each retry up to a ceiling, and is then shortened by up to half at
+/// random so that retries don't arrive in lockstep.
+fn backoff_delay(retry: usize) -> Duration {
+    let delay = INITIAL_RETRY_DELAY
+        .checked_mul(1 << retry.min(16))
+        .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY));
+    let millis = delay.as_millis() as u64;
+    Duration::from_millis(thread_rng().gen_range(millis / 2, millis + 1))
+}
+
+/// Run `attempt` until it succeeds, retrying it up to `max_retries` times while it fails because
+/// Foursquare is rate limiting us (429) or overloaded (503). `on_retry` is told of each failure
+/// and the delay before the next attempt.
+///
+/// The delay is the one the response's `Retry-After` header asks for, or else grows exponentially
+/// with jitter. Any other failure, such as a 400, 401 or 404, is returned at once, as retrying
+/// won't change the answer.
+fn retry_with_backoff<T>(
+    max_retries: usize,
+    mut attempt: impl FnMut() -> Result<T, ScrapeError>,
+    mut on_retry: impl FnMut(&ScrapeError, Duration),
+) -> Result<T, ScrapeError> {
+    let mut retries = 0;
+    loop {
+        let err = match attempt() {
+            Ok(result) => return Ok(result),
+            Err(err) => err,
+        };
+        let retry_after = match &err {
+            ScrapeError::RateLimited { retry_after, .. } => *retry_after,
+            ScrapeError::Http {
+                status: Some(StatusCode::SERVICE_UNAVAILABLE),
+                retry_after,
+                ..
+            } => *retry_after,
+            _ => return Err(err),
+        };
+        if retries >= max_retries {
+            return Err(err);
+        }
+        let delay = retry_after.unwrap_or_else(|| backoff_delay(retries));
+        on_retry(&err, delay);
+        thread::sleep(delay);
+        retries += 1;
+    }
+}
+
+/// The first of the environment variables which is set and not empty.
+fn env_var(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+}
+
+/// Whether requests to `host` should bypass the proxy, given a `NO_PROXY` style comma separated
+/// list of hosts. An entry matches the host itself and its subdomains, and `*` matches every host.
+fn bypasses_proxy(host: &str, no_proxy: &[String]) -> bool {
+    no_proxy.iter().any(|entry| {
+        let entry = entry.trim_start_matches('.');
+        entry == "*"
+            || host.eq_ignore_ascii_case(entry)
+            || host
+                .to_lowercase()
+                .ends_with(&format!(".{}", entry.to_lowercase()))
+    })
+}
+
+impl ApiClient {
+    /// Build the client.
+    ///
+    /// Requests go through the proxy given by `--proxy`, or else the `HTTPS_PROXY` environment
+    /// variable, except for hosts listed in `--no-proxy` or else `NO_PROXY`.
+    pub fn new(options: &ScrapeOptions) -> Result<Self, String> {
+        let mut builder = reqwest::Client::builder().timeout(options.request_timeout);
+        let proxy = options
+            .proxy
+            .clone()
+            .or_else(|| env_var(&["HTTPS_PROXY", "https_proxy"]));
+        if let Some(proxy) = proxy {
+            let proxy_url = reqwest::Url::parse(&proxy)
+                .map_err(|err| format!("Invalid proxy \"{}\": {}", proxy, err))?;
+            let no_proxy: Vec<String> = match options.no_proxy {
+                Some(ref hosts) => hosts.clone(),
+                None => env_var(&["NO_PROXY", "no_proxy"])
+                    .map(|hosts| {
+                        hosts
+                            .split(',')
+                            .map(|host| host.trim().to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            };
+            builder = builder.proxy(reqwest::Proxy::custom(move |url| match url.host_str() {
+                Some(host) if bypasses_proxy(host, &no_proxy) => None,
+                _ => Some(proxy_url.clone()),
+            }));
+        }
+
+        Ok(Self {
+            client: builder
+                .build()
+                .map_err(|err| format!("Couldn't build the HTTP client: {}", err))?,
+            max_jitter: options.max_request_jitter,
+            requests: AtomicUsize::new(0),
+            max_retries: options.max_retries,
+            retries: AtomicUsize::new(0),
+            paused_until: Mutex::new(None),
+            strict_schema: options.strict_schema,
+            cache: if options.no_cache {
+                None
+            } else {
+                Some(ResponseCache::new(CACHE_DIR, options.cache_ttl))
+            },
+        })
+    }
+
+    /// Pick how long to pause before the next request, uniformly between zero and the maximum.
+    fn jitter(&self) -> Duration {
+        let max_millis = self.max_jitter.as_millis() as u64;
+        Duration::from_millis(thread_rng().gen_range(0, max_millis + 1))
+    }
+
+    /// Hold back every thread's requests for `delay`, so the others don't keep Foursquare busy
+    /// while one backs off.
+    fn pause(&self, delay: Duration) {
+        let until = Instant::now() + delay;
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|paused_until| paused_until < until) {
+            *paused_until = Some(until);
+        }
+    }
+
+    /// Sleep until any pause asked for by `pause` is over.
+    fn wait_while_paused(&self) {
+        let paused_until = *self.paused_until.lock().unwrap();
+        if let Some(paused_until) = paused_until {
+            let now = Instant::now();
+            if paused_until > now {
+                thread::sleep(paused_until - now);
+            }
+        }
+    }
+
+    /// Issue a GET request.
+    ///
+    /// Each request is preceded by a short random pause, so our traffic doesn't arrive at
+    /// perfectly periodic intervals and synchronize with Foursquare's rate limiting, and then
+    /// waits out any backoff another thread is in the middle of.
+    fn get(&self, uri: &str, authorization: Option<&str>) -> reqwest::Result<reqwest::Response> {
+        thread::sleep(self.jitter());
+        self.wait_while_paused();
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let request = self.client.get(uri);
+        match authorization {
+            Some(authorization) => request.header(AUTHORIZATION, authorization),
+            None => request,
+        }
+        .send()
+    }
+
+    /// Issue a GET request and decode its JSON response, retrying with `retry_with_backoff` while
+    /// Foursquare is rate limiting us or overloaded.
+    ///
+    /// With `--strict-schema`, the response must have every field `schema` requires, and no fields
+    /// it doesn't know about.
+    pub fn get_json<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        schema: &Schema,
+    ) -> Result<T, ScrapeError> {
+        self.get_json_with(uri, None, schema, false)
+    }
+
+    /// Like `get_json`, answering from the response cache if it holds a fresh response to `uri`,
+    /// and caching the response otherwise.
+    pub fn get_json_cached<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        schema: &Schema,
+    ) -> Result<T, ScrapeError> {
+        self.get_json_with(uri, None, schema, true)
+    }
+
+    /// Like `get_json`, sending `authorization` in the `Authorization` header.
+    pub fn get_json_authorized<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        authorization: &str,
+        schema: &Schema,
+    ) -> Result<T, ScrapeError> {
+        self.get_json_with(uri, Some(authorization), schema, false)
+    }
+
+    /// Like `get_json_cached`, sending `authorization` in the `Authorization` header.
+    pub fn get_json_authorized_cached<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        authorization: &str,
+        schema: &Schema,
+    ) -> Result<T, ScrapeError> {
+        self.get_json_with(uri, Some(authorization), schema, true)
+    }
+
+    fn get_json_with<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        authorization: Option<&str>,
+        schema: &Schema,
+        cached: bool,
+    ) -> Result<T, ScrapeError> {
+        let cache = self.cache.as_ref().filter(|_| cached);
+        if let Some(body) = cache.and_then(|cache| cache.get(uri)) {
+            return self.decode_json(uri, StatusCode::OK, &body, schema);
+        }
+        let (status, body) = retry_with_backoff(
+            self.max_retries,
+            || self.try_get_body(uri, authorization),
+            |err, delay| {
+                eprintln!("{}. Retrying in {} seconds.", err, delay.as_secs());
+                self.retries.fetch_add(1, Ordering::Relaxed);
+                self.pause(delay);
+            },
+        )?;
+        let decoded = self.decode_json(uri, status, &body, schema)?;
+        // Only responses which decoded are cached, so a bad one is fetched again next time. The
+        // cache only saves requests, so failing to write it doesn't fail the scrape.
+        if let Some(cache) = cache {
+            if let Err(err) = cache.put(uri, &body) {
+                eprintln!(
+                    "Couldn't cache the response to {}: {}",
+                    redact_url(uri),
+                    err
+                );
+            }
+        }
+        Ok(decoded)
+    }
+
+    /// Issue a GET request, once, returning the status and body of a successful response.
+    fn try_get_body(
+        &self,
+        uri: &str,
+        authorization: Option<&str>,
+    ) -> Result<(StatusCode, String), ScrapeError> {
+        let http_error = |err: reqwest::Error| ScrapeError::Http {
+            url: redact_url(uri),
+            status: err.status(),
+            // The error's own description includes the URL, secrets and all.
+            message: err
+                .get_ref()
+                .map_or_else(|| "request failed".to_string(), |inner| inner.to_string()),
+            retry_after: None,
+        };
+        let mut response = self.get(uri, authorization).map_err(http_error)?;
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(ScrapeError::RateLimited {
+                url: redact_url(uri),
+                retry_after,
+            });
+        }
+        if !status.is_success() {
+            return Err(ScrapeError::Http {
+                url: redact_url(uri),
+                status: Some(status),
+                message: format!("Foursquare responded {}", status),
+                retry_after,
+            });
+        }
+        let body = response.text().map_err(http_error)?;
+        Ok((status, body))
+    }
+
+    /// Decode a JSON response body, checking it against `schema` with `--strict-schema`.
+    fn decode_json<T: DeserializeOwned>(
+        &self,
+        uri: &str,
+        status: StatusCode,
+        body: &str,
+        schema: &Schema,
+    ) -> Result<T, ScrapeError> {
+        let decoded = decode(uri, status, body)?;
+        if self.strict_schema {
+            // The body decoded as `T`, so it is valid JSON.
+            let value: serde_json::Value = decode(uri, status, body)?;
+            if let Some(field) = schema::missing_field(&value, schema.required) {
+                return Err(ScrapeError::Schema {
+                    url: redact_url(uri),
+                    field,
+                });
+            }
+            if let Some(field) = schema::unexpected_field(&value, schema.known) {
+                return Err(ScrapeError::UnexpectedField {
+                    url: redact_url(uri),
+                    field,
+                });
+            }
+        }
+        Ok(decoded)
+    }
+
+    /// Number of requests issued so far.
+    pub fn requests_made(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    /// Number of requests retried so far.
+    pub fn retries_made(&self) -> usize {
+        self.retries.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// A client pausing up to `max_jitter` before each request.
+    fn client(max_jitter: Duration) -> ApiClient {
+        ApiClient::new(&ScrapeOptions {
+            max_request_jitter: max_jitter,
+            no_cache: true,
+            ..ScrapeOptions::default()
+        })
+        .unwrap()
+    }
+
+    /// A client sending requests through `proxy`, except to the `no_proxy` hosts.
+    fn proxied_client(proxy: &str, no_proxy: &[&str]) -> Result<ApiClient, String> {
+        ApiClient::new(&ScrapeOptions {
+            proxy: Some(proxy.to_string()),
+            no_proxy: Some(no_proxy.iter().map(|host| host.to_string()).collect()),
+            no_cache: true,
+            ..ScrapeOptions::default()
+        })
+    }
+
+    /// Answer a single request on a local port with an empty 200, returning the address and a
+    /// handle giving the request line received.
+    fn serve_once() -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            // Read the rest of the request up to the blank line ending its headers.
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            request_line
+        });
+        (address, handle)
+    }
+
+    #[test]
+    fn requests_go_through_the_configured_proxy() {
+        let (proxy, received) = serve_once();
+        let client = proxied_client(&format!("http://{}", proxy), &[]).unwrap();
+        let response = client
+            .get("http://api.foursquare.test/v2/venues/search", None)
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // A proxy is sent the whole URL to fetch, rather than just its path.
+        assert_eq!(
+            received.join().unwrap(),
+            "GET http://api.foursquare.test/v2/venues/search HTTP/1.1\r\n"
+        );
+    }
+
+    #[test]
+    fn no_proxy_hosts_are_reached_directly() {
+        let (server, received) = serve_once();
+        // Nothing listens on the discard port, so the request would fail if it were proxied.
+        let client = proxied_client("http://127.0.0.1:9", &["127.0.0.1"]).unwrap();
+        let response = client
+            .get(&format!("http://{}/v2/venues/search", server), None)
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            received.join().unwrap(),
+            "GET /v2/venues/search HTTP/1.1\r\n"
+        );
+    }
+
+    #[test]
+    fn no_proxy_entries_match_subdomains() {
+        let no_proxy = vec![".foursquare.com".to_string()];
+        assert!(bypasses_proxy("foursquare.com", &no_proxy));
+        assert!(bypasses_proxy("API.Foursquare.com", &no_proxy));
+        assert!(!bypasses_proxy("notfoursquare.com", &no_proxy));
+        assert!(bypasses_proxy("example.com", &["*".to_string()]));
+    }
+
+    #[test]
+    fn malformed_proxies_are_rejected() {
+        let err = proxied_client("not a proxy", &[]).err().unwrap();
+        assert!(err.starts_with("Invalid proxy \"not a proxy\""), "{}", err);
+    }
+
+    #[test]
+    fn jitter_stays_within_its_bounds() {
+        let client = client(Duration::from_millis(200));
+        let jitters: Vec<Duration> = (0..1000).map(|_| client.jitter()).collect();
+        assert!(jitters
+            .iter()
+            .all(|&jitter| jitter <= Duration::from_millis(200)));
+        // Spread across the range rather than stuck at one end of it.
+        assert!(jitters
+            .iter()
+            .any(|&jitter| jitter < Duration::from_millis(50)));
+        assert!(jitters
+            .iter()
+            .any(|&jitter| jitter > Duration::from_millis(150)));
+    }
+
+    #[test]
+    fn zero_jitter_never_pauses() {
+        let client = client(Duration::from_millis(0));
+        assert!((0..100).all(|_| client.jitter() == Duration::from_millis(0)));
+    }
+}
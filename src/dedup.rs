@@ -0,0 +1,252 @@
+//! Collapsing of near-duplicate data in the listing.
+//!
+//! Venues sometimes have the same tip posted several times with small variations in punctuation,
+//! case or spelling. Left alone these bloat the listing and make a bar look more popular than it is.
+//! Foursquare also sometimes lists one bar as several venues, which would otherwise be suggested as
+//! though they were different bars.
+
+use crate::{distance_latlong, Bar, LatLong, Tip, METERS_PER_MILE};
+
+/// Bars further apart than this are never the same bar.
+const DUPLICATE_BAR_MAX_METERS: f64 = 25.0;
+
+/// Least similarity of two bars' names, as measured by `similarity`, for them to be the same bar.
+const DUPLICATE_BAR_NAME_SIMILARITY: f64 = 0.8;
+
+/// Normalize text for comparison: lowercase, with punctuation dropped and whitespace collapsed.
+pub fn normalize_text(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// The Levenshtein edit distance between two strings, counted in characters.
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        ::std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Similarity of two texts after normalization, from 0 (nothing in common) to 1 (identical).
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = normalize_text(a).chars().collect();
+    let b: Vec<char> = normalize_text(b).chars().collect();
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(&a, &b) as f64 / longest as f64
+}
+
+/// Collapse tips at least `threshold` similar to an earlier tip into that earlier tip.
+///
+/// Returns the distinct tips in their original order, each with the number of tips (including
+/// itself) that collapsed into it.
+pub fn collapse_near_duplicate_tips(tips: Vec<Tip>, threshold: f64) -> Vec<(Tip, usize)> {
+    let mut distinct: Vec<(Tip, usize)> = Vec::new();
+    for tip in tips {
+        match distinct
+            .iter_mut()
+            .find(|(kept, _)| similarity(&kept.text, &tip.text) >= threshold)
+        {
+            Some((_, count)) => *count += 1,
+            None => distinct.push((tip, 1)),
+        }
+    }
+    distinct
+}
+
+/// Whether two bars are the same bar listed twice: within `DUPLICATE_BAR_MAX_METERS` of each other,
+/// with names at least `DUPLICATE_BAR_NAME_SIMILARITY` similar.
+fn same_bar(a: &Bar, b: &Bar) -> bool {
+    let distance_meters = distance_latlong(
+        &LatLong {
+            latitude: a.lat,
+            longitude: a.lng,
+        },
+        &LatLong {
+            latitude: b.lat,
+            longitude: b.lng,
+        },
+    ) * METERS_PER_MILE;
+    distance_meters <= DUPLICATE_BAR_MAX_METERS
+        && similarity(&a.name, &b.name) >= DUPLICATE_BAR_NAME_SIMILARITY
+}
+
+/// Merge `duplicate` into `bar`, adding the tips it has which `bar` doesn't after `bar`'s own.
+/// Otherwise `bar`'s details are kept, filling in any it lacks.
+fn merge_bar(bar: &mut Bar, duplicate: Bar) {
+    let has_weights = !bar.tip_weights.is_empty();
+    for (index, tip) in duplicate.tips.into_iter().enumerate() {
+        if bar.tips.iter().any(|kept| kept.text == tip.text) {
+            continue;
+        }
+        bar.tips.push(tip);
+        if has_weights {
+            bar.tip_weights
+                .push(duplicate.tip_weights.get(index).cloned().unwrap_or(1));
+        }
+    }
+    bar.score = bar.score.max(duplicate.score);
+    bar.rating = bar.rating.or(duplicate.rating);
+    bar.popularity = bar.popularity.max(duplicate.popularity);
+    bar.neighborhood = bar.neighborhood.take().or(duplicate.neighborhood);
+    bar.fsq_url = bar.fsq_url.take().or(duplicate.fsq_url);
+    bar.seasonal = bar.seasonal.max(duplicate.seasonal);
+    bar.photo_url = bar.photo_url.take().or(duplicate.photo_url);
+}
+
+/// Merge bars which Foursquare lists more than once under different ids, as judged by `same_bar`.
+///
+/// The first of each set of duplicates is kept, with the others merged into it, so the bars should
+/// be ordered best first. A merged bar lists its own tips before those of its duplicates.
+pub fn dedup_bars(bars: Vec<Bar>) -> Vec<Bar> {
+    let mut distinct: Vec<Bar> = Vec::new();
+    for bar in bars {
+        match distinct.iter_mut().find(|kept| same_bar(kept, &bar)) {
+            Some(kept) => {
+                eprintln!(
+                    "Merging venue {} ({}) into venue {} ({}), which looks like the same bar.",
+                    bar.id, bar.name, kept.id, kept.name
+                );
+                merge_bar(kept, bar);
+            }
+            None => distinct.push(bar),
+        }
+    }
+    distinct
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Degrees of latitude in a meter.
+    const DEGREES_PER_METER: f64 = 1.0 / 111_195.0;
+
+    /// A bar `meters_north` of a point in the East Village, with one tip.
+    fn bar(id: &str, name: &str, meters_north: f64, tip: &str) -> Bar {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "lat": 40.7265 + meters_north * DEGREES_PER_METER,
+            "lng": -73.9846,
+            "tips": [{"text": tip}],
+        }))
+        .unwrap()
+    }
+
+    fn tips(texts: &[&str]) -> Vec<Tip> {
+        texts
+            .iter()
+            .map(|text| serde_json::from_value(serde_json::json!({ "text": text })).unwrap())
+            .collect()
+    }
+
+    fn collapsed(texts: &[&str], threshold: f64) -> Vec<(String, usize)> {
+        collapse_near_duplicate_tips(tips(texts), threshold)
+            .into_iter()
+            .map(|(tip, count)| (tip.text, count))
+            .collect()
+    }
+
+    fn ids(bars: &[Bar]) -> Vec<&str> {
+        bars.iter().map(|bar| bar.id.as_str()).collect()
+    }
+
+    #[test]
+    fn reposted_tips_are_collapsed_into_the_first() {
+        let collapsed = collapsed(
+            &[
+                "Get the pickleback, it's the best in town!",
+                "Picklebacks are half price on Tuesdays",
+                "GET THE PICKLEBACK -- it's the best in town",
+                "Get the pickelback, its the best in town.",
+            ],
+            0.9,
+        );
+        assert_eq!(
+            collapsed,
+            [
+                ("Get the pickleback, it's the best in town!".to_string(), 3),
+                ("Picklebacks are half price on Tuesdays".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_threshold_decides_how_close_duplicates_must_be() {
+        let texts = [
+            "Get the pickleback, it's the best in town!",
+            "GET THE PICKLEBACK -- it's the best in town",
+            "Get the pickelback, its the best in town.",
+        ];
+        // Only differences in case, punctuation and spacing are ignored at 1.
+        let exact = collapsed(&texts, 1.0);
+        assert_eq!(
+            exact.iter().map(|(_, count)| *count).collect::<Vec<_>>(),
+            [2, 1]
+        );
+        assert_eq!(exact[1].0, texts[2]);
+    }
+
+    #[test]
+    fn short_tips_differing_by_a_word_are_kept_apart() {
+        let collapsed = collapsed(&["Great picklebacks", "Good picklebacks"], 0.9);
+        assert_eq!(collapsed.len(), 2);
+        assert!(similarity("Great picklebacks", "Good picklebacks") < 0.9);
+    }
+
+    #[test]
+    fn nearby_bars_with_similar_names_are_merged() {
+        let bars = dedup_bars(vec![
+            bar("a", "Sweet Afton", 0.0, "Great picklebacks"),
+            bar("b", "Sweet Afton's", 20.0, "Pickleback heaven"),
+        ]);
+        assert_eq!(ids(&bars), ["a"]);
+        let tips: Vec<&str> = bars[0].tips.iter().map(|tip| tip.text.as_str()).collect();
+        assert_eq!(tips, ["Great picklebacks", "Pickleback heaven"]);
+    }
+
+    #[test]
+    fn bars_just_beyond_the_distance_are_kept_apart() {
+        let bars = dedup_bars(vec![
+            bar("a", "Sweet Afton", 0.0, "Great picklebacks"),
+            bar("b", "Sweet Afton", 24.0, "Pickleback heaven"),
+            bar("c", "Sweet Afton", 50.0, "Picklebacks on tap"),
+        ]);
+        // The second is within 25 meters of the first, but the third is more than 25 from it.
+        assert_eq!(ids(&bars), ["a", "c"]);
+    }
+
+    #[test]
+    fn nearby_bars_with_different_names_are_kept_apart() {
+        let bars = dedup_bars(vec![
+            bar("a", "Sweet Afton", 0.0, "Great picklebacks"),
+            // Under the 0.8 similarity needed, though not by much.
+            bar("b", "Sweet Afton Bar", 5.0, "Pickleback heaven"),
+            bar("c", "The Pickle Jar", 5.0, "Picklebacks on tap"),
+        ]);
+        assert_eq!(ids(&bars), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn tips_shared_by_duplicates_are_kept_once() {
+        let bars = dedup_bars(vec![
+            bar("a", "Sweet Afton", 0.0, "Great picklebacks"),
+            bar("b", "sweet afton!", 10.0, "Great picklebacks"),
+        ]);
+        assert_eq!(ids(&bars), ["a"]);
+        assert_eq!(bars[0].tips.len(), 1);
+    }
+}
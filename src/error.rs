@@ -0,0 +1,148 @@
+//! Errors which end a scrape.
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::client::DecodeError;
+
+/// Something which stopped a scrape from finishing.
+///
+/// URLs are redacted, as by `client::redact_url`, so errors can be shown without leaking secrets.
+#[derive(Debug)]
+pub enum ScrapeError {
+    /// A request couldn't be made, or was answered with an error status.
+    Http {
+        url: String,
+        status: Option<StatusCode>,
+        message: String,
+        /// How long the response asked us to wait before trying again.
+        retry_after: Option<Duration>,
+    },
+    /// A response wasn't the JSON we expected.
+    Deserialize(DecodeError),
+    /// With `--strict-schema`, a response lacked a field we expect, which suggests Foursquare's
+    /// API has changed.
+    Schema { url: String, field: String },
+    /// With `--strict-schema`, a response had a field we don't know about, which suggests
+    /// Foursquare's API has changed.
+    UnexpectedField { url: String, field: String },
+    /// Foursquare refused a request because we have made too many.
+    RateLimited {
+        url: String,
+        /// How long Foursquare asked us to wait before trying again.
+        retry_after: Option<Duration>,
+    },
+    /// A file couldn't be read or written.
+    Io { path: String, source: io::Error },
+    /// The options can't be used, such as a malformed proxy URL.
+    Config(String),
+    /// With `--missing-coordinates abort`, Foursquare returned a venue without usable coordinates.
+    MissingCoordinates { id: String, name: String },
+}
+
+impl ScrapeError {
+    /// An IO error on the file at `path`.
+    pub fn io(path: &str, source: io::Error) -> Self {
+        ScrapeError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScrapeError::Http { url, message, .. } => {
+                write!(f, "Request to {} failed: {}", url, message)
+            }
+            ScrapeError::Deserialize(err) => err.fmt(f),
+            ScrapeError::Schema { url, field } => write!(
+                f,
+                "The response to {} has no {}, which the scraper expects. Foursquare's API may \
+                 have changed.",
+                url, field
+            ),
+            ScrapeError::UnexpectedField { url, field } => write!(
+                f,
+                "The response to {} has an unexpected {}, which the scraper doesn't know about. \
+                 Foursquare's API may have changed.",
+                url, field
+            ),
+            ScrapeError::RateLimited { url, .. } => {
+                write!(f, "Foursquare rate limited the request to {}", url)
+            }
+            ScrapeError::Io { path, source } => write!(f, "Couldn't access {}: {}", path, source),
+            ScrapeError::Config(message) => f.write_str(message),
+            ScrapeError::MissingCoordinates { id, name } => {
+                write!(f, "Venue {} ({}) has missing coordinates", id, name)
+            }
+        }
+    }
+}
+
+impl Error for ScrapeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScrapeError::Deserialize(err) => Some(err),
+            ScrapeError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for ScrapeError {
+    fn from(err: DecodeError) -> Self {
+        ScrapeError::Deserialize(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::decode;
+
+    /// The error from decoding `body` as a response to a venue search.
+    fn decode_error(status: StatusCode, body: &str) -> ScrapeError {
+        let url = "https://api.foursquare.com/v2/venues/search?client_id=id&client_secret=hunter2";
+        decode::<serde_json::Value>(url, status, body)
+            .map_err(ScrapeError::from)
+            .unwrap_err()
+    }
+
+    #[test]
+    fn decode_errors_carry_the_request_and_response() {
+        let body = format!("<html><body>{}</body></html>", "Bad gateway. ".repeat(50));
+        let err = decode_error(StatusCode::BAD_GATEWAY, &body);
+        match &err {
+            ScrapeError::Deserialize(decode) => {
+                assert_eq!(
+                    decode.url,
+                    "https://api.foursquare.com/v2/venues/search?client_id=id\
+                     &client_secret=REDACTED"
+                );
+                assert_eq!(decode.status, StatusCode::BAD_GATEWAY);
+                assert!(body.starts_with(decode.snippet.trim_end_matches("...")));
+                assert_eq!(decode.snippet.chars().count(), 203);
+            }
+            _ => panic!("expected a decode error, got {:?}", err),
+        }
+        let message = err.to_string();
+        assert!(message.contains("client_secret=REDACTED"), "{}", message);
+        assert!(!message.contains("hunter2"), "{}", message);
+        assert!(message.contains("502 Bad Gateway"), "{}", message);
+        assert!(message.contains("The response began: <html><body>Bad gateway."));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn short_bodies_are_included_whole() {
+        match decode_error(StatusCode::OK, r#"{"response": "#) {
+            ScrapeError::Deserialize(decode) => assert_eq!(decode.snippet, r#"{"response": "#),
+            err => panic!("expected a decode error, got {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Conversions of a scraped listing into formats other tools understand.
+use std::fs::File;
+use std::io::{self, Write};
+
+use crate::Bar;
+
+/// Load a listing previously written by the scraper.
+pub fn read_bars(path: &str) -> io::Result<Vec<Bar>> {
+    let file = File::open(path)?;
+    Ok(serde_json::from_reader(file)?)
+}
+
+/// Escape the characters which are special inside XML text and attribute values, which are also
+/// those special in HTML.
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quote a CSV field if it contains a comma, quote or line break, doubling any quotes, as RFC 4180
+/// and spreadsheets expect.
+pub fn escape_csv(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Write the bars of the listing as CSV, one row per bar, for spreadsheets.
+pub fn write_bars_csv(bars: &[Bar], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "id,name,lat,lng,tip_count")?;
+    for bar in bars {
+        writeln!(
+            out,
+            "{},{},{},{},{}",
+            escape_csv(&bar.id),
+            escape_csv(&bar.name),
+            bar.lat,
+            bar.lng,
+            bar.tips.len()
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the tips of the listing as CSV, one row per tip, keyed by the id of their bar.
+pub fn write_tips_csv(bars: &[Bar], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "bar_id,tip_text")?;
+    for bar in bars {
+        for tip in &bar.tips {
+            writeln!(out, "{},{}", escape_csv(&bar.id), escape_csv(&tip.text))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the listing as a KML document for Google Earth or Google My Maps.
+///
+/// Each bar becomes a Placemark, with the first tip mentioning picklebacks as its description.
+pub fn write_kml(bars: &[Bar], out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(out, "  <Document>")?;
+    writeln!(out, "    <name>Pickletrack</name>")?;
+    for bar in bars {
+        writeln!(out, "    <Placemark>")?;
+        writeln!(out, "      <name>{}</name>", escape_xml(&bar.name))?;
+        if let Some(tip) = bar.tips.first() {
+            writeln!(
+                out,
+                "      <description>{}</description>",
+                escape_xml(&tip.text)
+            )?;
+        }
+        // KML orders coordinates longitude first.
+        writeln!(
+            out,
+            "      <Point><coordinates>{},{}</coordinates></Point>",
+            bar.lng, bar.lat
+        )?;
+        writeln!(out, "    </Placemark>")?;
+    }
+    writeln!(out, "  </Document>")?;
+    writeln!(out, "</kml>")?;
+    Ok(())
+}
+
+/// Write the listing as a self-contained HTML page, for browsers without JavaScript.
+///
+/// Bars are listed in a table by neighborhood and then name, each with the first tip mentioning
+/// picklebacks and a link to its Foursquare page when we have one.
+pub fn write_html(bars: &[Bar], out: &mut dyn Write) -> io::Result<()> {
+    let mut bars: Vec<&Bar> = bars.iter().collect();
+    bars.sort_by(|a, b| (&a.neighborhood, &a.name).cmp(&(&b.neighborhood, &b.name)));
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, r#"<html lang="en">"#)?;
+    writeln!(out, "  <head>")?;
+    writeln!(out, r#"    <meta charset="utf-8">"#)?;
+    writeln!(
+        out,
+        r#"    <meta name="viewport" content="width=device-width, initial-scale=1">"#
+    )?;
+    writeln!(
+        out,
+        "    <title>Pickletrack: bars serving picklebacks</title>"
+    )?;
+    writeln!(out, "  </head>")?;
+    writeln!(out, "  <body>")?;
+    writeln!(out, "    <main>")?;
+    writeln!(out, "      <h1>Bars serving picklebacks</h1>")?;
+    writeln!(out, "      <table>")?;
+    writeln!(out, "        <thead>")?;
+    writeln!(out, "          <tr>")?;
+    writeln!(out, r#"            <th scope="col">Bar</th>"#)?;
+    writeln!(out, r#"            <th scope="col">Neighborhood</th>"#)?;
+    writeln!(out, r#"            <th scope="col">What people say</th>"#)?;
+    writeln!(out, "          </tr>")?;
+    writeln!(out, "        </thead>")?;
+    writeln!(out, "        <tbody>")?;
+    for bar in bars {
+        writeln!(out, "          <tr>")?;
+        match &bar.fsq_url {
+            Some(url) => writeln!(
+                out,
+                r#"            <th scope="row"><a href="{}">{}</a></th>"#,
+                escape_xml(url),
+                escape_xml(&bar.name)
+            )?,
+            None => writeln!(
+                out,
+                r#"            <th scope="row">{}</th>"#,
+                escape_xml(&bar.name)
+            )?,
+        }
+        writeln!(
+            out,
+            "            <td>{}</td>",
+            escape_xml(bar.neighborhood.as_deref().unwrap_or_default())
+        )?;
+        writeln!(
+            out,
+            "            <td>{}</td>",
+            escape_xml(bar.tips.first().map_or("", |tip| tip.text.as_str()))
+        )?;
+        writeln!(out, "          </tr>")?;
+    }
+    writeln!(out, "        </tbody>")?;
+    writeln!(out, "      </table>")?;
+    writeln!(out, "    </main>")?;
+    writeln!(out, "  </body>")?;
+    writeln!(out, "</html>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that `xml` is well formed, as far as this module's output needs: every element is
+    /// closed in order, and text only uses `&` to begin one of the predefined entities. Returns
+    /// the name of the root element.
+    fn check_well_formed(xml: &str) -> Result<String, String> {
+        check_markup(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#, &[])
+    }
+
+    /// Check that `html` is well formed in the same way, besides its void elements, like `<meta>`,
+    /// which are never closed.
+    fn check_well_formed_html(html: &str) -> Result<String, String> {
+        check_markup(html, "<!DOCTYPE html>", &["meta"])
+    }
+
+    /// Check that `document` begins with `prolog` and is then well formed, with the elements named
+    /// by `void` taken as having no content or closing tag.
+    fn check_markup(document: &str, prolog: &str, void: &[&str]) -> Result<String, String> {
+        let mut open: Vec<&str> = Vec::new();
+        let mut root = None;
+        let mut rest = document
+            .strip_prefix(prolog)
+            .ok_or_else(|| format!("missing {}", prolog))?;
+        while let Some(start) = rest.find('<') {
+            check_text(&rest[..start])?;
+            let end = rest[start..].find('>').ok_or("unclosed tag")? + start;
+            let tag = &rest[start + 1..end];
+            check_text(tag)?;
+            if let Some(name) = tag.strip_prefix('/') {
+                if open.pop() != Some(name) {
+                    return Err(format!("unexpected </{}>", name));
+                }
+            } else {
+                let name = tag.split_whitespace().next().ok_or("empty tag")?;
+                if open.is_empty() && root.replace(name.to_string()).is_some() {
+                    return Err(format!("second root element <{}>", name));
+                }
+                if !void.contains(&name) {
+                    open.push(name);
+                }
+            }
+            rest = &rest[end + 1..];
+        }
+        check_text(rest)?;
+        match open.pop() {
+            Some(name) => Err(format!("<{}> is never closed", name)),
+            None => root.ok_or_else(|| "no root element".to_string()),
+        }
+    }
+
+    /// Check that text has no `<` or `>` and only uses `&` to begin a predefined entity.
+    fn check_text(text: &str) -> Result<(), String> {
+        if text.contains(&['<', '>'][..]) {
+            return Err(format!("unescaped markup in {:?}", text));
+        }
+        for (index, _) in text.match_indices('&') {
+            let entity = &text[index..];
+            if !["&amp;", "&lt;", "&gt;", "&quot;", "&apos;"]
+                .iter()
+                .any(|known| entity.starts_with(known))
+            {
+                return Err(format!("bare & in {:?}", text));
+            }
+        }
+        Ok(())
+    }
+
+    fn bars() -> Vec<Bar> {
+        serde_json::from_value(serde_json::json!([
+            {
+                "id": "a",
+                "name": "Pickles & Rye <Midtown>",
+                "neighborhood": "Midtown",
+                "fsq_url": "https://foursquare.com/v/pickles?ref=a&src=b",
+                "lat": 40.75,
+                "lng": -73.99,
+                "tips": [{"text": "Ask for the \"pickleback\" special, it's <great>"}],
+            },
+            {"id": "b", "name": "Tipless", "lat": 40.7, "lng": -74.0, "tips": []},
+        ]))
+        .unwrap()
+    }
+
+    fn kml(bars: &[Bar]) -> String {
+        let mut out = Vec::new();
+        write_kml(bars, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn kml_is_well_formed() {
+        assert_eq!(check_well_formed(&kml(&bars())), Ok("kml".to_string()));
+        assert_eq!(check_well_formed(&kml(&[])), Ok("kml".to_string()));
+    }
+
+    #[test]
+    fn kml_escapes_names_and_tips() {
+        let kml = kml(&bars());
+        assert!(kml.contains("<name>Pickles &amp; Rye &lt;Midtown&gt;</name>"));
+        assert!(kml.contains(
+            "<description>Ask for the &quot;pickleback&quot; special, it&apos;s &lt;great&gt;\
+             </description>"
+        ));
+        assert!(kml.contains("<coordinates>-73.99,40.75</coordinates>"));
+    }
+
+    fn html(bars: &[Bar]) -> String {
+        let mut out = Vec::new();
+        write_html(bars, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn html_is_well_formed() {
+        assert_eq!(
+            check_well_formed_html(&html(&bars())),
+            Ok("html".to_string())
+        );
+        assert_eq!(check_well_formed_html(&html(&[])), Ok("html".to_string()));
+    }
+
+    #[test]
+    fn html_has_a_row_per_bar_by_neighborhood() {
+        let html = html(&bars());
+        assert_eq!(html.matches(r#"<th scope="row">"#).count(), 2);
+        // Tipless has no neighborhood, so it sorts before Midtown.
+        assert!(html.find("Tipless").unwrap() < html.find("Pickles").unwrap());
+    }
+
+    #[test]
+    fn html_escapes_names_and_tips() {
+        let html = html(&bars());
+        assert!(html.contains(r#"<a href="https://foursquare.com/v/pickles?ref=a&amp;src=b">"#));
+        assert!(html.contains(">Pickles &amp; Rye &lt;Midtown&gt;</a>"));
+        assert!(html.contains(
+            "<td>Ask for the &quot;pickleback&quot; special, it&apos;s &lt;great&gt;</td>"
+        ));
+        assert!(html.contains(r#"<th scope="row">Tipless</th>"#));
+    }
+
+    #[test]
+    fn malformed_html_is_caught() {
+        assert!(check_well_formed_html("<!DOCTYPE html><html><td></html>").is_err());
+        assert!(check_well_formed_html("<html></html>").is_err());
+        assert!(
+            check_well_formed_html(r#"<!DOCTYPE html><html><meta charset="utf-8"></html>"#).is_ok()
+        );
+    }
+
+    #[test]
+    fn malformed_xml_is_caught() {
+        assert!(check_well_formed(r#"<?xml version="1.0" encoding="UTF-8"?><a><b></a>"#).is_err());
+        assert!(check_well_formed(r#"<?xml version="1.0" encoding="UTF-8"?><a>&</a>"#).is_err());
+        assert!(
+            check_well_formed(r#"<?xml version="1.0" encoding="UTF-8"?><a></a><a></a>"#).is_err()
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Requests to the Foursquare Places API v3, which replaces the v2 venues API the scraper was
+//! written against.
+//!
+//! v3 authenticates with an API key in the `Authorization` header rather than a client id and
+//! secret in the query. Places are converted to the v2 types the rest of the scraper works with.
+//! v3 has no checkin counts, so bars scraped through it have no popularity, and returns at most 50
+//! tips for a place.
+use chrono::DateTime;
+use serde::Deserialize;
+
+use crate::client::ApiClient;
+use crate::error::ScrapeError;
+use crate::schema::Schema;
+use crate::{
+    BoundingBox, FoursquareBar, FoursquareBarLocation, FoursquarePhoto, FoursquareTip,
+    FOURSQUARE_MAX_VENUES_PER_QUERY,
+};
+
+const API_URL: &str = "https://api.foursquare.com/v3";
+
+/// Foursquare API ID for the "Bar" category. v3 numbers its categories, rather than using the v2
+/// IDs.
+pub const BAR_CATEGORY_IDENTIFIER: &str = "13003";
+
+/// Place fields we ask for. Rating and photos are only included when asked for.
+const PLACE_FIELDS: &str = "fsq_id,name,geocodes,location,rating,photos";
+
+/// Most tips v3 returns for a place.
+const MAX_TIPS_PER_QUERY: usize = 50;
+
+/// What `--strict-schema` expects of a search response. A place's geocodes, location and photos
+/// are only picked from, so aren't checked for fields we don't know about.
+const SEARCH_SCHEMA: Schema = Schema {
+    required: &["results/*/fsq_id", "results/*/name", "results/*/geocodes"],
+    known: &[
+        "context",
+        "results/*/fsq_id",
+        "results/*/name",
+        "results/*/geocodes",
+        "results/*/location",
+        "results/*/rating",
+        "results/*/photos",
+    ],
+};
+
+/// What `--strict-schema` expects of a place details response, as for a search.
+const PLACE_DETAILS_SCHEMA: Schema = Schema {
+    required: &["fsq_id", "name", "geocodes"],
+    known: &["fsq_id", "name", "geocodes", "location", "rating", "photos"],
+};
+
+/// What `--strict-schema` expects of a tips response.
+const TIPS_SCHEMA: Schema = Schema {
+    required: &["*/text", "*/created_at"],
+    known: &["*/text", "*/created_at", "*/lang"],
+};
+
+#[derive(Deserialize)]
+struct Coordinates {
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Geocodes {
+    /// Where the place is shown on a map, as opposed to the entrance or the like.
+    main: Option<Coordinates>,
+}
+
+#[derive(Deserialize, Default)]
+struct Location {
+    /// The state, within the US.
+    region: Option<String>,
+    /// Within New York, the borough.
+    locality: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Photo {
+    prefix: String,
+    suffix: String,
+}
+
+#[derive(Deserialize)]
+struct Place {
+    fsq_id: String,
+    name: String,
+    geocodes: Option<Geocodes>,
+    #[serde(default)]
+    location: Location,
+    rating: Option<f64>,
+    /// Featured photos, best first.
+    #[serde(default)]
+    photos: Vec<Photo>,
+}
+
+impl From<Place> for FoursquareBar {
+    fn from(place: Place) -> Self {
+        let coordinates = place.geocodes.and_then(|geocodes| geocodes.main);
+        FoursquareBar {
+            canonical_url: Some(format!("https://foursquare.com/v/{}", place.fsq_id)),
+            id: place.fsq_id,
+            name: place.name,
+            location: FoursquareBarLocation {
+                lat: coordinates.as_ref().and_then(|main| main.latitude),
+                lng: coordinates.as_ref().and_then(|main| main.longitude),
+                state: place.location.region,
+                city: place.location.locality,
+            },
+            rating: place.rating,
+            stats: None,
+            best_photo: place
+                .photos
+                .into_iter()
+                .next()
+                .map(|photo| FoursquarePhoto {
+                    prefix: photo.prefix,
+                    suffix: photo.suffix,
+                }),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    results: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+struct Tip {
+    text: String,
+    /// When the tip was written, such as `2014-07-26T01:44:17.000Z`.
+    created_at: Option<String>,
+    lang: Option<String>,
+}
+
+/// Search a box for places in `category`, returning at most `FOURSQUARE_MAX_VENUES_PER_QUERY`.
+pub fn search(
+    client: &ApiClient,
+    api_key: &str,
+    bounds: &BoundingBox,
+    category: &str,
+) -> Result<Vec<FoursquareBar>, ScrapeError> {
+    let uri = format!(
+        "{}/places/search?sw={},{}&ne={},{}&categories={}&fields={}&limit={}",
+        API_URL,
+        bounds.sw.latitude,
+        bounds.sw.longitude,
+        bounds.ne.latitude,
+        bounds.ne.longitude,
+        category,
+        PLACE_FIELDS,
+        FOURSQUARE_MAX_VENUES_PER_QUERY
+    );
+    let result: SearchResult = client.get_json_authorized_cached(&uri, api_key, &SEARCH_SCHEMA)?;
+    Ok(result
+        .results
+        .into_iter()
+        .map(FoursquareBar::from)
+        .collect())
+}
+
+/// Fetch the details of a place.
+pub fn place(client: &ApiClient, api_key: &str, id: &str) -> Result<FoursquareBar, ScrapeError> {
+    let uri = format!("{}/places/{}?fields={}", API_URL, id, PLACE_FIELDS);
+    let place: Place = client.get_json_authorized(&uri, api_key, &PLACE_DETAILS_SCHEMA)?;
+    Ok(place.into())
+}
+
+/// Fetch the tips of a place.
+pub fn tips(
+    client: &ApiClient,
+    api_key: &str,
+    id: &str,
+) -> Result<Vec<FoursquareTip>, ScrapeError> {
+    let uri = format!(
+        "{}/places/{}/tips?fields=text,created_at,lang&limit={}",
+        API_URL, id, MAX_TIPS_PER_QUERY
+    );
+    let tips: Vec<Tip> = client.get_json_authorized(&uri, api_key, &TIPS_SCHEMA)?;
+    Ok(tips
+        .into_iter()
+        .map(|tip| FoursquareTip {
+            created_at: tip
+                .created_at
+                .and_then(|created_at| DateTime::parse_from_rfc3339(&created_at).ok())
+                .map(|created_at| created_at.timestamp()),
+            text: tip.text,
+            lang: tip.lang,
+        })
+        .collect())
+}
@@ -0,0 +1,2439 @@
+//! A library for scraping bars that mention picklebacks from Foursquare.
+//!
+//! The library writes the result of a scrape to the file ~/static/data/%Y%m%d.json and then updates
+//! the symlink ~/static/data/current.json to point to this new file. The web server will
+//! periodically reload the list of bars from the symlinked JSON file.
+//!
+//! The `scrape` binary is a thin wrapper around `run_cli`. Servers which scrape for themselves call
+//! `scrape_listing` instead, and get the listing back rather than having it written out.
+extern crate chrono;
+extern crate reqwest;
+extern crate serde;
+extern crate serde_derive;
+extern crate serde_json;
+
+mod backfill;
+mod boundary;
+mod cache;
+mod checkpoint;
+mod client;
+mod dedup;
+mod error;
+mod export;
+mod foursquare_v3;
+mod options;
+mod partition;
+mod phrases;
+mod promote;
+mod sample;
+mod schema;
+mod score;
+mod seasonal;
+mod summary;
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+use chrono::{Date, Utc};
+use score::ScoreInputs;
+use summary::ScrapeSummary;
+use serde::{Deserialize, Serialize};
+
+use boundary::Boundary;
+use checkpoint::CHECKPOINT_FILE_NAME;
+use client::ApiClient;
+use schema::Schema;
+use options::{
+    ApiVersion, Command, DryRun, MissingCoordinates, OutputFormat, OutputRanking, RegionOptions,
+    TipSelection,
+};
+
+pub use error::ScrapeError;
+pub use options::ScrapeOptions;
+pub use phrases::phrase_occurrences;
+
+/// Phrases a tip must contain to count as mentioning picklebacks, unless a phrase file is given.
+const TIP_SEARCH_PHRASES: &[&'static str] = &[
+    "pickle back",
+    "pickleback",
+    "pickel back",
+    "pickelback",
+    "pickle-back",
+    "pickle shot",
+    "pickel shot",
+    "pickle-shot",
+    "pickel-shot",
+    "shot of pickle",
+    "shot of pickel",
+    "shot pickle",
+    "shot pickel",
+    "pickle juice",
+    "pickel juice",
+    "pickle-juice",
+    "pickel-juice",
+];
+
+const MANHATTAN_BOUNDING_BOX_TOPLEFT: LatLong = LatLong {
+    latitude: 40.934688,
+    longitude: -74.061693,
+};
+const MANHATTAN_BOUNDING_BOX_HEIGHT_METERS: i32 = 48000;
+const MANHATTAN_BOUNDING_BOX_WIDTH_METERS: i32 = 33000;
+
+/// When querying the Foursquare API for places, this is the default bounding box search size we
+/// restrict to. If there are too many results, the bounding box will be choppped in half repeatedly
+/// until they are all found. Note that the API has a limit of 10 square kilometers per query, so we
+/// sneak in a little under this.
+const DEFAULT_SEARCH_SIZE_METERS: i32 = 3000;
+
+/// How far each search box reaches past its share of the grid on every side.
+const SEARCH_BOX_OVERLAP_METERS: i32 = 10;
+
+const METERS_PER_MILE: f64 = 1609.344;
+
+/// Radius of the sphere distances are measured on, the 3959 miles of `distance_latlong`.
+const EARTH_RADIUS_METERS: f64 = 3959.0 * METERS_PER_MILE;
+
+/// Foursquare API ID for the "Bar" category.
+const FOURSQUARE_BAR_CATEGORY_IDENTIFIER: &'static str = "4bf58dd8d48988d116941735";
+
+/// Friendly names for Foursquare categories a scrape can be narrowed to, with their API IDs. A
+/// search for a category also returns venues in its subcategories.
+pub const FOURSQUARE_CATEGORY_ALIASES: &[(&str, &str)] = &[
+    ("bar", FOURSQUARE_BAR_CATEGORY_IDENTIFIER),
+    ("beer-bar", "56aa371ce4b08b9a8d57356c"),
+    ("beer-garden", "4bf58dd8d48988d117941735"),
+    ("cocktail-bar", "4bf58dd8d48988d11e941735"),
+    ("dive-bar", "4bf58dd8d48988d118941735"),
+    ("gay-bar", "4bf58dd8d48988d1d8941735"),
+    ("hotel-bar", "4bf58dd8d48988d1d5941735"),
+    ("karaoke-bar", "4bf58dd8d48988d120941735"),
+    ("pub", "4bf58dd8d48988d11b941735"),
+    ("sake-bar", "4bf58dd8d48988d11c941735"),
+    ("speakeasy", "4bf58dd8d48988d1d4941735"),
+    ("sports-bar", "4bf58dd8d48988d11d941735"),
+    ("whiskey-bar", "4bf58dd8d48988d122941735"),
+    ("whisky-bar", "4bf58dd8d48988d122941735"),
+    ("wine-bar", "4bf58dd8d48988d123941735"),
+];
+
+/// Foursquare API version tested against. Format YYYYMMDD.
+const FOURSQUARE_API_VERSION_TARGETED: &'static str = "20170911";
+
+/// Foursquare maximum results returned per query.
+const FOURSQUARE_MAX_VENUES_PER_QUERY: usize = 50;
+
+/// Name of the symlink to a region's newest listing, within its data directory.
+const CURRENT_LISTING_FILE_NAME: &str = "current.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct LatLong {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct BoundingBox {
+    sw: LatLong,
+    ne: LatLong,
+}
+
+/// A search box a venue was returned for, recorded with `--debug-boxes`. A venue in the overlap
+/// between boxes is recorded once for each.
+#[derive(Serialize, Deserialize)]
+pub struct VenueBox {
+    id: String,
+    name: String,
+    /// South west corner of the box, as latitude and longitude.
+    sw: [f64; 2],
+    /// North east corner of the box, as latitude and longitude.
+    ne: [f64; 2],
+}
+
+/// The rectangular area we scrape, which is divided into a grid of search boxes.
+#[derive(Debug, Clone)]
+struct Region {
+    name: String,
+    top_left: LatLong,
+    height_meters: i32,
+    width_meters: i32,
+    /// Venues whose state is anything else are discarded. Without one, venues in any state are
+    /// kept.
+    state: Option<String>,
+    /// Directory the region's listings are written to.
+    data_dir: String,
+}
+
+/// Round a distance up to a whole number of search boxes, and at least one.
+fn round_up_to_search_boxes(meters: f64) -> i32 {
+    let boxes = (meters / DEFAULT_SEARCH_SIZE_METERS as f64).ceil().max(1.0);
+    boxes as i32 * DEFAULT_SEARCH_SIZE_METERS
+}
+
+/// The directory a region's listings are kept in: `output_dir` for the default region, or a
+/// subdirectory of it named after a region given with `--region-name`.
+fn region_data_dir(output_dir: &str, region_name: Option<&str>) -> String {
+    let slug = region_name.map(partition::slug).unwrap_or_default();
+    if slug.is_empty() {
+        output_dir.to_string()
+    } else {
+        format!("{}/{}", output_dir, slug)
+    }
+}
+
+/// The symlink to a region's newest listing, which the scrape and `promote` point at a listing
+/// and `rollback` swaps back.
+fn current_listing_path(output_dir: &str, region_name: Option<&str>) -> String {
+    let data_dir = region_data_dir(output_dir, region_name);
+    format!("{}/{}", data_dir, CURRENT_LISTING_FILE_NAME)
+}
+
+impl Region {
+    /// The default region, covering Manhattan and its surroundings, with its listings kept in
+    /// `data_dir`.
+    fn manhattan(data_dir: &str) -> Self {
+        Self {
+            name: "Manhattan".to_string(),
+            top_left: MANHATTAN_BOUNDING_BOX_TOPLEFT,
+            height_meters: MANHATTAN_BOUNDING_BOX_HEIGHT_METERS,
+            width_meters: MANHATTAN_BOUNDING_BOX_WIDTH_METERS,
+            state: Some("NY".to_string()),
+            data_dir: data_dir.to_string(),
+        }
+    }
+
+    /// A region given with the `--region` options, rounded up to a whole number of search boxes.
+    /// Its listings are kept in a subdirectory of `data_dir` named after it.
+    fn from_options(region: &RegionOptions, data_dir: &str) -> Self {
+        Self {
+            name: region.name.clone(),
+            top_left: LatLong {
+                latitude: region.top_left.0,
+                longitude: region.top_left.1,
+            },
+            height_meters: round_up_to_search_boxes(region.height_meters as f64),
+            width_meters: round_up_to_search_boxes(region.width_meters as f64),
+            state: None,
+            data_dir: region_data_dir(data_dir, Some(&region.name)),
+        }
+    }
+
+    /// The smallest region covering a bounding box, rounded up to a whole number of search boxes.
+    ///
+    /// This inverts `point`, so that the far corner of the region is at or beyond the far corner of
+    /// the bounding box.
+    ///
+    /// Venues are only kept if Foursquare places them in `state`, if given, and its listings are
+    /// kept in `data_dir`.
+    fn covering(name: &str, bounds: &BoundingBox, state: Option<String>, data_dir: &str) -> Self {
+        let top_left = LatLong {
+            latitude: bounds.ne.latitude,
+            longitude: bounds.sw.longitude,
+        };
+        let height_meters = round_up_to_search_boxes(
+            (bounds.ne.latitude - bounds.sw.latitude).to_radians() * EARTH_RADIUS_METERS,
+        );
+        let middle = offset_latlong(&top_left, -height_meters / 2, 0);
+        let width = (bounds.ne.longitude - bounds.sw.longitude).to_radians()
+            * EARTH_RADIUS_METERS
+            * middle.latitude.to_radians().cos();
+
+        Self {
+            name: name.to_string(),
+            top_left,
+            height_meters,
+            width_meters: round_up_to_search_boxes(width),
+            state,
+            data_dir: data_dir.to_string(),
+        }
+    }
+
+    /// The point `dn` meters north and `de` meters east of the top left corner.
+    ///
+    /// Search boxes are bounded by lines of latitude and longitude, so for them to meet without
+    /// gaps every point on a column of the grid must share a longitude. East-west offsets are
+    /// therefore measured along the parallel through the middle of the region, whatever the
+    /// latitude, and a box is a little narrower than that at the region's edge nearer the pole
+    /// and wider at the other, by the ratio of the cosines of the latitudes.
+    fn point(&self, dn: i32, de: i32) -> LatLong {
+        let middle = offset_latlong(&self.top_left, -self.height_meters / 2, 0);
+        LatLong {
+            latitude: offset_latlong(&self.top_left, dn, 0).latitude,
+            longitude: offset_latlong(&middle, 0, de).longitude,
+        }
+    }
+
+    /// Whether a point lies within the region, or no more than `tolerance_meters` outside of it.
+    fn contains(&self, point: &LatLong, tolerance_meters: i32) -> bool {
+        let ne = self.point(tolerance_meters, self.width_meters + tolerance_meters);
+        let sw = self.point(
+            -(self.height_meters + tolerance_meters),
+            -tolerance_meters,
+        );
+        point.latitude >= sw.latitude
+            && point.latitude <= ne.latitude
+            && point.longitude >= sw.longitude
+            && point.longitude <= ne.longitude
+    }
+
+    /// Where the checkpoint of the region's search is kept.
+    fn checkpoint_path(&self) -> String {
+        format!("{}/{}", self.data_dir, CHECKPOINT_FILE_NAME)
+    }
+
+    /// The center of the region.
+    fn centroid(&self) -> LatLong {
+        self.point(-self.height_meters / 2, self.width_meters / 2)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FoursquareBarLocation {
+    lat: Option<f64>,
+    lng: Option<f64>,
+
+    state: Option<String>,
+    /// Within New York, the borough.
+    city: Option<String>,
+}
+
+impl FoursquareBarLocation {
+    /// The coordinates of the venue, if Foursquare gave us a usable pair.
+    fn coordinates(&self) -> Option<LatLong> {
+        match (self.lat, self.lng) {
+            (Some(latitude), Some(longitude))
+                if latitude.is_finite()
+                    && longitude.is_finite()
+                    && latitude.abs() <= 90.0
+                    && longitude.abs() <= 180.0 =>
+            {
+                Some(LatLong {
+                    latitude,
+                    longitude,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FoursquareBarStats {
+    #[serde(rename = "checkinsCount")]
+    checkins_count: Option<u64>,
+}
+
+/// A photo, whose URL is assembled from a prefix, a size and a suffix.
+#[derive(Serialize, Deserialize, Debug)]
+struct FoursquarePhoto {
+    prefix: String,
+    suffix: String,
+}
+
+impl FoursquarePhoto {
+    /// URL of the photo at its original size.
+    fn url(&self) -> String {
+        format!("{}original{}", self.prefix, self.suffix)
+    }
+}
+
+/// A venue as Foursquare describes it. This serializes in the same form, for checkpoints.
+#[derive(Serialize, Deserialize, Debug)]
+struct FoursquareBar {
+    id: String,
+    name: String,
+    location: FoursquareBarLocation,
+    rating: Option<f64>,
+    stats: Option<FoursquareBarStats>,
+    /// The venue's page on Foursquare.
+    #[serde(rename = "canonicalUrl")]
+    canonical_url: Option<String>,
+    /// The venue's featured photo, which Foursquare only includes in some responses.
+    #[serde(rename = "bestPhoto")]
+    best_photo: Option<FoursquarePhoto>,
+}
+
+/// A tip as it may appear in a listing. Older listings store each tip as a plain string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredTip {
+    Text(String),
+    Detailed {
+        text: String,
+        #[serde(default)]
+        matched: Vec<String>,
+        #[serde(default)]
+        lang: Option<String>,
+    },
+}
+
+/// A tip mentioning picklebacks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "StoredTip")]
+pub struct Tip {
+    text: String,
+    /// Search phrases matched in the text, so it's clear why the tip was kept. Empty for tips
+    /// read from listings which predate this.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    matched: Vec<String>,
+    /// Language of the tip as reported by Foursquare, such as "en".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<String>,
+}
+
+impl From<StoredTip> for Tip {
+    fn from(stored: StoredTip) -> Self {
+        match stored {
+            StoredTip::Text(text) => Tip {
+                text,
+                matched: Vec::new(),
+                lang: None,
+            },
+            StoredTip::Detailed {
+                text,
+                matched,
+                lang,
+            } => Tip {
+                text,
+                matched,
+                lang,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Bar {
+    id: String,
+    name: String,
+    lat: f64,
+    lng: f64,
+    tips: Vec<Tip>,
+    /// Quality score used to rank bars for curated feeds. See the `score` module.
+    #[serde(default)]
+    score: f64,
+    /// How many near-duplicate tips collapsed into each tip, when the scrape was asked to keep
+    /// these counts. Empty otherwise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tip_weights: Vec<usize>,
+    /// Distance in miles from the center of the scraped region, when the scrape was asked to
+    /// annotate it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    centroid_distance_miles: Option<f64>,
+    /// Foursquare rating out of 10, if the venue has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rating: Option<f64>,
+    /// Number of Foursquare check-ins at the venue, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    popularity: Option<u64>,
+    /// Area the bar is in, as the city Foursquare files the venue under. Within New York this is
+    /// the borough.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    neighborhood: Option<String>,
+    /// The bar's page on Foursquare, if Foursquare gave us one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fsq_url: Option<String>,
+    /// Whether tips suggest the bar only serves picklebacks seasonally, when the scrape was asked
+    /// to classify this. See the `seasonal` module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seasonal: Option<bool>,
+    /// The bar's featured photo on Foursquare, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    photo_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareTip {
+    text: String,
+    /// Unix timestamp of when the tip was written.
+    #[serde(rename = "createdAt")]
+    created_at: Option<i64>,
+    lang: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareVenueQueryResponse {
+    venues: Vec<FoursquareBar>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareTipsItems {
+    items: Vec<FoursquareTip>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareTipsQueryResponse {
+    tips: FoursquareTipsItems,
+}
+
+/// What `--strict-schema` expects of a venue search response. A venue's location, stats and photo
+/// are only picked from, so aren't checked for fields we don't know about.
+const VENUE_SEARCH_SCHEMA: Schema = Schema {
+    required: &[
+        "response/venues/*/id",
+        "response/venues/*/name",
+        "response/venues/*/location",
+    ],
+    known: &[
+        "meta",
+        "notifications",
+        "response/venues/*/id",
+        "response/venues/*/name",
+        "response/venues/*/location",
+        "response/venues/*/rating",
+        "response/venues/*/stats",
+        "response/venues/*/canonicalUrl",
+        "response/venues/*/bestPhoto",
+    ],
+};
+
+/// What `--strict-schema` expects of a venue details response, as for a venue search.
+const VENUE_DETAILS_SCHEMA: Schema = Schema {
+    required: &[
+        "response/venue/id",
+        "response/venue/name",
+        "response/venue/location",
+        "response/venue/canonicalUrl",
+    ],
+    known: &[
+        "meta",
+        "notifications",
+        "response/venue/id",
+        "response/venue/name",
+        "response/venue/location",
+        "response/venue/rating",
+        "response/venue/stats",
+        "response/venue/canonicalUrl",
+        "response/venue/bestPhoto",
+    ],
+};
+
+/// What `--strict-schema` expects of a tips response.
+const TIPS_SCHEMA: Schema = Schema {
+    required: &["response/tips/items/*/text", "response/tips/items/*/createdAt"],
+    known: &[
+        "meta",
+        "notifications",
+        "response/tips/count",
+        "response/tips/items/*/text",
+        "response/tips/items/*/createdAt",
+        "response/tips/items/*/lang",
+    ],
+};
+
+#[derive(Deserialize, Debug)]
+struct FoursquareVenueQueryResult {
+    response: FoursquareVenueQueryResponse,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareVenueDetailsResponse {
+    venue: FoursquareBar,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareVenueDetailsResult {
+    response: FoursquareVenueDetailsResponse,
+}
+
+#[derive(Deserialize, Debug)]
+struct FoursquareTipsQueryResult {
+    response: FoursquareTipsQueryResponse,
+}
+
+/// How we authenticate with Foursquare, which decides the version of the API we use.
+enum Credentials {
+    V2 {
+        client_id: String,
+        client_secret: String,
+    },
+    V3 {
+        api_key: String,
+    },
+}
+
+impl Credentials {
+    /// Read the credentials for `version` from the environment.
+    fn from_env(version: ApiVersion) -> Result<Self, ScrapeError> {
+        let var = |name: &str| {
+            ::std::env::var(name).map_err(|_| ScrapeError::Config(format!("{} must be set", name)))
+        };
+        Ok(match version {
+            ApiVersion::V2 => Credentials::V2 {
+                client_id: var("CLIENT_ID")?,
+                client_secret: var("CLIENT_SECRET")?,
+            },
+            ApiVersion::V3 => Credentials::V3 {
+                api_key: var("FOURSQUARE_API_KEY")?,
+            },
+        })
+    }
+
+    /// The categories searched, as the API version numbers them. Bars are searched unless others
+    /// are given.
+    fn categories<'a>(&self, options: &'a ScrapeOptions) -> Vec<&'a str> {
+        match self {
+            Credentials::V2 { .. } if !options.category_ids.is_empty() => {
+                options.category_ids.iter().map(String::as_str).collect()
+            }
+            Credentials::V2 { .. } => vec![FOURSQUARE_BAR_CATEGORY_IDENTIFIER],
+            Credentials::V3 { .. } => vec![foursquare_v3::BAR_CATEGORY_IDENTIFIER],
+        }
+    }
+
+    /// Search a box for venues in `category`, returning at most
+    /// `FOURSQUARE_MAX_VENUES_PER_QUERY`.
+    fn search(
+        &self,
+        client: &ApiClient,
+        bounds: &BoundingBox,
+        category: &str,
+    ) -> Result<Vec<FoursquareBar>, ScrapeError> {
+        let (client_id, client_secret) = match self {
+            Credentials::V2 {
+                client_id,
+                client_secret,
+            } => (client_id, client_secret),
+            Credentials::V3 { api_key } => {
+                return foursquare_v3::search(client, api_key, bounds, category)
+            }
+        };
+        let uri = format!(
+            "https://api.foursquare.com/v2/venues/search?\
+             sw={},{}&\
+             ne={},{}&\
+             intent=browse&\
+             categoryId={}&\
+             client_id={}&\
+             client_secret={}&\
+             v={}&\
+             m=foursquare&\
+             limit={}",
+            bounds.sw.latitude,
+            bounds.sw.longitude,
+            bounds.ne.latitude,
+            bounds.ne.longitude,
+            category,
+            client_id,
+            client_secret,
+            FOURSQUARE_API_VERSION_TARGETED,
+            FOURSQUARE_MAX_VENUES_PER_QUERY
+        );
+        let results: FoursquareVenueQueryResult =
+            client.get_json_cached(&uri, &VENUE_SEARCH_SCHEMA)?;
+        Ok(results.response.venues)
+    }
+
+    /// Fetch the details of a venue.
+    fn venue(&self, client: &ApiClient, venue_id: &str) -> Result<FoursquareBar, ScrapeError> {
+        let (client_id, client_secret) = match self {
+            Credentials::V2 {
+                client_id,
+                client_secret,
+            } => (client_id, client_secret),
+            Credentials::V3 { api_key } => return foursquare_v3::place(client, api_key, venue_id),
+        };
+        let uri = format!(
+            "https://api.foursquare.com/v2/venues/{}?\
+             client_id={}&\
+             client_secret={}&\
+             v={}",
+            venue_id, client_id, client_secret, FOURSQUARE_API_VERSION_TARGETED
+        );
+        let result: FoursquareVenueDetailsResult = client.get_json(&uri, &VENUE_DETAILS_SCHEMA)?;
+        Ok(result.response.venue)
+    }
+
+    /// Fetch the tips of a venue.
+    fn tips(&self, client: &ApiClient, venue_id: &str) -> Result<Vec<FoursquareTip>, ScrapeError> {
+        let (client_id, client_secret) = match self {
+            Credentials::V2 {
+                client_id,
+                client_secret,
+            } => (client_id, client_secret),
+            Credentials::V3 { api_key } => return foursquare_v3::tips(client, api_key, venue_id),
+        };
+        let uri = format!(
+            "https://api.foursquare.com/v2/venues/{}/tips?\
+             limit=500&\
+             client_id={}&\
+             client_secret={}&\
+             v={}",
+            venue_id, client_id, client_secret, FOURSQUARE_API_VERSION_TARGETED
+        );
+        let results: FoursquareTipsQueryResult = client.get_json(&uri, &TIPS_SCHEMA)?;
+        Ok(results.response.tips.items)
+    }
+}
+
+/// Given a source lat/long point, and distances in meters to travel north and east from that point,
+/// produce a new lat/long point at the resulting location.
+///
+/// The point is reached by travelling `dn` meters along the meridian, then `de` meters along the
+/// parallel at the new latitude, on the sphere `distance_latlong` measures. Both legs are exact on
+/// that sphere, so `distance_latlong` agrees with either offset alone to within rounding. Scaling
+/// longitude by the source latitude instead, as this once did, is off by the ratio of the cosines
+/// of the two latitudes: 0.7% 50 km north of Manhattan, and 1.4% at 60 degrees. The sphere itself
+/// is within 0.5% of the real Earth.
+fn offset_latlong(source: &LatLong, dn: i32, de: i32) -> LatLong {
+    let latitude = source.latitude + (dn as f64 / EARTH_RADIUS_METERS).to_degrees();
+    let d_lon = de as f64 / (EARTH_RADIUS_METERS * latitude.to_radians().cos());
+
+    LatLong {
+        latitude,
+        longitude: source.longitude + d_lon.to_degrees(),
+    }
+}
+
+/// Compute the distance in miles between two points.
+fn distance_latlong(a: &LatLong, b: &LatLong) -> f64 {
+    let d_lat: f64 = (b.latitude - a.latitude).to_radians();
+    let d_lon: f64 = (b.longitude - a.longitude).to_radians();
+    let h = (d_lat / 2.0).sin().powf(2.0)
+        + a.latitude.to_radians().cos()
+            * b.latitude.to_radians().cos()
+            * (d_lon / 2.0).sin().powf(2.0);
+    let c = 2.0 * h.sqrt().atan2((1.0 - h).sqrt());
+    3959.0 * c
+}
+
+/// A bar's distance in miles from the region's centroid, if the scrape annotates it.
+fn centroid_distance(options: &ScrapeOptions, bar: &LatLong, centroid: &LatLong) -> Option<f64> {
+    if options.annotate_centroid_distance {
+        Some(distance_latlong(bar, centroid))
+    } else {
+        None
+    }
+}
+
+/// Given a bounding box, split it into four equally distributed sub quadrants.
+///
+/// This is used for fine grained search within the limits of the Foursquare API. Foursquare will
+/// return at most 50 results for any given bounding box, so when we encounter a box that has 50
+/// items, we subdivide it and keep trying until all results are known comprehensively.
+fn split_to_quadrants(source: &BoundingBox) -> [BoundingBox; 4] {
+    let midpoint_lat: f64 = (source.sw.latitude + source.ne.latitude) / 2.0f64;
+    let midpoint_lon: f64 = (source.sw.longitude + source.ne.longitude) / 2.0f64;
+
+    [
+        // Top left
+        BoundingBox {
+            sw: LatLong {
+                latitude: midpoint_lat,
+                longitude: source.sw.longitude,
+            },
+            ne: LatLong {
+                latitude: source.ne.latitude,
+                longitude: midpoint_lon,
+            },
+        },
+        // Top right
+        BoundingBox {
+            sw: LatLong {
+                latitude: midpoint_lat,
+                longitude: midpoint_lon,
+            },
+            ne: LatLong {
+                latitude: source.ne.latitude,
+                longitude: source.ne.longitude,
+            },
+        },
+        // Bottom left
+        BoundingBox {
+            sw: LatLong {
+                latitude: source.sw.latitude,
+                longitude: source.sw.longitude,
+            },
+            ne: LatLong {
+                latitude: midpoint_lat,
+                longitude: midpoint_lon,
+            },
+        },
+        // Bottom right
+        BoundingBox {
+            sw: LatLong {
+                latitude: source.sw.latitude,
+                longitude: midpoint_lon,
+            },
+            ne: LatLong {
+                latitude: midpoint_lat,
+                longitude: source.ne.longitude,
+            },
+        },
+    ]
+}
+
+/// Subdivide the region bounding box into a grid of search boxes, `DEFAULT_SEARCH_SIZE_METERS`
+/// square before overlap.
+fn search_grid(region: &Region) -> Vec<BoundingBox> {
+    let mut grid = Vec::new();
+    for de in 0..region.width_meters / DEFAULT_SEARCH_SIZE_METERS {
+        for dn in 0..region.height_meters / DEFAULT_SEARCH_SIZE_METERS {
+            // We push the edges of the sub boxes to overlap a little bit, to account for
+            // potential GIS issues and missing places in the lat/long cracks.
+            grid.push(BoundingBox {
+                sw: region.point(
+                    -((dn + 1) * DEFAULT_SEARCH_SIZE_METERS + SEARCH_BOX_OVERLAP_METERS),
+                    de * DEFAULT_SEARCH_SIZE_METERS - SEARCH_BOX_OVERLAP_METERS,
+                ),
+                ne: region.point(
+                    -(dn * DEFAULT_SEARCH_SIZE_METERS - SEARCH_BOX_OVERLAP_METERS),
+                    (de + 1) * DEFAULT_SEARCH_SIZE_METERS + SEARCH_BOX_OVERLAP_METERS,
+                ),
+            });
+        }
+    }
+    grid
+}
+
+/// Print the search grid for the region, without searching it, to show what a scrape would cost
+/// and cover.
+fn run_plan(boundary: Option<&str>) -> ::std::io::Result<()> {
+    // Nothing is written or filtered, so where listings would go and which state venues must be in
+    // don't matter.
+    let data_dir = ScrapeOptions::default().output_dir;
+    let region = match boundary {
+        Some(path) => {
+            let bounds = Boundary::load(path)?.bounding_box();
+            Region::covering(&format!("boundary {}", path), &bounds, None, &data_dir)
+        }
+        None => Region::manhattan(&data_dir),
+    };
+    let grid = search_grid(&region);
+
+    println!(
+        "{:>5}  {:>11}  {:>11}  {:>11}  {:>11}",
+        "Box", "SW lat", "SW lng", "NE lat", "NE lng"
+    );
+    for (number, search_box) in grid.iter().enumerate() {
+        println!(
+            "{:>5}  {:>11.6}  {:>11.6}  {:>11.6}  {:>11.6}",
+            number + 1,
+            search_box.sw.latitude,
+            search_box.sw.longitude,
+            search_box.ne.latitude,
+            search_box.ne.longitude
+        );
+    }
+
+    let box_side_meters = DEFAULT_SEARCH_SIZE_METERS + 2 * SEARCH_BOX_OVERLAP_METERS;
+    let square_km = |meters: f64| meters / 1_000_000.0;
+    let region_area = square_km(region.width_meters as f64 * region.height_meters as f64);
+    let searched_area = square_km((box_side_meters as f64).powi(2) * grid.len() as f64);
+    println!();
+    println!(
+        "Region: {} m x {} m, {:.1} km²",
+        region.width_meters, region.height_meters, region_area
+    );
+    println!(
+        "Boxes: {} of {} m x {} m, searching {:.1} km² ({:.3}x the region, with overlap)",
+        grid.len(),
+        box_side_meters,
+        box_side_meters,
+        searched_area,
+        searched_area / region_area
+    );
+    Ok(())
+}
+
+/// Progress through the search of a region, saved so an interrupted scrape can resume.
+#[derive(Serialize, Deserialize)]
+struct SearchCheckpoint {
+    /// What is being searched, so that a different search doesn't resume from the checkpoint.
+    search: String,
+    /// Boxes yet to be searched, with how many times each has been split from a grid square.
+    unexplored: Vec<(BoundingBox, usize)>,
+    bars: Vec<FoursquareBar>,
+    venue_boxes: Vec<VenueBox>,
+    total_large: usize,
+    total_large_handled: usize,
+    deepest: usize,
+    capped_boxes: usize,
+    /// Boxes split into quadrants for having too many venues to list at once.
+    #[serde(default)]
+    subdivisions: usize,
+}
+
+impl SearchCheckpoint {
+    /// The start of a search of the region.
+    fn new(search: String, region: &Region) -> Self {
+        // Explore the grid squares one by one to build the place database.
+        let unexplored: Vec<(BoundingBox, usize)> = search_grid(region)
+            .into_iter()
+            .map(|grid_square| (grid_square, 0))
+            .collect();
+        Self {
+            search,
+            total_large: unexplored.len(),
+            unexplored,
+            bars: Vec::new(),
+            venue_boxes: Vec::new(),
+            total_large_handled: 0,
+            deepest: 0,
+            capped_boxes: 0,
+            subdivisions: 0,
+        }
+    }
+}
+
+/// Search the region for bars in each of `categories`, with `search` listing the venues of one
+/// category in a box. If `venue_boxes` is given, the box each bar was found in is recorded there.
+///
+/// Boxes with too many venues to list at once are split into quadrants, down to
+/// `--max-subdivision-depth` levels below the initial grid. A box at that depth is accepted with
+/// the venues Foursquare returned, which may miss some.
+///
+/// Progress is saved to a checkpoint every `--checkpoint-interval` boxes, and with `--resume` the
+/// search continues from the checkpoint if there is one for the same search.
+fn get_bars<F>(
+    region: &Region,
+    categories: &[&str],
+    options: &ScrapeOptions,
+    venue_boxes: Option<&mut Vec<VenueBox>>,
+    summary: &mut ScrapeSummary,
+    mut search: F,
+) -> Result<Vec<FoursquareBar>, ScrapeError>
+where
+    F: FnMut(&BoundingBox, &str) -> Result<Vec<FoursquareBar>, ScrapeError>,
+{
+    let description = format!(
+        "{},{} {}x{}m category {} depth {:?}",
+        region.top_left.latitude,
+        region.top_left.longitude,
+        region.width_meters,
+        region.height_meters,
+        categories.join(","),
+        options.max_subdivision_depth
+    );
+    let checkpoint_path = region.checkpoint_path();
+    let resumed: Option<SearchCheckpoint> = if options.resume {
+        checkpoint::load(&checkpoint_path).map_err(|err| ScrapeError::io(&checkpoint_path, err))?
+    } else {
+        None
+    };
+    let mut progress = match resumed {
+        Some(resumed) if resumed.search == description => {
+            eprintln!(
+                "Resuming the search with {} boxes left to search.",
+                resumed.unexplored.len()
+            );
+            resumed
+        }
+        Some(_) => {
+            return Err(ScrapeError::Config(format!(
+                "The checkpoint {} is for a different search. Remove it or run without --resume.",
+                checkpoint_path
+            )));
+        }
+        None => SearchCheckpoint::new(description, region),
+    };
+
+    let mut searched_since_checkpoint: usize = 0;
+    while let Some((next, depth)) = progress.unexplored.pop() {
+        progress.deepest = progress.deepest.max(depth);
+
+        // Each category is searched separately, so each gets the full allowance of results. A venue
+        // in several of them is found more than once, and is only kept once by the caller.
+        let mut venues = Vec::new();
+        let mut saturated = false;
+        for category in categories {
+            let mut found = search(&next, category)?;
+            saturated |= found.len() == FOURSQUARE_MAX_VENUES_PER_QUERY;
+            venues.append(&mut found);
+        }
+
+        if saturated {
+            // We got 50 venue results in a category, which is the maximum. This means there are
+            // more in this geographic quadrant and we need to break it down further to retrieve
+            // them fully.
+            if options
+                .max_subdivision_depth
+                .is_none_or(|max_depth| depth < max_depth)
+            {
+                progress.unexplored.extend(
+                    split_to_quadrants(&next)
+                        .iter()
+                        .map(|quadrant| (quadrant.clone(), depth + 1)),
+                );
+                progress.subdivisions += 1;
+                continue;
+            }
+            progress.capped_boxes += 1;
+            eprintln!(
+                "Box {},{} to {},{} still has too many venues at the maximum subdivision depth \
+                 of {}. Keeping the first {} of each category.",
+                next.sw.latitude,
+                next.sw.longitude,
+                next.ne.latitude,
+                next.ne.longitude,
+                depth,
+                FOURSQUARE_MAX_VENUES_PER_QUERY
+            );
+        }
+
+        if venue_boxes.is_some() {
+            progress
+                .venue_boxes
+                .extend(venues.iter().map(|venue| VenueBox {
+                    id: venue.id.clone(),
+                    name: venue.name.clone(),
+                    sw: [next.sw.latitude, next.sw.longitude],
+                    ne: [next.ne.latitude, next.ne.longitude],
+                }));
+        }
+        progress.bars.append(&mut venues);
+        if progress.unexplored.len() < progress.total_large - progress.total_large_handled {
+            progress.total_large_handled += 1;
+            eprintln!(
+                "Processed {}/{} large quadrants. Found {} bars.",
+                progress.total_large_handled,
+                progress.total_large,
+                progress.bars.len()
+            );
+        }
+
+        searched_since_checkpoint += 1;
+        if searched_since_checkpoint >= options.checkpoint_interval {
+            checkpoint::save(&checkpoint_path, &progress)
+                .map_err(|err| ScrapeError::io(&checkpoint_path, err))?;
+            searched_since_checkpoint = 0;
+        }
+    }
+    // Save the finished search too, so that resuming skips straight to fetching tips.
+    checkpoint::save(&checkpoint_path, &progress)
+        .map_err(|err| ScrapeError::io(&checkpoint_path, err))?;
+
+    eprintln!(
+        "Subdivided boxes up to {} levels deep. {} boxes were cut off at the maximum depth.",
+        progress.deepest, progress.capped_boxes
+    );
+    summary.subdivisions = progress.subdivisions;
+    if let Some(venue_boxes) = venue_boxes {
+        venue_boxes.append(&mut progress.venue_boxes);
+    }
+    Ok(progress.bars)
+}
+
+/// Read a list of Foursquare venue ids, one per line. Blank lines and lines starting with `#` are
+/// ignored.
+fn read_venue_ids(path: &str) -> ::std::io::Result<Vec<String>> {
+    Ok(::std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Fetch the details of specific venues with `fetch`, rather than discovering them by searching the
+/// region.
+///
+/// Venues Foursquare doesn't know about are logged and skipped.
+fn get_bars_by_id<F>(venue_ids: &[String], mut fetch: F) -> Result<Vec<FoursquareBar>, ScrapeError>
+where
+    F: FnMut(&str) -> Result<FoursquareBar, ScrapeError>,
+{
+    let mut bars: Vec<FoursquareBar> = Vec::new();
+    for venue_id in venue_ids {
+        let venue = match fetch(venue_id) {
+            Ok(venue) => venue,
+            Err(ScrapeError::Http {
+                status: Some(status),
+                ..
+            }) if status.is_client_error() => {
+                eprintln!(
+                    "Skipping venue {}, Foursquare responded {}.",
+                    venue_id, status
+                );
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        bars.push(venue);
+    }
+
+    Ok(bars)
+}
+
+/// Keep the tips of `bar` within `--max-tips-read-per-bar` and `--max-tip-chars`, dropping the
+/// rest.
+///
+/// This guards against broken responses, which could otherwise have us holding and searching huge
+/// amounts of text.
+fn limit_tips_read(
+    bar: &FoursquareBar,
+    mut items: Vec<FoursquareTip>,
+    options: &ScrapeOptions,
+) -> Vec<FoursquareTip> {
+    if items.len() > options.max_tips_read_per_bar {
+        eprintln!(
+            "Venue {} ({}) returned {} tips, only reading the first {}.",
+            bar.id,
+            bar.name,
+            items.len(),
+            options.max_tips_read_per_bar
+        );
+        items.truncate(options.max_tips_read_per_bar);
+    }
+    let tip_count_before = items.len();
+    items.retain(|tip| tip.text.chars().count() <= options.max_tip_chars);
+    if items.len() < tip_count_before {
+        eprintln!(
+            "Skipping {} tips of venue {} ({}) longer than {} characters.",
+            tip_count_before - items.len(),
+            bar.id,
+            bar.name,
+            options.max_tip_chars
+        );
+    }
+    items
+}
+
+/// What we know about a tip which matched, used to pick the best tips to store.
+#[derive(Default)]
+struct TipEvidence {
+    /// Number of search phrases the tip matched.
+    phrases: usize,
+    /// Unix timestamp of when the tip was written.
+    created_at: Option<i64>,
+}
+
+/// Keep the `max_tips` best tips according to `selection`, along with their duplicate counts.
+/// Tips which rank equally keep the order Foursquare returned them in.
+fn select_tips(
+    mut tips: Vec<(Tip, usize)>,
+    evidence: &HashMap<String, TipEvidence>,
+    max_tips: usize,
+    selection: TipSelection,
+) -> Vec<(Tip, usize)> {
+    let phrases = |tip: &Tip| evidence.get(&tip.text).map_or(0, |evidence| evidence.phrases);
+    let created_at = |tip: &Tip| evidence.get(&tip.text).and_then(|evidence| evidence.created_at);
+    let length = |tip: &Tip| tip.text.chars().count();
+    tips.sort_by(|(a, _), (b, _)| match selection {
+        TipSelection::MostMatched => phrases(b)
+            .cmp(&phrases(a))
+            .then_with(|| length(b).cmp(&length(a))),
+        TipSelection::Longest => length(b).cmp(&length(a)),
+        TipSelection::MostRecent => created_at(b).cmp(&created_at(a)),
+    });
+    tips.truncate(max_tips);
+    tips
+}
+
+/// Order bars best first according to `ranking`, then drop all but the first `max_bars`.
+fn cap_output_bars(bars: &mut Vec<Bar>, region: &Region, max_bars: usize, ranking: OutputRanking) {
+    let centroid = region.centroid();
+    let distance = |bar: &Bar| {
+        distance_latlong(
+            &LatLong {
+                latitude: bar.lat,
+                longitude: bar.lng,
+            },
+            &centroid,
+        )
+    };
+
+    bars.sort_by(|a, b| {
+        let by_tips = b.tips.len().cmp(&a.tips.len());
+        let by_distance = distance(a).total_cmp(&distance(b));
+        let by_score = b.score.total_cmp(&a.score);
+        match ranking {
+            OutputRanking::TipCount => by_tips.then(by_distance),
+            OutputRanking::Centrality => by_distance.then(by_tips),
+            OutputRanking::Score => by_score.then(by_tips),
+        }
+    });
+    bars.truncate(max_bars);
+}
+
+/// Fetch the tips of each of `bars`, with up to `concurrency` requests in flight at once, returning
+/// them in the same order.
+///
+/// The first error stops the remaining fetches and is returned.
+fn fetch_tips(
+    client: &ApiClient,
+    credentials: &Credentials,
+    bars: &[(FoursquareBar, LatLong)],
+    concurrency: usize,
+) -> Result<Vec<Vec<FoursquareTip>>, ScrapeError> {
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let mut fetched: Vec<Vec<FoursquareTip>> = Vec::new();
+    fetched.resize_with(bars.len(), Vec::new);
+
+    let first_error = thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (next, failed) = (&next, &failed);
+            scope.spawn(move || {
+                // Each worker takes the next bar nobody has fetched yet, until there are none or
+                // another has failed.
+                while !failed.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let bar = match bars.get(index) {
+                        Some((bar, _)) => bar,
+                        None => break,
+                    };
+                    if sender.send((index, credentials.tips(client, &bar.id))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // The results end once every worker is done with its sender.
+        drop(sender);
+
+        let mut first_error = None;
+        let mut reported_percent: Option<usize> = None;
+        for (received, (index, result)) in receiver.into_iter().enumerate() {
+            match result {
+                Ok(tips) => fetched[index] = tips,
+                Err(err) => {
+                    failed.store(true, Ordering::Relaxed);
+                    first_error.get_or_insert(err);
+                }
+            }
+            let percent = (received + 1) * 100 / bars.len();
+            if reported_percent != Some(percent) {
+                eprintln!("Fetching details {}% complete.", percent);
+                reported_percent = Some(percent);
+            }
+        }
+        first_error
+    });
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(fetched),
+    }
+}
+
+/// The coordinates of `bar`. If it has none, it is either logged and skipped, giving None, or ends
+/// the scrape, as `missing` says.
+fn venue_coordinates(
+    bar: &FoursquareBar,
+    missing: MissingCoordinates,
+) -> Result<Option<LatLong>, ScrapeError> {
+    match (bar.location.coordinates(), missing) {
+        (Some(coordinates), _) => Ok(Some(coordinates)),
+        (None, MissingCoordinates::Skip) => {
+            eprintln!(
+                "Skipping venue {} ({}) with missing coordinates {:?}.",
+                bar.id, bar.name, bar.location
+            );
+            Ok(None)
+        }
+        (None, MissingCoordinates::Abort) => Err(ScrapeError::MissingCoordinates {
+            id: bar.id.clone(),
+            name: bar.name.clone(),
+        }),
+    }
+}
+
+/// Scrape the bars serving picklebacks in `region`. With a `boundary`, venues outside it are
+/// discarded as well.
+fn scrape_pickleback_bars(
+    credentials: &Credentials,
+    region: &Region,
+    boundary: Option<&Boundary>,
+    options: &ScrapeOptions,
+    venue_boxes: Option<&mut Vec<VenueBox>>,
+    summary: &mut ScrapeSummary,
+) -> Result<Vec<Bar>, ScrapeError> {
+    assert!(region.width_meters % DEFAULT_SEARCH_SIZE_METERS == 0);
+    assert!(region.height_meters % DEFAULT_SEARCH_SIZE_METERS == 0);
+    let centroid = region.centroid();
+
+    let client = ApiClient::new(options).map_err(ScrapeError::Config)?;
+    let phrases_file = options.phrases_file.as_deref();
+    let search_phrases = phrases::effective(
+        phrases::load(phrases_file)
+            .map_err(|err| ScrapeError::io(phrases_file.unwrap_or(phrases::DEFAULT_PATH), err))?,
+    );
+    let bars: Vec<FoursquareBar> = match &options.venue_ids_file {
+        Some(path) => {
+            let venue_ids = read_venue_ids(path).map_err(|err| ScrapeError::io(path, err))?;
+            get_bars_by_id(&venue_ids, |venue_id| credentials.venue(&client, venue_id))?
+        }
+        None => get_bars(
+            region,
+            &credentials.categories(options),
+            options,
+            venue_boxes,
+            summary,
+            |bounds, category| credentials.search(&client, bounds, category),
+        )?,
+    };
+    summary.venues_found = bars.len();
+    let mut visited: HashSet<String> = HashSet::new();
+
+    // Pick out the venues worth fetching tips for, with their coordinates.
+    let mut candidates: Vec<(FoursquareBar, LatLong)> = Vec::new();
+    for bar in bars {
+        if visited.contains(&bar.id.clone()) {
+            continue;
+        }
+        visited.insert(bar.id.clone());
+
+        if let Some(state) = &region.state {
+            if bar.location.state.as_ref() != Some(state) {
+                continue;
+            }
+        }
+        summary.venues_in_state += 1;
+
+        // Venues without coordinates can never be suggested, so don't bother fetching their tips.
+        let coordinates = match venue_coordinates(&bar, options.missing_coordinates)? {
+            Some(coordinates) => coordinates,
+            None => continue,
+        };
+
+        // Foursquare occasionally returns venues far from the box we searched. Venues we asked for
+        // by id are wherever they are, so they are exempt.
+        if options.venue_ids_file.is_none()
+            && !region.contains(&coordinates, options.region_tolerance_meters)
+        {
+            eprintln!(
+                "Skipping venue {} ({}) far outside the region at {:?}.",
+                bar.id, bar.name, coordinates
+            );
+            continue;
+        }
+
+        if let Some(boundary) = boundary {
+            if !boundary.contains(&coordinates) {
+                eprintln!(
+                    "Skipping venue {} ({}) outside the boundary at {:?}.",
+                    bar.id, bar.name, coordinates
+                );
+                continue;
+            }
+        }
+
+        candidates.push((bar, coordinates));
+    }
+
+    if options.dry_run == Some(DryRun::Venues) {
+        println!(
+            "Found {} venues with {} requests. Fetching their tips would take {} more requests, \
+             not counting retries.",
+            summary.venues_found,
+            client.requests_made(),
+            candidates.len()
+        );
+        return Ok(Vec::new());
+    }
+
+    let fetched = fetch_tips(&client, credentials, &candidates, options.concurrency)?;
+    let mut pickle_bars: Vec<Bar> = Vec::new();
+    for ((bar, coordinates), items) in candidates.into_iter().zip(fetched) {
+        let items = limit_tips_read(&bar, items, options);
+        let mut tips: Vec<Tip> = Vec::new();
+        let mut evidence: HashMap<String, TipEvidence> = HashMap::new();
+        let mut newest_tip: Option<i64> = None;
+        for tip in items {
+            for search_phrase in &search_phrases {
+                if phrases::phrase_matches(&tip.text, search_phrase) {
+                    let kept = match tips.iter().position(|kept| kept.text == tip.text) {
+                        Some(index) => index,
+                        None => {
+                            tips.push(Tip {
+                                text: tip.text.clone(),
+                                matched: Vec::new(),
+                                lang: tip.lang.clone(),
+                            });
+                            tips.len() - 1
+                        }
+                    };
+                    if !tips[kept].matched.contains(search_phrase) {
+                        tips[kept].matched.push(search_phrase.clone());
+                    }
+                    let tip_evidence = evidence.entry(tip.text.clone()).or_default();
+                    tip_evidence.phrases += 1;
+                    tip_evidence.created_at = tip_evidence.created_at.max(tip.created_at);
+                    newest_tip = newest_tip.max(tip.created_at);
+                }
+            }
+        }
+
+        let mut collapsed =
+            dedup::collapse_near_duplicate_tips(tips, options.tip_similarity_threshold);
+        // The score and classification consider every tip, even those which aren't stored.
+        let tip_count = collapsed.len();
+        let seasonal = options.seasonal_cues.as_ref().map(|cues| {
+            collapsed
+                .iter()
+                .any(|(tip, _)| seasonal::has_seasonal_cue(&tip.text, cues))
+        });
+        if let Some(max_tips) = options.max_tips_per_bar {
+            collapsed = select_tips(collapsed, &evidence, max_tips, options.tip_selection);
+        }
+        let (tips, weights): (Vec<Tip>, Vec<usize>) = collapsed.into_iter().unzip();
+        let tip_weights = if options.keep_duplicate_counts {
+            weights
+        } else {
+            Vec::new()
+        };
+
+        if tips.len() > 0 {
+            let checkins = bar.stats.and_then(|stats| stats.checkins_count);
+            let score = score::quality_score(
+                &options.score_weights,
+                &ScoreInputs {
+                    tip_count,
+                    newest_tip_age_days: newest_tip
+                        .map(|created_at| (Utc::now().timestamp() - created_at) as f64 / 86400.0),
+                    rating: bar.rating,
+                    checkins,
+                },
+            );
+            pickle_bars.push(Bar {
+                id: bar.id,
+                name: bar.name,
+                lat: coordinates.latitude,
+                lng: coordinates.longitude,
+                tips: tips,
+                score,
+                tip_weights,
+                centroid_distance_miles: centroid_distance(options, &coordinates, &centroid),
+                rating: bar.rating,
+                popularity: checkins,
+                neighborhood: bar.location.city,
+                fsq_url: bar.canonical_url,
+                seasonal,
+                photo_url: bar.best_photo.as_ref().map(FoursquarePhoto::url),
+            });
+        }
+    }
+
+    // Emit the best bars first, so curated feeds can simply read from the top of the listing.
+    // Sorting first also means duplicates are merged into the best of them.
+    pickle_bars.sort_by(|a, b| b.score.total_cmp(&a.score));
+    summary.venues_with_tips = pickle_bars.len();
+    let mut pickle_bars = dedup::dedup_bars(pickle_bars);
+    if let Some(max_tips) = options.max_tips_per_bar {
+        // Merged bars may have gained tips beyond the cap, which come after their own.
+        for bar in &mut pickle_bars {
+            bar.tips.truncate(max_tips);
+            bar.tip_weights.truncate(max_tips);
+        }
+    }
+
+    if let Some(max_bars) = options.max_output_bars {
+        cap_output_bars(&mut pickle_bars, region, max_bars, options.output_ranking);
+    }
+
+    summary.requests = client.requests_made();
+    summary.retries = client.retries_made();
+    Ok(pickle_bars)
+}
+
+/// Print how many requests a scrape of `region` would make, as far as we can tell without making
+/// any.
+fn print_request_count(options: &ScrapeOptions, region: &Region) -> Result<(), ScrapeError> {
+    match &options.venue_ids_file {
+        Some(path) => {
+            let venue_ids = read_venue_ids(path).map_err(|err| ScrapeError::io(path, err))?;
+            println!(
+                "Would make {} venue details requests, and up to as many tips requests.",
+                venue_ids.len()
+            );
+        }
+        None => println!(
+            "Would make {} venue search requests for the initial grid, plus more for boxes with \
+             too many venues to list at once. Use --dry-run-venues to search and count the tips \
+             requests which would follow.",
+            search_grid(region).len() * options.category_ids.len().max(1)
+        ),
+    }
+    Ok(())
+}
+
+/// The region `options` asks to scrape, along with the boundary venues must be inside, if any.
+fn scrape_region(options: &ScrapeOptions) -> Result<(Region, Option<Boundary>), ScrapeError> {
+    // With a boundary we only search the rectangle around it, and discard venues that fall outside
+    // the boundary itself once we know where they are.
+    let boundary: Option<Boundary> = match &options.boundary {
+        Some(path) => Some(Boundary::load(path).map_err(|err| ScrapeError::io(path, err))?),
+        None => None,
+    };
+    let mut region = match (&options.region, &options.boundary, &boundary) {
+        (Some(region), _, _) => Region::from_options(region, &options.output_dir),
+        (None, Some(path), Some(boundary)) => {
+            let name = format!("boundary {}", path);
+            let state = options.region_state.clone();
+            Region::covering(&name, &boundary.bounding_box(), state, &options.output_dir)
+        }
+        _ => Region::manhattan(&options.output_dir),
+    };
+    if options.region_state.is_some() {
+        region.state = options.region_state.clone();
+    }
+    Ok((region, boundary))
+}
+
+/// Scrape Foursquare as `scrape` would with `options`, returning the listing instead of writing it
+/// out, for servers which serve it straight from memory.
+///
+/// Only the checkpoint is written to disk, so an interrupted scrape can resume, and it is removed
+/// once the scrape finishes. Options which only affect writing the listing, such as `--stdout` or
+/// `--partition-by-neighborhood`, are ignored, as are dry runs.
+pub fn scrape_listing(options: &ScrapeOptions) -> Result<Vec<Bar>, ScrapeError> {
+    let (region, boundary) = scrape_region(options)?;
+    let credentials = Credentials::from_env(options.api_version)?;
+    ::std::fs::create_dir_all(&region.data_dir)
+        .map_err(|err| ScrapeError::io(&region.data_dir, err))?;
+
+    let options = ScrapeOptions {
+        dry_run: None,
+        ..options.clone()
+    };
+    let mut summary = ScrapeSummary {
+        region: region.name.clone(),
+        ..ScrapeSummary::default()
+    };
+    let bars = scrape_pickleback_bars(
+        &credentials,
+        &region,
+        boundary.as_ref(),
+        &options,
+        None,
+        &mut summary,
+    )?;
+    let checkpoint_path = region.checkpoint_path();
+    checkpoint::remove(&checkpoint_path).map_err(|err| ScrapeError::io(&checkpoint_path, err))?;
+    Ok(bars)
+}
+
+/// Scrape Foursquare, write the listing to a dated file and point the current symlink at it, or
+/// just write the listing to standard output.
+fn run_scrape(options: &ScrapeOptions) -> Result<(), ScrapeError> {
+    let now: Date<Utc> = Utc::today();
+    let (region, boundary) = scrape_region(options)?;
+    if options.dry_run == Some(DryRun::Grid) {
+        return print_request_count(options, &region);
+    }
+
+    let credentials = Credentials::from_env(options.api_version)?;
+    ::std::fs::create_dir_all(&region.data_dir)
+        .map_err(|err| ScrapeError::io(&region.data_dir, err))?;
+
+    let date_path = format!("{}/{}.json", region.data_dir, now.format("%Y%m%d"));
+    let symlink_path = format!("{}/{}", region.data_dir, CURRENT_LISTING_FILE_NAME);
+
+    let started = Instant::now();
+    let mut summary = ScrapeSummary {
+        region: match &options.venue_ids_file {
+            Some(path) => format!("venues listed in {}", path),
+            None => region.name.clone(),
+        },
+        ..ScrapeSummary::default()
+    };
+    let mut venue_boxes = Vec::new();
+    let bars = scrape_pickleback_bars(
+        &credentials,
+        &region,
+        boundary.as_ref(),
+        options,
+        if options.debug_boxes {
+            Some(&mut venue_boxes)
+        } else {
+            None
+        },
+        &mut summary,
+    )?;
+    // The venue pass has reported what the rest of the scrape would take.
+    if options.dry_run.is_some() {
+        return Ok(());
+    }
+    summary.set_listing(&bars);
+    if options.debug_boxes {
+        let boxes_path = format!("{}/{}.boxes.json", region.data_dir, now.format("%Y%m%d"));
+        File::create(&boxes_path)
+            .and_then(|file| Ok(serde_json::to_writer_pretty(file, &venue_boxes)?))
+            .map_err(|err| ScrapeError::io(&boxes_path, err))?;
+    }
+
+    if options.partition_by_neighborhood {
+        let partition_dir = format!("{}/{}", region.data_dir, now.format("%Y%m%d"));
+        partition::write_partitions(&bars, ::std::path::Path::new(&partition_dir))
+            .map_err(|err| ScrapeError::io(&partition_dir, err))?;
+    }
+
+    if options.stdout {
+        serde_json::to_writer(::std::io::stdout().lock(), &bars)
+            .map_err(|err| ScrapeError::io("standard output", err.into()))?;
+    } else if options.output_format == OutputFormat::Csv {
+        // The server only reads JSON, so the listing it serves is left as it is.
+        let day = now.format("%Y%m%d");
+        let bars_path = format!("{}/{}.csv", region.data_dir, day);
+        File::create(&bars_path)
+            .and_then(|mut file| export::write_bars_csv(&bars, &mut file))
+            .map_err(|err| ScrapeError::io(&bars_path, err))?;
+        let tips_path = format!("{}/{}.tips.csv", region.data_dir, day);
+        File::create(&tips_path)
+            .and_then(|mut file| export::write_tips_csv(&bars, &mut file))
+            .map_err(|err| ScrapeError::io(&tips_path, err))?;
+    } else {
+        File::create(&date_path)
+            .and_then(|file| Ok(serde_json::to_writer_pretty(file, &bars)?))
+            .map_err(|err| ScrapeError::io(&date_path, err))?;
+
+        ::std::fs::canonicalize(&date_path)
+            .and_then(|target| {
+                promote::advance_symlink(&target, ::std::path::Path::new(&symlink_path))
+            })
+            .map_err(|err| ScrapeError::io(&symlink_path, err))?;
+    }
+    let checkpoint_path = region.checkpoint_path();
+    checkpoint::remove(&checkpoint_path).map_err(|err| ScrapeError::io(&checkpoint_path, err))?;
+
+    summary.duration = started.elapsed();
+    if !options.stdout {
+        let stats_path = format!("{}/{}.stats.json", region.data_dir, now.format("%Y%m%d"));
+        File::create(&stats_path)
+            .and_then(|file| Ok(serde_json::to_writer_pretty(file, &summary)?))
+            .map_err(|err| ScrapeError::io(&stats_path, err))?;
+    }
+    if options.quiet {
+        return Ok(());
+    }
+    // Standard output is taken by the listing itself with --stdout.
+    if options.stdout {
+        eprint!("{}", summary);
+    } else {
+        print!("{}", summary);
+    }
+    Ok(())
+}
+
+/// Convert the listing at `input` to KML, writing it to `output` or standard output.
+fn run_kml_export(input: &str, output: Option<&str>) -> ::std::io::Result<()> {
+    let bars = export::read_bars(input)?;
+    match output {
+        Some(path) => export::write_kml(&bars, &mut File::create(path)?),
+        None => export::write_kml(&bars, &mut ::std::io::stdout().lock()),
+    }
+}
+
+/// Render the listing at `input` as an HTML page, writing it to `output` or standard output.
+fn run_html_export(input: &str, output: Option<&str>) -> ::std::io::Result<()> {
+    let bars = export::read_bars(input)?;
+    match output {
+        Some(path) => export::write_html(&bars, &mut File::create(path)?),
+        None => export::write_html(&bars, &mut ::std::io::stdout().lock()),
+    }
+}
+
+/// Backfill fields from the `newer` listing into the `older` one, writing the result to `output` or
+/// standard output.
+fn run_backfill(
+    older: &str,
+    newer: &str,
+    output: Option<&str>,
+    overwrite: &[String],
+) -> ::std::io::Result<()> {
+    let mut older_bars: Vec<serde_json::Value> = serde_json::from_reader(File::open(older)?)?;
+    let newer_bars: Vec<serde_json::Value> = serde_json::from_reader(File::open(newer)?)?;
+    let matched = backfill::backfill(&mut older_bars, &newer_bars, overwrite);
+    eprintln!(
+        "Backfilled {} of {} bars from {}.",
+        matched,
+        older_bars.len(),
+        newer
+    );
+
+    // Reading the result back as bars fills in defaults for fields neither listing had.
+    let bars: Vec<Bar> = serde_json::from_value(serde_json::Value::Array(older_bars))?;
+    match output {
+        Some(path) => serde_json::to_writer_pretty(File::create(path)?, &bars)?,
+        None => serde_json::to_writer_pretty(::std::io::stdout().lock(), &bars)?,
+    }
+    Ok(())
+}
+
+/// Run the scraper subcommand given by `args`, excluding the program name, exiting the process with
+/// an error status if it fails.
+pub fn run_cli(args: impl Iterator<Item = String>) {
+    let command = match options::parse_command(args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}", err);
+            ::std::process::exit(2);
+        }
+    };
+
+    match command {
+        Command::Scrape(options) => {
+            if let Err(err) = run_scrape(&options) {
+                eprintln!("Scrape failed: {}", err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::Kml { input, output } => {
+            if let Err(err) = run_kml_export(&input, output.as_deref()) {
+                eprintln!("Couldn't export {} as KML: {}", input, err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::Html { input, output } => {
+            if let Err(err) = run_html_export(&input, output.as_deref()) {
+                eprintln!("Couldn't export {} as HTML: {}", input, err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::Plan { boundary } => {
+            if let Err(err) = run_plan(boundary.as_deref()) {
+                eprintln!("Couldn't plan the search grid: {}", err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::Backfill {
+            older,
+            newer,
+            output,
+            overwrite,
+        } => {
+            if let Err(err) = run_backfill(&older, &newer, output.as_deref(), &overwrite) {
+                eprintln!("Couldn't backfill {} from {}: {}", older, newer, err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::LintPhrases { phrases_file } => {
+            let phrases = match phrases::load(phrases_file.as_deref()) {
+                Ok(phrases) => phrases,
+                Err(err) => {
+                    eprintln!("Couldn't read phrases: {}", err);
+                    ::std::process::exit(1);
+                }
+            };
+            let problems = phrases::lint(&phrases);
+            for problem in &problems {
+                println!("{}: \"{}\"", problem, phrases[problem.number() - 1]);
+            }
+            if !problems.is_empty() {
+                ::std::process::exit(1);
+            }
+            println!("{} phrases, no problems found.", phrases.len());
+        }
+        Command::Promote {
+            file,
+            max_count_drop,
+            output_dir,
+            region_name,
+        } => {
+            let current = current_listing_path(&output_dir, region_name.as_deref());
+            if let Err(err) = promote::promote(&file, &current, max_count_drop) {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::Rollback {
+            output_dir,
+            region_name,
+        } => {
+            let current = current_listing_path(&output_dir, region_name.as_deref());
+            if let Err(err) = promote::rollback(&current) {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+        Command::SampleTips {
+            input,
+            count,
+            seed,
+            phrases_file,
+        } => {
+            let phrases = match phrases::load(phrases_file.as_deref()) {
+                Ok(phrases) => phrases::effective(phrases),
+                Err(err) => {
+                    eprintln!("Couldn't read phrases: {}", err);
+                    ::std::process::exit(1);
+                }
+            };
+            if let Err(err) = sample::print_sample(&input, count, seed, &phrases) {
+                eprintln!("{}", err);
+                ::std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A venue as Foursquare returns it from a search, at `location`.
+    fn venue(location: serde_json::Value) -> FoursquareBar {
+        serde_json::from_value(serde_json::json!({
+            "id": "4a9b0e2cf964a520373420e3",
+            "name": "Sweet Afton",
+            "location": location,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn named_regions_keep_their_listings_apart() {
+        assert_eq!(current_listing_path("static/data", None), "static/data/current.json");
+        assert_eq!(
+            current_listing_path("static/data", Some("New Orleans")),
+            "static/data/new-orleans/current.json"
+        );
+        assert_eq!(current_listing_path("/srv/data", Some("!")), "/srv/data/current.json");
+    }
+
+    #[test]
+    fn venue_with_coordinates_is_kept() {
+        let bar = venue(serde_json::json!({"lat": 40.76535, "lng": -73.918983, "state": "NY"}));
+        let coordinates = venue_coordinates(&bar, MissingCoordinates::Abort)
+            .unwrap()
+            .unwrap();
+        assert_eq!(coordinates.latitude, 40.76535);
+        assert_eq!(coordinates.longitude, -73.918983);
+    }
+
+    #[test]
+    fn venue_without_coordinates_is_skipped() {
+        let bar = venue(serde_json::json!({"state": "NY"}));
+        assert!(venue_coordinates(&bar, MissingCoordinates::Skip)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn venue_without_coordinates_aborts_with_an_error() {
+        let bar = venue(serde_json::json!({"lat": 40.76535, "state": "NY"}));
+        match venue_coordinates(&bar, MissingCoordinates::Abort) {
+            Err(ScrapeError::MissingCoordinates { id, .. }) => {
+                assert_eq!(id, "4a9b0e2cf964a520373420e3")
+            }
+            other => panic!("expected a missing coordinates error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn venue_with_coordinates_off_the_globe_counts_as_missing() {
+        let bar = venue(serde_json::json!({"lat": 91.0, "lng": -73.918983}));
+        assert!(venue_coordinates(&bar, MissingCoordinates::Skip)
+            .unwrap()
+            .is_none());
+    }
+
+    /// Lengths in meters of a degree of longitude and of latitude on the WGS84 ellipsoid, at
+    /// several latitudes.
+    const WGS84_DEGREE_METERS: &[(f64, i32, i32)] = &[
+        (0.0, 111_320, 110_574),
+        (30.0, 96_486, 110_852),
+        (45.0, 78_847, 111_132),
+        (60.0, 55_800, 111_412),
+        (75.0, 28_902, 111_618),
+    ];
+
+    /// How far offsets on the sphere may be from the WGS84 reference, as a fraction.
+    const SPHERE_TOLERANCE: f64 = 0.006;
+
+    fn origin(latitude: f64) -> LatLong {
+        LatLong {
+            latitude,
+            longitude: -74.0,
+        }
+    }
+
+    #[test]
+    fn offset_east_matches_the_reference_degree_of_longitude() {
+        for &(latitude, lng_meters, _) in WGS84_DEGREE_METERS {
+            for &latitude in &[latitude, -latitude] {
+                let offset = offset_latlong(&origin(latitude), 0, lng_meters);
+                assert_eq!(offset.latitude, latitude);
+                let degrees = offset.longitude + 74.0;
+                assert!((degrees - 1.0).abs() < SPHERE_TOLERANCE, "{} at {}", degrees, latitude);
+            }
+        }
+    }
+
+    #[test]
+    fn offset_north_matches_the_reference_degree_of_latitude() {
+        for &(latitude, _, lat_meters) in WGS84_DEGREE_METERS {
+            // Centered on the latitude the reference is for.
+            let start = origin(latitude - 0.5);
+            let offset = offset_latlong(&start, lat_meters, 0);
+            assert_eq!(offset.longitude, -74.0);
+            let degrees = offset.latitude - start.latitude;
+            assert!((degrees - 1.0).abs() < SPHERE_TOLERANCE, "{} at {}", degrees, latitude);
+        }
+    }
+
+    #[test]
+    fn offsets_agree_with_measured_distances() {
+        for &latitude in &[0.0, 40.7, 60.0, -33.9, 75.0] {
+            let source = origin(latitude);
+            for &(dn, de) in &[(10_000, 0), (0, 10_000), (-5_000, 0), (0, -20_000)] {
+                let meters = distance_latlong(&source, &offset_latlong(&source, dn, de))
+                    * METERS_PER_MILE;
+                // Along a parallel is slightly longer than the great circle distance measures.
+                let expected = f64::from(dn.abs() + de.abs());
+                assert!(
+                    (meters - expected).abs() < 1.0,
+                    "{} for {:?} at {}",
+                    meters,
+                    (dn, de),
+                    latitude
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_add_up_without_drifting() {
+        // Splitting a box into quadrants offsets by halves, which must meet where the whole did.
+        let source = origin(60.0);
+        let whole = offset_latlong(&source, 0, 40_000);
+        let halves = offset_latlong(&offset_latlong(&source, 0, 20_000), 0, 20_000);
+        assert!((whole.longitude - halves.longitude).abs() < 1e-9);
+        let whole = offset_latlong(&source, -40_000, 0);
+        let halves = offset_latlong(&offset_latlong(&source, -20_000, 0), -20_000, 0);
+        assert!((whole.latitude - halves.latitude).abs() < 1e-9);
+    }
+
+    /// Scrape options parsed from `args`, as given after `scrape` on the command line.
+    fn scrape_options(args: &[&str]) -> ScrapeOptions {
+        let args = ::std::iter::once(&"scrape")
+            .chain(args)
+            .map(|arg| arg.to_string());
+        match options::parse_command(args) {
+            Ok(Command::Scrape(options)) => *options,
+            Ok(_) => panic!("expected a scrape"),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// A bar in a listing with `tip_count` tips, `dn` meters north and `de` meters east of `from`.
+    fn listed_bar(id: &str, tip_count: usize, from: &LatLong, dn: i32, de: i32) -> Bar {
+        let at = offset_latlong(from, dn, de);
+        let tips: Vec<String> = (0..tip_count)
+            .map(|number| format!("Pickleback number {}", number))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "lat": at.latitude,
+            "lng": at.longitude,
+            "tips": tips,
+        }))
+        .unwrap()
+    }
+
+    fn ids(bars: &[Bar]) -> Vec<&str> {
+        bars.iter().map(|bar| bar.id.as_str()).collect()
+    }
+
+    #[test]
+    fn output_bars_are_capped_to_those_with_most_tips_then_most_central() {
+        let region = Region::manhattan("static/data");
+        let centroid = region.centroid();
+        let mut bars = vec![
+            listed_bar("far-pair", 2, &centroid, 5_000, 0),
+            listed_bar("near-single", 1, &centroid, 100, 0),
+            listed_bar("outlying-trio", 3, &centroid, -10_000, 2_000),
+            listed_bar("near-pair", 2, &centroid, 0, -500),
+        ];
+        cap_output_bars(&mut bars, &region, 3, OutputRanking::TipCount);
+        assert_eq!(ids(&bars), ["outlying-trio", "near-pair", "far-pair"]);
+
+        let mut bars = vec![
+            listed_bar("far-pair", 2, &centroid, 5_000, 0),
+            listed_bar("near-single", 1, &centroid, 100, 0),
+            listed_bar("near-pair", 2, &centroid, 0, -500),
+        ];
+        cap_output_bars(&mut bars, &region, 2, OutputRanking::Centrality);
+        assert_eq!(ids(&bars), ["near-single", "near-pair"]);
+    }
+
+    /// Matching tips with the number of phrases each matched and when it was written, with no
+    /// duplicates.
+    fn evidenced_tips(
+        tips: &[(&str, usize, i64)],
+    ) -> (Vec<(Tip, usize)>, HashMap<String, TipEvidence>) {
+        let evidence = tips
+            .iter()
+            .map(|&(text, phrases, created_at)| {
+                let evidence = TipEvidence {
+                    phrases,
+                    created_at: Some(created_at),
+                };
+                (text.to_string(), evidence)
+            })
+            .collect();
+        let tips = tips
+            .iter()
+            .map(|&(text, _, _)| {
+                let tip = Tip {
+                    text: text.to_string(),
+                    matched: Vec::new(),
+                    lang: None,
+                };
+                (tip, 1)
+            })
+            .collect();
+        (tips, evidence)
+    }
+
+    fn texts(tips: &[(Tip, usize)]) -> Vec<&str> {
+        tips.iter().map(|(tip, _)| tip.text.as_str()).collect()
+    }
+
+    #[test]
+    fn best_tips_are_kept_up_to_the_cap() {
+        let (tips, evidence) = evidenced_tips(&[
+            ("Pickleback", 1, 300),
+            ("Pickleback or pickle back, either way", 2, 100),
+            ("The picklebacks here are made with house brine", 1, 200),
+            ("Pickle backs!", 1, 400),
+        ]);
+        let select = |selection| {
+            let selected = select_tips(tips.clone(), &evidence, 2, selection);
+            texts(&selected)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            select(TipSelection::MostMatched),
+            [
+                "Pickleback or pickle back, either way",
+                "The picklebacks here are made with house brine"
+            ]
+        );
+        assert_eq!(
+            select(TipSelection::Longest),
+            [
+                "The picklebacks here are made with house brine",
+                "Pickleback or pickle back, either way"
+            ]
+        );
+        assert_eq!(
+            select(TipSelection::MostRecent),
+            ["Pickle backs!", "Pickleback"]
+        );
+    }
+
+    #[test]
+    fn tips_ranking_equally_keep_their_order() {
+        let (tips, evidence) = evidenced_tips(&[
+            ("Pickleback!", 1, 100),
+            ("Pickleback?", 1, 100),
+            ("Pickleback.", 1, 100),
+        ]);
+        let selected = select_tips(tips.clone(), &evidence, 5, TipSelection::MostRecent);
+        assert_eq!(
+            texts(&selected),
+            ["Pickleback!", "Pickleback?", "Pickleback."]
+        );
+        let selected = select_tips(tips, &evidence, 0, TipSelection::MostMatched);
+        assert!(selected.is_empty());
+    }
+
+    #[test]
+    fn manhattan_is_searched_in_a_grid_of_boxes() {
+        let region = Region::manhattan("static/data");
+        let grid = search_grid(&region);
+        // 33 km by 48 km in 3 km boxes.
+        assert_eq!(grid.len(), 11 * 16);
+
+        // Neighboring boxes overlap by twice the margin, and the grid covers the whole region.
+        let meters = |a: &LatLong, b: &LatLong| distance_latlong(a, b) * METERS_PER_MILE;
+        let first = &grid[0];
+        let below = &grid[1];
+        let overlap = meters(
+            &LatLong {
+                latitude: first.sw.latitude,
+                longitude: first.sw.longitude,
+            },
+            &LatLong {
+                latitude: below.ne.latitude,
+                longitude: first.sw.longitude,
+            },
+        );
+        assert!((overlap - 2.0 * SEARCH_BOX_OVERLAP_METERS as f64).abs() < 0.1);
+        assert!(first.ne.latitude > region.top_left.latitude);
+        assert!(first.sw.longitude < region.top_left.longitude);
+        let last = &grid[grid.len() - 1];
+        let far_corner = region.point(-region.height_meters, region.width_meters);
+        assert!(last.sw.latitude < far_corner.latitude);
+        assert!(last.ne.longitude > far_corner.longitude);
+    }
+
+    #[test]
+    fn venues_at_wildly_wrong_coordinates_are_outside_the_region() {
+        let region = Region::manhattan("static/data");
+        let tolerance = scrape_options(&[]).region_tolerance_meters;
+        let inside = |location| {
+            let coordinates = venue_coordinates(&venue(location), MissingCoordinates::Skip)
+                .unwrap()
+                .unwrap();
+            region.contains(&coordinates, tolerance)
+        };
+        assert!(inside(
+            serde_json::json!({"lat": 40.76535, "lng": -73.918983})
+        ));
+        // The longitude's sign flipped, putting the bar in Kyrgyzstan.
+        assert!(!inside(
+            serde_json::json!({"lat": 40.76535, "lng": 73.918983})
+        ));
+        // Null Island, where venues with unset coordinates end up.
+        assert!(!inside(serde_json::json!({"lat": 0.0, "lng": 0.0})));
+    }
+
+    #[test]
+    fn venues_just_outside_the_region_are_within_tolerance() {
+        let region = Region::manhattan("static/data");
+        let north_of = |meters: i32| region.point(meters, region.width_meters / 2);
+        assert!(region.contains(&north_of(-10), 0));
+        assert!(!region.contains(&north_of(10), 0));
+        assert!(region.contains(&north_of(10), 100));
+        assert!(!region.contains(&north_of(200), 100));
+        let west_of = |meters: i32| region.point(-region.height_meters / 2, -meters);
+        assert!(region.contains(&west_of(50), 100));
+        assert!(!region.contains(&west_of(150), 100));
+    }
+
+    #[test]
+    fn centroid_distance_is_annotated_when_requested() {
+        let region = Region::from_options(
+            &RegionOptions {
+                name: "test".to_string(),
+                top_left: (40.8, -74.0),
+                width_meters: 3000,
+                height_meters: 3000,
+            },
+            "static/data",
+        );
+        let centroid = region.centroid();
+        assert!(
+            (centroid.latitude - (40.8 - (1500.0 / EARTH_RADIUS_METERS).to_degrees())).abs() < 1e-9
+        );
+
+        // A mile due north of the centroid.
+        let bar = region.point(-1500 + 1609, 1500);
+        let options = scrape_options(&["--annotate-centroid-distance"]);
+        let distance = centroid_distance(&options, &bar, &centroid).unwrap();
+        assert!((distance - 1.0).abs() < 0.001, "{}", distance);
+
+        assert_eq!(
+            centroid_distance(&scrape_options(&[]), &bar, &centroid),
+            None
+        );
+    }
+
+    #[test]
+    fn custom_regions_are_rounded_up_to_whole_boxes() {
+        let region = Region::from_options(
+            &RegionOptions {
+                name: "New Orleans".to_string(),
+                top_left: (30.05, -90.15),
+                width_meters: 7000,
+                height_meters: 3000,
+            },
+            "static/data",
+        );
+        assert_eq!((region.width_meters, region.height_meters), (9000, 3000));
+        assert_eq!(search_grid(&region).len(), 3);
+    }
+
+    /// A one box region, whose data directory is a new temporary directory named after `name`.
+    fn search_region(name: &str) -> Region {
+        let name = format!("pickletrack-{}-{}", name, std::process::id());
+        let region = Region::from_options(
+            &RegionOptions {
+                name,
+                top_left: (40.8, -74.0),
+                width_meters: DEFAULT_SEARCH_SIZE_METERS,
+                height_meters: DEFAULT_SEARCH_SIZE_METERS,
+            },
+            std::env::temp_dir().to_str().unwrap(),
+        );
+        std::fs::create_dir_all(&region.data_dir).unwrap();
+        region
+    }
+
+    /// `count` venues as a search returns them, with ids starting with `prefix`.
+    fn found_venues(prefix: &str, count: usize) -> Vec<FoursquareBar> {
+        (0..count)
+            .map(|index| {
+                serde_json::from_value(serde_json::json!({
+                    "id": format!("{}-{}", prefix, index),
+                    "name": "Bar",
+                    "location": {},
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn corners(bounds: &BoundingBox) -> ([f64; 2], [f64; 2]) {
+        (
+            [bounds.sw.latitude, bounds.sw.longitude],
+            [bounds.ne.latitude, bounds.ne.longitude],
+        )
+    }
+
+    #[test]
+    fn venues_are_tagged_with_the_box_they_were_found_in() {
+        let region = search_region("venue-boxes");
+        let mut searched: Vec<BoundingBox> = Vec::new();
+        let mut venue_boxes = Vec::new();
+        let bars = get_bars(
+            &region,
+            &["bars"],
+            &scrape_options(&[]),
+            Some(&mut venue_boxes),
+            &mut ScrapeSummary::default(),
+            |bounds, _| {
+                searched.push(bounds.clone());
+                // The grid square is full, so it's split into quadrants, each with two venues.
+                Ok(match searched.len() {
+                    1 => found_venues("crowded", FOURSQUARE_MAX_VENUES_PER_QUERY),
+                    box_number => found_venues(&format!("box{}", box_number), 2),
+                })
+            },
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&region.data_dir).unwrap();
+
+        assert_eq!(searched.len(), 5);
+        assert_eq!(corners(&searched[0]), corners(&search_grid(&region)[0]));
+        assert_eq!(bars.len(), 8);
+        assert_eq!(venue_boxes.len(), 8);
+        for (venue_box, bar) in venue_boxes.iter().zip(&bars) {
+            assert_eq!(venue_box.id, bar.id);
+            // The venues of box N were listed by the Nth search.
+            let box_number: usize = bar.id[3..4].parse().unwrap();
+            assert_eq!(
+                (venue_box.sw, venue_box.ne),
+                corners(&searched[box_number - 1])
+            );
+        }
+    }
+
+    #[test]
+    fn venue_boxes_are_only_recorded_when_asked_for() {
+        let region = search_region("no-venue-boxes");
+        let bars = get_bars(
+            &region,
+            &["bars"],
+            &scrape_options(&[]),
+            None,
+            &mut ScrapeSummary::default(),
+            |_, _| Ok(found_venues("box", 3)),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&region.data_dir).unwrap();
+        assert_eq!(bars.len(), 3);
+    }
+
+    /// Search a region in which every box is full, with `--max-subdivision-depth` set to `depth`.
+    /// Returns the number of boxes searched and of venues found.
+    fn search_crowded_region(depth: &str, summary: &mut ScrapeSummary) -> (usize, usize) {
+        let region = search_region(&format!("crowded-{}", depth));
+        let mut searched = 0;
+        let bars = get_bars(
+            &region,
+            &["bars"],
+            &scrape_options(&["--max-subdivision-depth", depth]),
+            None,
+            summary,
+            |_, _| {
+                searched += 1;
+                Ok(found_venues(
+                    &format!("box{}", searched),
+                    FOURSQUARE_MAX_VENUES_PER_QUERY,
+                ))
+            },
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&region.data_dir).unwrap();
+        (searched, bars.len())
+    }
+
+    #[test]
+    fn subdivision_stops_at_the_maximum_depth() {
+        let mut summary = ScrapeSummary::default();
+        // The grid square, then its 4 quadrants, then their 16.
+        assert_eq!(search_crowded_region("2", &mut summary), (21, 16 * 50));
+        assert_eq!(summary.subdivisions, 5);
+
+        let mut summary = ScrapeSummary::default();
+        assert_eq!(search_crowded_region("0", &mut summary), (1, 50));
+        assert_eq!(summary.subdivisions, 0);
+    }
+
+    #[test]
+    fn boxes_which_are_not_full_are_not_subdivided() {
+        let region = search_region("sparse");
+        let mut summary = ScrapeSummary::default();
+        let bars = get_bars(
+            &region,
+            &["bars"],
+            &scrape_options(&[]),
+            None,
+            &mut summary,
+            |_, _| Ok(found_venues("box", FOURSQUARE_MAX_VENUES_PER_QUERY - 1)),
+        )
+        .unwrap();
+        std::fs::remove_dir_all(&region.data_dir).unwrap();
+        assert_eq!(bars.len(), 49);
+        assert_eq!(summary.subdivisions, 0);
+    }
+
+    /// Tips as Foursquare returns them, with `texts`.
+    fn foursquare_tips(texts: &[String]) -> Vec<FoursquareTip> {
+        texts
+            .iter()
+            .map(|text| serde_json::from_value(serde_json::json!({ "text": text })).unwrap())
+            .collect()
+    }
+
+    fn tips_read(texts: &[String], args: &[&str]) -> Vec<String> {
+        let bar = found_venues("bar", 1).remove(0);
+        limit_tips_read(&bar, foursquare_tips(texts), &scrape_options(args))
+            .into_iter()
+            .map(|tip| tip.text)
+            .collect()
+    }
+
+    #[test]
+    fn oversized_tips_are_dropped() {
+        let texts = vec![
+            "Picklebacks!".to_string(),
+            "pickleback ".repeat(1000),
+            "x".repeat(2000),
+            "x".repeat(2001),
+        ];
+        // The default limit is 2000 characters.
+        assert_eq!(tips_read(&texts, &[]), [texts[0].as_str(), &texts[2]]);
+        assert_eq!(
+            tips_read(&texts, &["--max-tip-chars", "20"]),
+            ["Picklebacks!"]
+        );
+        // Characters are counted rather than bytes.
+        let accented = vec!["é".repeat(20)];
+        assert_eq!(tips_read(&accented, &["--max-tip-chars", "20"]), accented);
+    }
+
+    #[test]
+    fn only_the_first_tips_of_a_venue_are_read() {
+        let texts: Vec<String> = (0..600).map(|index| format!("Tip {}", index)).collect();
+        assert_eq!(tips_read(&texts, &[]), &texts[..500]);
+        assert_eq!(
+            tips_read(&texts, &["--max-tips-read-per-bar", "3"]),
+            ["Tip 0", "Tip 1", "Tip 2"]
+        );
+        // Tips are capped before the long ones are dropped.
+        let texts = vec!["x".repeat(3000), "Tip".to_string(), "Tip 2".to_string()];
+        assert_eq!(
+            tips_read(&texts, &["--max-tips-read-per-bar", "2"]),
+            ["Tip"]
+        );
+    }
+
+    /// The error for a request Foursquare answered with `status`.
+    fn http_error(status: u16) -> ScrapeError {
+        ScrapeError::Http {
+            url: "https://api.foursquare.com/v2/venues/missing".to_string(),
+            status: Some(reqwest::StatusCode::from_u16(status).unwrap()),
+            message: format!("HTTP {}", status),
+            retry_after: None,
+        }
+    }
+
+    #[test]
+    fn venue_ids_are_read_skipping_comments_and_blank_lines() {
+        let path = std::env::temp_dir().join(format!("pickletrack-ids-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Bars to check\n4a9b0e2cf964a520373420e3\n\n  49d7e6b5f964a5209c5c1fe3  \n",
+        )
+        .unwrap();
+        let ids = read_venue_ids(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            ids,
+            ["4a9b0e2cf964a520373420e3", "49d7e6b5f964a5209c5c1fe3"]
+        );
+    }
+
+    #[test]
+    fn venues_foursquare_does_not_know_are_skipped() {
+        let venue_ids: Vec<String> = vec!["a".into(), "gone".into(), "b".into()];
+        let mut fetched = Vec::new();
+        let bars = get_bars_by_id(&venue_ids, |venue_id| {
+            fetched.push(venue_id.to_string());
+            match venue_id {
+                "gone" => Err(http_error(404)),
+                _ => Ok(found_venues(venue_id, 1).remove(0)),
+            }
+        })
+        .unwrap();
+        assert_eq!(fetched, venue_ids);
+        let ids: Vec<&str> = bars.iter().map(|bar| bar.id.as_str()).collect();
+        assert_eq!(ids, ["a-0", "b-0"]);
+    }
+
+    #[test]
+    fn other_venue_errors_stop_the_scrape() {
+        let venue_ids: Vec<String> = vec!["a".into(), "down".into(), "b".into()];
+        let mut fetched = 0;
+        let result = get_bars_by_id(&venue_ids, |venue_id| {
+            fetched += 1;
+            match venue_id {
+                "down" => Err(http_error(503)),
+                _ => Ok(found_venues(venue_id, 1).remove(0)),
+            }
+        });
+        match result {
+            Err(ScrapeError::Http { status, .. }) => {
+                assert_eq!(status, Some(reqwest::StatusCode::SERVICE_UNAVAILABLE))
+            }
+            _ => panic!("expected the 503 to be returned"),
+        }
+        assert_eq!(fetched, 2);
+    }
+
+    #[test]
+    fn venue_urls_are_written_only_when_known() {
+        let centroid = Region::manhattan("static/data").centroid();
+        let mut bar = listed_bar("a", 1, &centroid, 0, 0);
+        assert!(serde_json::to_value(&bar).unwrap().get("fsq_url").is_none());
+        bar.fsq_url = Some("https://foursquare.com/v/a".to_string());
+        let json = serde_json::to_value(&bar).unwrap();
+        assert_eq!(json["fsq_url"], "https://foursquare.com/v/a");
+
+        let venue: FoursquareBar = serde_json::from_value(serde_json::json!({
+            "id": "a",
+            "name": "A",
+            "location": {},
+            "canonicalUrl": "https://foursquare.com/v/a",
+        }))
+        .unwrap();
+        assert_eq!(
+            venue.canonical_url.as_deref(),
+            Some("https://foursquare.com/v/a")
+        );
+    }
+
+    #[test]
+    fn output_bars_are_unlimited_by_default() {
+        let options = scrape_options(&[]);
+        assert_eq!(options.max_output_bars, None);
+        assert_eq!(options.output_ranking, OutputRanking::TipCount);
+        assert_eq!(
+            scrape_options(&["--max-output-bars", "25"]).max_output_bars,
+            Some(25)
+        );
+    }
+}
@@ -0,0 +1,790 @@
+//! Command line options for the scraper.
+//!
+//! The first argument optionally names a subcommand, running a scrape if it is omitted. Options are
+//! passed as `--name value` pairs, or just `--name` for switches. Anything not given on the command
+//! line falls back to the defaults below.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::score::ScoreWeights;
+use crate::seasonal::DEFAULT_SEASONAL_CUES;
+use crate::FOURSQUARE_CATEGORY_ALIASES;
+
+/// Directory the listings of the default region are written to.
+const DEFAULT_OUTPUT_DIR: &str = "static/data";
+
+/// What to do with a venue that Foursquare returns without usable coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingCoordinates {
+    /// Log the venue and leave it out of the listing.
+    Skip,
+    /// Stop the scrape.
+    Abort,
+}
+
+impl MissingCoordinates {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "skip" => Ok(MissingCoordinates::Skip),
+            "abort" => Ok(MissingCoordinates::Abort),
+            _ => Err(format!(
+                "Unknown missing coordinates policy \"{}\", expected \"skip\" or \"abort\"",
+                value
+            )),
+        }
+    }
+}
+
+/// Which version of the Foursquare API to scrape.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiVersion {
+    /// The venues API, authenticated with `CLIENT_ID` and `CLIENT_SECRET`. This is end of life.
+    V2,
+    /// The Places API, authenticated with `FOURSQUARE_API_KEY`.
+    V3,
+}
+
+impl ApiVersion {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "2" => Ok(ApiVersion::V2),
+            "3" => Ok(ApiVersion::V3),
+            _ => Err(format!(
+                "Unknown API version \"{}\", expected \"2\" or \"3\"",
+                value
+            )),
+        }
+    }
+}
+
+/// How far a dry run goes before stopping, instead of scraping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DryRun {
+    /// Count the venue searches of the initial grid, without making any requests.
+    Grid,
+    /// Search for venues, and count the tips requests which would follow.
+    Venues,
+}
+
+/// How bars are ranked when the output is capped to a maximum number of bars.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputRanking {
+    /// Bars with the most tips first, ties broken by distance to the region centroid.
+    TipCount,
+    /// Bars closest to the region centroid first, ties broken by tip count.
+    Centrality,
+    /// Bars with the highest quality score first, ties broken by tip count.
+    Score,
+}
+
+impl OutputRanking {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "tips" => Ok(OutputRanking::TipCount),
+            "central" => Ok(OutputRanking::Centrality),
+            "score" => Ok(OutputRanking::Score),
+            _ => Err(format!(
+                "Unknown output ranking \"{}\", expected \"tips\", \"central\" or \"score\"",
+                value
+            )),
+        }
+    }
+}
+
+/// The format a scrape writes its listing in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// The JSON listing the server loads.
+    Json,
+    /// A CSV file of bars and another of their tips, for spreadsheets.
+    Csv,
+}
+
+impl OutputFormat {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!(
+                "Unknown output format \"{}\", expected \"json\" or \"csv\"",
+                value
+            )),
+        }
+    }
+}
+
+/// Which tips are kept when a bar has more than the maximum stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TipSelection {
+    /// Tips matching the most search phrases first, ties broken by length.
+    MostMatched,
+    /// The longest tips first.
+    Longest,
+    /// The most recently written tips first.
+    MostRecent,
+}
+
+impl TipSelection {
+    fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "matched" => Ok(TipSelection::MostMatched),
+            "longest" => Ok(TipSelection::Longest),
+            "recent" => Ok(TipSelection::MostRecent),
+            _ => Err(format!(
+                "Unknown tip selection \"{}\", expected \"matched\", \"longest\" or \"recent\"",
+                value
+            )),
+        }
+    }
+}
+
+/// Look up the Foursquare category ID for a friendly name such as "Dive Bar" or "dive-bar".
+fn category_for_alias(alias: &str) -> Result<&'static str, String> {
+    let normalized = alias.trim().to_lowercase().replace(' ', "-");
+    FOURSQUARE_CATEGORY_ALIASES
+        .iter()
+        .find(|&&(name, _)| name == normalized)
+        .map(|&(_, id)| id)
+        .ok_or_else(|| {
+            let names: Vec<&str> = FOURSQUARE_CATEGORY_ALIASES
+                .iter()
+                .map(|&(name, _)| name)
+                .collect();
+            format!(
+                "Unknown category \"{}\", expected one of {}",
+                alias,
+                names.join(", ")
+            )
+        })
+}
+
+/// Check a Foursquare v2 category ID, which is 24 hexadecimal digits.
+fn parse_category_id(id: &str) -> Result<String, String> {
+    let id = id.trim();
+    if id.len() == 24 && id.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(id.to_lowercase())
+    } else {
+        Err(format!(
+            "Invalid Foursquare category ID \"{}\", expected 24 hexadecimal digits",
+            id
+        ))
+    }
+}
+
+/// Options controlling a scrape run.
+#[derive(Debug, Clone)]
+pub struct ScrapeOptions {
+    pub missing_coordinates: MissingCoordinates,
+    /// Maximum random pause inserted before each request to Foursquare.
+    pub max_request_jitter: Duration,
+    /// Cap on the number of bars written, keeping the best ranked. `None` keeps every bar.
+    pub max_output_bars: Option<usize>,
+    pub output_ranking: OutputRanking,
+    /// Region to scrape in place of Manhattan.
+    pub region: Option<RegionOptions>,
+    /// State venues must be in, as Foursquare records it. Defaults to "NY" for Manhattan and no
+    /// filter for other regions.
+    pub region_state: Option<String>,
+    /// GeoJSON file with a polygon boundary. Venues outside of it are discarded.
+    pub boundary: Option<String>,
+    pub score_weights: ScoreWeights,
+    /// File listing Foursquare venue ids to scrape, instead of searching the region for venues.
+    pub venue_ids_file: Option<String>,
+    /// Tips at least this similar (from 0 to 1) to an earlier tip of the same bar are collapsed
+    /// into it.
+    pub tip_similarity_threshold: f64,
+    /// Record how many near-duplicate tips collapsed into each stored tip.
+    pub keep_duplicate_counts: bool,
+    /// Record each bar's distance from the center of the scraped region.
+    pub annotate_centroid_distance: bool,
+    /// How far outside the scraped region a venue may lie before it is assumed to be bad data.
+    pub region_tolerance_meters: i32,
+    /// HTTP(S) proxy to send requests through, overriding the `HTTPS_PROXY` environment variable.
+    pub proxy: Option<String>,
+    /// Hosts to reach directly rather than through the proxy, overriding `NO_PROXY`.
+    pub no_proxy: Option<Vec<String>>,
+    /// Also write the listing split into one file per neighborhood.
+    pub partition_by_neighborhood: bool,
+    /// File of phrases a tip must contain to count as mentioning picklebacks, replacing the
+    /// built-in list.
+    pub phrases_file: Option<String>,
+    /// Write which search box each venue was found in to a sidecar file.
+    pub debug_boxes: bool,
+    /// Cap on the number of tips stored for each bar. `None` stores every matching tip.
+    pub max_tips_per_bar: Option<usize>,
+    pub tip_selection: TipSelection,
+    /// Cap on the number of tips read from the response for each bar. Tips beyond it are ignored.
+    pub max_tips_read_per_bar: usize,
+    /// Tips longer than this many characters are ignored.
+    pub max_tip_chars: usize,
+    /// Time after which a request to Foursquare is abandoned.
+    pub request_timeout: Duration,
+    /// Search Foursquare for every box, rather than reusing responses cached by earlier scrapes.
+    pub no_cache: bool,
+    /// Age after which a cached venue search response is ignored.
+    pub cache_ttl: Duration,
+    /// How many times a search box may be split into quadrants. `None` splits until every box
+    /// can be listed in full, which never ends if Foursquare keeps returning full pages.
+    pub max_subdivision_depth: Option<usize>,
+    /// Foursquare categories searched for venues, as v2 IDs. Empty searches bars.
+    pub category_ids: Vec<String>,
+    pub api_version: ApiVersion,
+    /// Directory the listings of the default region are written to. Regions given with
+    /// `--region-name` get a subdirectory of their own.
+    pub output_dir: String,
+    /// Write the listing to standard output rather than publishing it.
+    pub stdout: bool,
+    pub output_format: OutputFormat,
+    /// Report how many requests a scrape would make rather than scraping.
+    pub dry_run: Option<DryRun>,
+    /// Number of bars whose tips are fetched at once.
+    pub concurrency: usize,
+    /// Most times a request is retried while Foursquare is rate limiting us or overloaded.
+    pub max_retries: usize,
+    /// Fail the scrape on a Foursquare response missing fields we expect.
+    pub strict_schema: bool,
+    /// Continue the search from the checkpoint left by an interrupted scrape, if there is one.
+    pub resume: bool,
+    /// Number of boxes searched between checkpoints.
+    pub checkpoint_interval: usize,
+    /// Don't print a summary when the scrape finishes.
+    pub quiet: bool,
+    /// Cues which mark a bar as serving picklebacks only seasonally. `None` skips classification.
+    pub seasonal_cues: Option<Vec<String>>,
+}
+
+impl Default for ScrapeOptions {
+    fn default() -> Self {
+        Self {
+            missing_coordinates: MissingCoordinates::Skip,
+            max_request_jitter: Duration::from_millis(200),
+            max_output_bars: None,
+            output_ranking: OutputRanking::TipCount,
+            region: None,
+            region_state: None,
+            boundary: None,
+            score_weights: ScoreWeights::default(),
+            venue_ids_file: None,
+            tip_similarity_threshold: 0.9,
+            keep_duplicate_counts: false,
+            annotate_centroid_distance: false,
+            region_tolerance_meters: 1000,
+            proxy: None,
+            no_proxy: None,
+            partition_by_neighborhood: false,
+            phrases_file: None,
+            debug_boxes: false,
+            max_tips_per_bar: None,
+            // As many as we ask Foursquare for.
+            max_tips_read_per_bar: 500,
+            max_tip_chars: 2000,
+            request_timeout: Duration::from_secs(30),
+            no_cache: false,
+            cache_ttl: Duration::from_secs(60 * 60 * 24),
+            tip_selection: TipSelection::MostMatched,
+            // Ten splits take a 3000 meter box down to 3 meters, far denser than any real venues.
+            max_subdivision_depth: Some(10),
+            category_ids: Vec::new(),
+            api_version: ApiVersion::V2,
+            output_dir: DEFAULT_OUTPUT_DIR.to_string(),
+            stdout: false,
+            output_format: OutputFormat::Json,
+            dry_run: None,
+            concurrency: 4,
+            max_retries: 8,
+            strict_schema: false,
+            resume: false,
+            checkpoint_interval: 20,
+            quiet: false,
+            seasonal_cues: None,
+        }
+    }
+}
+
+/// A region to scrape in place of Manhattan.
+#[derive(Debug, Clone)]
+pub struct RegionOptions {
+    /// Name of the region, which its listing files are kept under.
+    pub name: String,
+    /// Latitude and longitude of the north west corner.
+    pub top_left: (f64, f64),
+    pub width_meters: i32,
+    pub height_meters: i32,
+}
+
+/// The action requested on the command line.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Scrape Foursquare and publish a new listing.
+    Scrape(Box<ScrapeOptions>),
+    /// Convert a listing to KML, writing to standard output if no output path is given.
+    Kml {
+        input: String,
+        output: Option<String>,
+    },
+    /// Render a listing as a static HTML page, writing to standard output if no output path is
+    /// given.
+    Html {
+        input: String,
+        output: Option<String>,
+    },
+    /// Print the grid of boxes a scrape would search, without searching.
+    Plan { boundary: Option<String> },
+    /// Copy fields from a newer listing into an older one, writing the result to standard output
+    /// if no output path is given.
+    Backfill {
+        older: String,
+        newer: String,
+        output: Option<String>,
+        /// Fields to take from the newer listing even when the older one has them.
+        overwrite: Vec<String>,
+    },
+    /// Check a phrase list for empty, duplicate and redundant phrases.
+    LintPhrases { phrases_file: Option<String> },
+    /// Validate a listing and make it the current one.
+    Promote {
+        file: String,
+        /// Largest fraction of the current listing's bars the new listing may be missing.
+        max_count_drop: f64,
+        /// Where the listings are kept, as for `ScrapeOptions::output_dir`.
+        output_dir: String,
+        /// The region the listing is for, as given to `--region-name`, if not the default region.
+        region_name: Option<String>,
+    },
+    /// Swap the current listing with the one it replaced.
+    Rollback {
+        output_dir: String,
+        region_name: Option<String>,
+    },
+    /// Print a random sample of the tips in a listing.
+    SampleTips {
+        input: String,
+        count: usize,
+        /// Seed for choosing the sample, so it can be repeated.
+        seed: Option<u64>,
+        phrases_file: Option<String>,
+    },
+}
+
+/// Take the value following a flag, failing if the flag was the last argument.
+fn flag_value(flag: &str, args: &mut dyn Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+/// Take the value following a flag and parse it as a number.
+fn numeric_flag_value<T: FromStr>(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<T, String> {
+    let value = flag_value(flag, args)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value \"{}\" for {}", value, flag))
+}
+
+/// Take the next positional argument, failing with a description of it if it is missing.
+fn positional(name: &str, args: &mut dyn Iterator<Item = String>) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("Missing {}", name))
+}
+
+/// Take the value following a flag and parse it as a comma separated pair of numbers.
+fn numeric_pair_flag_value<T: FromStr>(
+    flag: &str,
+    args: &mut dyn Iterator<Item = String>,
+) -> Result<(T, T), String> {
+    let value = flag_value(flag, args)?;
+    let invalid = || {
+        format!(
+            "Invalid value \"{}\" for {}, expected two numbers",
+            value, flag
+        )
+    };
+    let mut parts = value.split(',').map(|part| part.trim().parse::<T>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(first)), Some(Ok(second)), None) => Ok((first, second)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse the command line arguments (excluding the program name).
+pub fn parse_command(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.peekable();
+    let subcommand = match args.peek() {
+        Some(arg) if !arg.starts_with("--") => args.next().unwrap(),
+        _ => "scrape".into(),
+    };
+
+    match subcommand.as_str() {
+        "scrape" => Ok(Command::Scrape(Box::new(parse_scrape_options(args)?))),
+        "kml" => {
+            let input = positional("input listing path", &mut args)?;
+            let output = args.next();
+            if let Some(arg) = args.next() {
+                return Err(format!("Unknown argument \"{}\"", arg));
+            }
+            Ok(Command::Kml { input, output })
+        }
+        "html" => {
+            let input = positional("input listing path", &mut args)?;
+            let output = args.next();
+            if let Some(arg) = args.next() {
+                return Err(format!("Unknown argument \"{}\"", arg));
+            }
+            Ok(Command::Html { input, output })
+        }
+        "plan" => {
+            let mut boundary = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--boundary" => boundary = Some(flag_value(&arg, &mut args)?),
+                    _ => return Err(format!("Unknown argument \"{}\"", arg)),
+                }
+            }
+            Ok(Command::Plan { boundary })
+        }
+        "backfill" => {
+            let older = positional("older listing path", &mut args)?;
+            let newer = positional("newer listing path", &mut args)?;
+            let mut output = None;
+            let mut overwrite = Vec::new();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--overwrite" => {
+                        overwrite = flag_value(&arg, &mut args)?
+                            .split(',')
+                            .map(|field| field.trim().to_string())
+                            .collect();
+                    }
+                    _ if output.is_none() && !arg.starts_with("--") => output = Some(arg),
+                    _ => return Err(format!("Unknown argument \"{}\"", arg)),
+                }
+            }
+            Ok(Command::Backfill {
+                older,
+                newer,
+                output,
+                overwrite,
+            })
+        }
+        "lint-phrases" => {
+            let mut phrases_file = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--phrases" => phrases_file = Some(flag_value(&arg, &mut args)?),
+                    _ => return Err(format!("Unknown argument \"{}\"", arg)),
+                }
+            }
+            Ok(Command::LintPhrases { phrases_file })
+        }
+        "promote" => {
+            let file = positional("listing path", &mut args)?;
+            let mut max_count_drop = 0.5;
+            let mut output_dir = DEFAULT_OUTPUT_DIR.to_string();
+            let mut region_name = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--max-count-drop" => {
+                        max_count_drop = numeric_flag_value(&arg, &mut args)?;
+                    }
+                    "--output-dir" => output_dir = flag_value(&arg, &mut args)?,
+                    "--region-name" => region_name = Some(flag_value(&arg, &mut args)?),
+                    _ => return Err(format!("Unknown argument \"{}\"", arg)),
+                }
+            }
+            Ok(Command::Promote {
+                file,
+                max_count_drop,
+                output_dir,
+                region_name,
+            })
+        }
+        "rollback" => {
+            let mut output_dir = DEFAULT_OUTPUT_DIR.to_string();
+            let mut region_name = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--output-dir" => output_dir = flag_value(&arg, &mut args)?,
+                    "--region-name" => region_name = Some(flag_value(&arg, &mut args)?),
+                    _ => return Err(format!("Unknown argument \"{}\"", arg)),
+                }
+            }
+            Ok(Command::Rollback {
+                output_dir,
+                region_name,
+            })
+        }
+        "sample-tips" => {
+            let input = positional("listing path", &mut args)?;
+            let mut count = 10;
+            let mut seed = None;
+            let mut phrases_file = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--n" => count = numeric_flag_value(&arg, &mut args)?,
+                    "--seed" => seed = Some(numeric_flag_value(&arg, &mut args)?),
+                    "--phrases" => phrases_file = Some(flag_value(&arg, &mut args)?),
+                    _ => return Err(format!("Unknown argument \"{}\"", arg)),
+                }
+            }
+            Ok(Command::SampleTips {
+                input,
+                count,
+                seed,
+                phrases_file,
+            })
+        }
+        _ => Err(format!("Unknown subcommand \"{}\"", subcommand)),
+    }
+}
+
+/// Parse the options for a scrape run.
+fn parse_scrape_options(mut args: impl Iterator<Item = String>) -> Result<ScrapeOptions, String> {
+    let mut options = ScrapeOptions::default();
+    let mut region_name = None;
+    let mut region_top_left = None;
+    let mut region_size = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--region-name" => region_name = Some(flag_value(&arg, &mut args)?),
+            "--region-top-left" => {
+                region_top_left = Some(numeric_pair_flag_value(&arg, &mut args)?)
+            }
+            "--region-size-meters" => {
+                let (width, height): (i32, i32) = numeric_pair_flag_value(&arg, &mut args)?;
+                if width <= 0 || height <= 0 {
+                    return Err(format!("{} must be positive", arg));
+                }
+                region_size = Some((width, height));
+            }
+            "--region-state" => options.region_state = Some(flag_value(&arg, &mut args)?),
+            "--missing-coordinates" => {
+                options.missing_coordinates =
+                    MissingCoordinates::parse(&flag_value(&arg, &mut args)?)?;
+            }
+            "--request-jitter-ms" => {
+                options.max_request_jitter =
+                    Duration::from_millis(numeric_flag_value(&arg, &mut args)?);
+            }
+            "--max-output-bars" => {
+                options.max_output_bars = Some(numeric_flag_value(&arg, &mut args)?);
+            }
+            "--output-ranking" => {
+                options.output_ranking = OutputRanking::parse(&flag_value(&arg, &mut args)?)?;
+            }
+            "--boundary" => {
+                options.boundary = Some(flag_value(&arg, &mut args)?);
+            }
+            "--score-weights" => {
+                options.score_weights = ScoreWeights::parse(&flag_value(&arg, &mut args)?)?;
+            }
+            "--venue-ids-file" => {
+                options.venue_ids_file = Some(flag_value(&arg, &mut args)?);
+            }
+            "--tip-similarity-threshold" => {
+                options.tip_similarity_threshold = numeric_flag_value(&arg, &mut args)?;
+            }
+            "--keep-duplicate-counts" => options.keep_duplicate_counts = true,
+            "--annotate-centroid-distance" => options.annotate_centroid_distance = true,
+            "--region-tolerance-meters" => {
+                options.region_tolerance_meters = numeric_flag_value(&arg, &mut args)?;
+            }
+            "--proxy" => options.proxy = Some(flag_value(&arg, &mut args)?),
+            "--partition-by-neighborhood" => options.partition_by_neighborhood = true,
+            "--phrases" => options.phrases_file = Some(flag_value(&arg, &mut args)?),
+            "--quiet" => options.quiet = true,
+            "--resume" => options.resume = true,
+            "--checkpoint-interval" => {
+                options.checkpoint_interval = numeric_flag_value(&arg, &mut args)?;
+                if options.checkpoint_interval == 0 {
+                    return Err(format!("{} must be at least 1", arg));
+                }
+            }
+            "--max-subdivision-depth" => {
+                options.max_subdivision_depth = match flag_value(&arg, &mut args)?.as_str() {
+                    "unlimited" => None,
+                    value => Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid value \"{}\" for {}", value, arg))?,
+                    ),
+                };
+            }
+            "--classify-seasonal" => {
+                if options.seasonal_cues.is_none() {
+                    options.seasonal_cues = Some(
+                        DEFAULT_SEASONAL_CUES
+                            .iter()
+                            .map(|cue| cue.to_string())
+                            .collect(),
+                    );
+                }
+            }
+            "--seasonal-cues" => {
+                options.seasonal_cues = Some(
+                    flag_value(&arg, &mut args)?
+                        .split(',')
+                        .map(|cue| cue.trim().to_string())
+                        .filter(|cue| !cue.is_empty())
+                        .collect(),
+                );
+            }
+            "--debug-boxes" => options.debug_boxes = true,
+            "--stdout" => options.stdout = true,
+            "--output-dir" => options.output_dir = flag_value(&arg, &mut args)?,
+            "--format" => {
+                options.output_format = OutputFormat::parse(&flag_value(&arg, &mut args)?)?;
+            }
+            "--strict-schema" => options.strict_schema = true,
+            "--dry-run" => options.dry_run = Some(DryRun::Grid),
+            "--dry-run-venues" => options.dry_run = Some(DryRun::Venues),
+            "--concurrency" => {
+                options.concurrency = numeric_flag_value(&arg, &mut args)?;
+                if options.concurrency == 0 {
+                    return Err(format!("{} must be at least 1", arg));
+                }
+            }
+            "--max-retries" => options.max_retries = numeric_flag_value(&arg, &mut args)?,
+            "--api-version" => {
+                options.api_version = ApiVersion::parse(&flag_value(&arg, &mut args)?)?;
+            }
+            "--category-alias" => {
+                let id = category_for_alias(&flag_value(&arg, &mut args)?)?;
+                options.category_ids.push(id.to_string());
+            }
+            "--category-ids" => {
+                for id in flag_value(&arg, &mut args)?.split(',') {
+                    options.category_ids.push(parse_category_id(id)?);
+                }
+            }
+            "--max-tips-per-bar" => {
+                options.max_tips_per_bar = Some(numeric_flag_value(&arg, &mut args)?);
+            }
+            "--max-tips-read-per-bar" => {
+                options.max_tips_read_per_bar = numeric_flag_value(&arg, &mut args)?;
+            }
+            "--max-tip-chars" => options.max_tip_chars = numeric_flag_value(&arg, &mut args)?,
+            "--request-timeout-secs" => {
+                options.request_timeout = Duration::from_secs(numeric_flag_value(&arg, &mut args)?);
+            }
+            "--no-cache" => options.no_cache = true,
+            "--cache-ttl-hours" => {
+                let hours: u64 = numeric_flag_value(&arg, &mut args)?;
+                options.cache_ttl = Duration::from_secs(hours * 60 * 60);
+            }
+            "--tip-selection" => {
+                options.tip_selection = TipSelection::parse(&flag_value(&arg, &mut args)?)?;
+            }
+            "--no-proxy" => {
+                options.no_proxy = Some(
+                    flag_value(&arg, &mut args)?
+                        .split(',')
+                        .map(|host| host.trim().to_string())
+                        .collect(),
+                );
+            }
+            _ => return Err(format!("Unknown argument \"{}\"", arg)),
+        }
+    }
+    // The aliases name v2 categories, and v3 numbers its categories differently.
+    if options.api_version == ApiVersion::V3
+        && options
+            .category_ids
+            .iter()
+            .any(|id| id != FOURSQUARE_CATEGORY_ALIASES[0].1)
+    {
+        return Err(
+            "--category-alias and --category-ids aren't supported with --api-version 3, which \
+             searches bars"
+                .into(),
+        );
+    }
+    // The tips go to a second file, which has nowhere to go alongside standard output.
+    if options.stdout && options.output_format == OutputFormat::Csv {
+        return Err("--format csv can't be combined with --stdout".into());
+    }
+    // Searching a category twice would only repeat requests.
+    let mut seen = HashSet::new();
+    options.category_ids.retain(|id| seen.insert(id.clone()));
+    options.region = match (region_name, region_top_left, region_size) {
+        (Some(name), Some(top_left), Some((width_meters, height_meters))) => Some(RegionOptions {
+            name,
+            top_left,
+            width_meters,
+            height_meters,
+        }),
+        (_, _, _) if options.boundary.is_some() => {
+            return Err("--boundary can't be combined with the --region options".into())
+        }
+        (None, None, None) => None,
+        _ => {
+            return Err(
+                "--region-name, --region-top-left and --region-size-meters must be given together"
+                    .into(),
+            )
+        }
+    };
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The result of parsing a scrape with `args`.
+    fn parse_scrape(args: &[&str]) -> Result<ScrapeOptions, String> {
+        let args = ::std::iter::once(&"scrape")
+            .chain(args)
+            .map(|arg| arg.to_string());
+        match parse_command(args)? {
+            Command::Scrape(options) => Ok(*options),
+            _ => panic!("expected a scrape"),
+        }
+    }
+
+    #[test]
+    fn aliases_map_to_their_category_ids() {
+        assert_eq!(
+            category_for_alias("dive-bar"),
+            Ok("4bf58dd8d48988d118941735")
+        );
+        assert_eq!(category_for_alias("pub"), Ok("4bf58dd8d48988d11b941735"));
+        // Both spellings of whiskey are accepted.
+        assert_eq!(
+            category_for_alias("whisky-bar"),
+            category_for_alias("whiskey-bar")
+        );
+    }
+
+    #[test]
+    fn aliases_ignore_case_and_spaces() {
+        assert_eq!(
+            category_for_alias("  Dive Bar "),
+            category_for_alias("dive-bar")
+        );
+        assert_eq!(
+            category_for_alias("COCKTAIL-BAR"),
+            Ok("4bf58dd8d48988d11e941735")
+        );
+    }
+
+    #[test]
+    fn unknown_aliases_list_the_known_ones() {
+        let err = category_for_alias("tiki-bar").unwrap_err();
+        assert!(err.starts_with("Unknown category \"tiki-bar\""), "{}", err);
+        assert!(err.contains("dive-bar, gay-bar"), "{}", err);
+    }
+
+    #[test]
+    fn each_alias_adds_its_category() {
+        let options =
+            parse_scrape(&["--category-alias", "pub", "--category-alias", "Wine Bar"]).unwrap();
+        assert_eq!(
+            options.category_ids,
+            ["4bf58dd8d48988d11b941735", "4bf58dd8d48988d123941735"]
+        );
+        assert!(parse_scrape(&["--category-alias", "tiki-bar"]).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Splitting a listing into one file per neighborhood, for clients which load bars by area.
+//!
+//! The partitions are written to a directory alongside an `index.json` naming the file for each
+//! neighborhood and how many bars it holds. Bars with no known neighborhood go in an "other"
+//! partition.
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::Bar;
+
+/// Partition for bars whose neighborhood isn't known.
+pub const OTHER_NEIGHBORHOOD: &str = "other";
+
+#[derive(Serialize)]
+struct IndexEntry {
+    neighborhood: String,
+    file: String,
+    bars: usize,
+}
+
+/// Group bars by neighborhood, keeping their order within each group.
+pub fn partition_by_neighborhood(bars: &[Bar]) -> BTreeMap<String, Vec<&Bar>> {
+    let mut partitions: BTreeMap<String, Vec<&Bar>> = BTreeMap::new();
+    for bar in bars {
+        let neighborhood = bar
+            .neighborhood
+            .as_deref()
+            .map(str::trim)
+            .filter(|neighborhood| !neighborhood.is_empty())
+            .unwrap_or(OTHER_NEIGHBORHOOD);
+        partitions
+            .entry(neighborhood.to_string())
+            .or_default()
+            .push(bar);
+    }
+    partitions
+}
+
+/// A name reduced to lowercase letters and digits separated by dashes, such as `lower-east-side`,
+/// for use in file names. This may be empty.
+pub fn slug(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// A file name for a neighborhood, such as `lower-east-side.json`.
+pub fn partition_file_name(neighborhood: &str) -> String {
+    let slug = slug(neighborhood);
+    if slug.is_empty() {
+        format!("{}.json", OTHER_NEIGHBORHOOD)
+    } else {
+        format!("{}.json", slug)
+    }
+}
+
+/// Write one listing file per neighborhood into `dir`, along with the index.
+pub fn write_partitions(bars: &[Bar], dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut index = Vec::new();
+    for (neighborhood, bars) in partition_by_neighborhood(bars) {
+        let file = partition_file_name(&neighborhood);
+        serde_json::to_writer_pretty(File::create(dir.join(&file))?, &bars)?;
+        index.push(IndexEntry {
+            neighborhood,
+            file,
+            bars: bars.len(),
+        });
+    }
+    serde_json::to_writer_pretty(File::create(dir.join("index.json"))?, &index)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(id: &str, neighborhood: Option<&str>) -> Bar {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "lat": 40.7,
+            "lng": -74.0,
+            "tips": ["Pickleback!"],
+            "neighborhood": neighborhood,
+        }))
+        .unwrap()
+    }
+
+    fn ids(partitions: &BTreeMap<String, Vec<&Bar>>, neighborhood: &str) -> Vec<String> {
+        let bars = &partitions[neighborhood];
+        let json = serde_json::to_value(bars).unwrap();
+        json.as_array()
+            .unwrap()
+            .iter()
+            .map(|bar| bar["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn bars_are_grouped_by_neighborhood_in_order() {
+        let bars = vec![
+            bar("a", Some("Manhattan")),
+            bar("b", Some("Brooklyn")),
+            bar("c", Some("Manhattan")),
+        ];
+        let partitions = partition_by_neighborhood(&bars);
+        assert_eq!(
+            partitions.keys().collect::<Vec<_>>(),
+            vec!["Brooklyn", "Manhattan"]
+        );
+        assert_eq!(ids(&partitions, "Manhattan"), vec!["a", "c"]);
+        assert_eq!(ids(&partitions, "Brooklyn"), vec!["b"]);
+    }
+
+    #[test]
+    fn bars_without_a_neighborhood_go_in_other() {
+        let bars = vec![
+            bar("a", None),
+            bar("b", Some("  ")),
+            bar("c", Some("Queens")),
+        ];
+        let partitions = partition_by_neighborhood(&bars);
+        assert_eq!(ids(&partitions, OTHER_NEIGHBORHOOD), vec!["a", "b"]);
+        assert_eq!(ids(&partitions, "Queens"), vec!["c"]);
+    }
+
+    #[test]
+    fn file_names_are_slugs() {
+        assert_eq!(
+            partition_file_name("Lower East Side"),
+            "lower-east-side.json"
+        );
+        assert_eq!(partition_file_name("  St. Mark's  "), "st-mark-s.json");
+        assert_eq!(partition_file_name("!!!"), "other.json");
+    }
+
+    #[test]
+    fn index_names_every_partition() {
+        let dir =
+            std::env::temp_dir().join(format!("pickletrack-partition-{}", std::process::id()));
+        let bars = vec![bar("a", Some("Lower East Side")), bar("b", None)];
+        write_partitions(&bars, &dir).unwrap();
+
+        let index: serde_json::Value =
+            serde_json::from_reader(File::open(dir.join("index.json")).unwrap()).unwrap();
+        assert_eq!(
+            index,
+            serde_json::json!([
+                {"neighborhood": "Lower East Side", "file": "lower-east-side.json", "bars": 1},
+                {"neighborhood": "other", "file": "other.json", "bars": 1},
+            ])
+        );
+        let other: serde_json::Value =
+            serde_json::from_reader(File::open(dir.join("other.json")).unwrap()).unwrap();
+        assert_eq!(other[0]["id"], "b");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
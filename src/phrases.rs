@@ -0,0 +1,292 @@
+//! The phrases a tip must contain to count as mentioning picklebacks.
+//!
+//! The built-in list can be replaced with a file holding one phrase per line, where lines starting
+//! with `#` are comments. Tips are matched ignoring case, as described by `phrase_matches`, and
+//! phrases are lowercased when loaded.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::TIP_SEARCH_PHRASES;
+
+/// Phrase file read when none is given, if it exists, so the list can be tuned without a rebuild.
+pub const DEFAULT_PATH: &str = "phrases.txt";
+
+/// Load the phrase list from `path`, or from `DEFAULT_PATH` if there is none, or the built-in list
+/// if that doesn't exist either.
+///
+/// Blank lines are kept as empty phrases, so that `lint` can point them out. Use `effective` to
+/// drop them before matching.
+pub fn load(path: Option<&str>) -> io::Result<Vec<String>> {
+    match path {
+        Some(path) => read(path),
+        None if Path::new(DEFAULT_PATH).exists() => read(DEFAULT_PATH),
+        None => Ok(TIP_SEARCH_PHRASES
+            .iter()
+            .map(|phrase| phrase.to_string())
+            .collect()),
+    }
+}
+
+fn read(path: &str) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect())
+}
+
+/// The phrases which are worth matching against tips.
+///
+/// An empty phrase would match every tip, so those are dropped.
+pub fn effective(phrases: Vec<String>) -> Vec<String> {
+    phrases
+        .into_iter()
+        .filter(|phrase| !phrase.is_empty())
+        .collect()
+}
+
+/// Whether `c`, the character either side of a match, leaves the match a whole word. The start and
+/// end of the text count too.
+fn is_word_boundary(c: Option<char>) -> bool {
+    c.is_none_or(|c| !c.is_alphanumeric())
+}
+
+/// Whether `text` mentions `phrase`, ignoring case.
+///
+/// The phrase must appear as whole words, so "pickle shot" doesn't match "pickle shotgun", though
+/// it may be pluralised with a trailing "s" or "es". Hyphens and other punctuation separate words,
+/// so a hyphenated compound such as "pickleback-flavored" is deliberately counted as mentioning
+/// picklebacks.
+pub fn phrase_matches(text: &str, phrase: &str) -> bool {
+    phrase_occurrences(text, phrase) > 0
+}
+
+/// How many times `text` mentions `phrase`, ignoring case, by the same rules as `phrase_matches`.
+pub fn phrase_occurrences(text: &str, phrase: &str) -> usize {
+    let text = text.to_lowercase();
+    let phrase = phrase.to_lowercase();
+    if phrase.is_empty() {
+        return 0;
+    }
+    text.char_indices()
+        .filter(|&(start, _)| text[start..].starts_with(phrase.as_str()))
+        .filter(|&(start, _)| {
+            let rest = &text[start + phrase.len()..];
+            let plural_rest = rest.strip_prefix("es").or_else(|| rest.strip_prefix('s'));
+            is_word_boundary(text[..start].chars().next_back())
+                && (is_word_boundary(rest.chars().next())
+                    || plural_rest.is_some_and(|rest| is_word_boundary(rest.chars().next())))
+        })
+        .count()
+}
+
+/// Something wrong with a phrase list. Phrases are numbered from 1, in list order.
+#[derive(Debug, PartialEq)]
+pub enum PhraseProblem {
+    Empty {
+        number: usize,
+    },
+    Duplicate {
+        number: usize,
+        first: usize,
+    },
+    /// Any tip containing the phrase also contains the shorter phrase it is redundant with.
+    Redundant {
+        number: usize,
+        covered_by: usize,
+    },
+}
+
+impl PhraseProblem {
+    /// The number of the phrase with the problem.
+    pub fn number(&self) -> usize {
+        match *self {
+            PhraseProblem::Empty { number }
+            | PhraseProblem::Duplicate { number, .. }
+            | PhraseProblem::Redundant { number, .. } => number,
+        }
+    }
+}
+
+impl fmt::Display for PhraseProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhraseProblem::Empty { number } => write!(f, "Phrase {} is empty", number),
+            PhraseProblem::Duplicate { number, first } => {
+                write!(f, "Phrase {} duplicates phrase {}", number, first)
+            }
+            PhraseProblem::Redundant { number, covered_by } => write!(
+                f,
+                "Phrase {} is redundant, it contains phrase {}",
+                number, covered_by
+            ),
+        }
+    }
+}
+
+/// Find the empty, duplicated and redundant phrases in a list.
+///
+/// Each phrase is reported at most once, with the first problem found.
+pub fn lint(phrases: &[String]) -> Vec<PhraseProblem> {
+    let mut problems = Vec::new();
+    for (index, phrase) in phrases.iter().enumerate() {
+        let number = index + 1;
+        if phrase.is_empty() {
+            problems.push(PhraseProblem::Empty { number });
+            continue;
+        }
+        if let Some(first) = phrases[..index].iter().position(|other| other == phrase) {
+            problems.push(PhraseProblem::Duplicate {
+                number,
+                first: first + 1,
+            });
+            continue;
+        }
+        if let Some(covered_by) = phrases
+            .iter()
+            .position(|other| !other.is_empty() && other != phrase && phrase_matches(phrase, other))
+        {
+            problems.push(PhraseProblem::Redundant {
+                number,
+                covered_by: covered_by + 1,
+            });
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phrases(list: &[&str]) -> Vec<String> {
+        list.iter().map(|phrase| phrase.to_string()).collect()
+    }
+
+    #[test]
+    fn hyphenated_compounds_count_as_mentions() {
+        assert!(phrase_matches(
+            "Try the pickleback-flavored popsicle",
+            "pickleback"
+        ));
+        assert!(phrase_matches(
+            "A pickleback-flavored-popsicle-store next door",
+            "pickleback"
+        ));
+        assert!(phrase_matches("Get a pickle-back here", "pickle-back"));
+        // The hyphenated spelling isn't the same phrase as the unhyphenated one.
+        assert!(!phrase_matches("Get a pickle-back here", "pickleback"));
+        assert!(!phrase_matches("Get a pickleback here", "pickle-back"));
+    }
+
+    #[test]
+    fn punctuation_either_side_is_a_boundary() {
+        assert!(phrase_matches("Pickleback!", "pickleback"));
+        assert!(phrase_matches("\"pickleback\" night", "pickleback"));
+        assert!(phrase_matches("(pickle juice), anyone?", "pickle juice"));
+        assert!(phrase_matches("Best in town...pickleback.", "pickleback"));
+    }
+
+    #[test]
+    fn phrases_within_longer_words_do_not_match() {
+        assert!(!phrase_matches(
+            "Sweet pickle juicer for sale",
+            "pickle juice"
+        ));
+        assert!(!phrase_matches("Ask for a pickle shotgun", "pickle shot"));
+        assert!(!phrase_matches("Unpickleback your night", "pickleback"));
+    }
+
+    #[test]
+    fn plurals_match() {
+        assert!(phrase_matches("Two picklebacks, please", "pickleback"));
+        assert!(phrase_matches("Pickle juices on tap", "pickle juice"));
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        assert!(phrase_matches("PICKLEBACK TIME", "pickleback"));
+        assert!(phrase_matches("PickleBack", "PICKLEBACK"));
+    }
+
+    #[test]
+    fn empty_phrase_matches_nothing() {
+        assert!(!phrase_matches("Pickleback", ""));
+    }
+
+    #[test]
+    fn occurrences_are_counted_as_whole_words() {
+        assert_eq!(
+            phrase_occurrences(
+                "Pickleback? Two picklebacks! A pickleback-chaser",
+                "pickleback"
+            ),
+            3
+        );
+        assert_eq!(
+            phrase_occurrences("Unpickleback your picklebacker", "pickleback"),
+            0
+        );
+        assert_eq!(phrase_occurrences("Pickleback", ""), 0);
+    }
+
+    #[test]
+    fn messy_list_has_its_problems_reported() {
+        let messy = phrases(&[
+            "pickleback",
+            "pickle-back",
+            "",
+            "pickle-back",
+            "pickleback shot",
+            "pickle juice",
+            "pickle juice chaser",
+            "jameson",
+        ]);
+        assert_eq!(
+            lint(&messy),
+            vec![
+                PhraseProblem::Empty { number: 3 },
+                PhraseProblem::Duplicate {
+                    number: 4,
+                    first: 2
+                },
+                PhraseProblem::Redundant {
+                    number: 5,
+                    covered_by: 1
+                },
+                PhraseProblem::Redundant {
+                    number: 7,
+                    covered_by: 6
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn clean_list_has_no_problems() {
+        assert_eq!(
+            lint(&phrases(&["pickleback", "pickle-back", "pickle shot"])),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn phrase_within_a_longer_word_is_not_redundant() {
+        // "pickle shot" doesn't match inside "pickle shotgun", so both are needed.
+        assert_eq!(lint(&phrases(&["pickle shot", "pickle shotgun"])), vec![]);
+    }
+
+    #[test]
+    fn phrase_file_skips_comments_and_lowercases() {
+        let path = std::env::temp_dir().join(format!("pickletrack-phrases-{}", std::process::id()));
+        fs::write(&path, "# Spellings\nPickleback\n\n  pickle-back  \n").unwrap();
+        let loaded = load(path.to_str()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, phrases(&["pickleback", "", "pickle-back"]));
+        assert_eq!(lint(&loaded), vec![PhraseProblem::Empty { number: 2 }]);
+        assert_eq!(effective(loaded), phrases(&["pickleback", "pickle-back"]));
+    }
+}
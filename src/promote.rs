@@ -0,0 +1,336 @@
+//! Putting a listing live by pointing the current symlink at it.
+//!
+//! Promotion is kept separate from scraping so a scrape can be reviewed before it goes live, and so
+//! an older known-good listing can be put back. A listing is validated before it is promoted, and
+//! refused if it has far fewer bars than the listing it replaces, which usually means the scrape
+//! was cut short rather than that bars closed.
+//!
+//! Each time the current listing changes, a `previous.json` symlink beside it is pointed at the
+//! listing it replaced, so the change can be rolled back with a single symlink swap.
+//!
+//! Where symlinks can't be made, such as on Windows without the privilege to, the links are copies
+//! of the listings instead. They serve the same, but `promote` can't tell a copy of a listing is
+//! already current, and the previous listing is kept as a copy rather than found by name.
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Bar;
+
+/// Check that a listing is something the server can sensibly serve.
+pub fn validate(bars: &[Bar]) -> Result<(), String> {
+    if bars.is_empty() {
+        return Err("Listing has no bars".into());
+    }
+
+    let mut ids = HashSet::new();
+    for bar in bars {
+        if !ids.insert(&bar.id) {
+            return Err(format!("Bar {} appears more than once", bar.id));
+        }
+        if !(-90.0..=90.0).contains(&bar.lat) || !(-180.0..=180.0).contains(&bar.lng) {
+            return Err(format!(
+                "Bar {} has invalid coordinates {}, {}",
+                bar.id, bar.lat, bar.lng
+            ));
+        }
+        if bar.tips.is_empty() {
+            return Err(format!("Bar {} has no tips", bar.id));
+        }
+    }
+    Ok(())
+}
+
+/// Refuse a listing with more than `max_drop` (a fraction from 0 to 1) fewer bars than the current
+/// one.
+pub fn check_count_drop(current: usize, candidate: usize, max_drop: f64) -> Result<(), String> {
+    let minimum = (current as f64 * (1.0 - max_drop)).ceil() as usize;
+    if candidate < minimum {
+        return Err(format!(
+            "Listing has {} bars, down from {} in the current listing. Promoting it needs at \
+             least {}.",
+            candidate, current, minimum
+        ));
+    }
+    Ok(())
+}
+
+/// Create a symlink at `link` to `target`.
+#[cfg(unix)]
+fn symlink_or_copy(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Create a symlink at `link` to `target`, or copy `target` there if symlinks can't be made.
+/// Windows only lets administrators and developer mode make them.
+#[cfg(windows)]
+fn symlink_or_copy(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link).or_else(|_| fs::copy(target, link).map(|_| ()))
+}
+
+/// Copy `target` to `link`, on platforms without symlinks.
+#[cfg(not(any(unix, windows)))]
+fn symlink_or_copy(target: &Path, link: &Path) -> io::Result<()> {
+    fs::copy(target, link).map(|_| ())
+}
+
+/// Atomically point the symlink at `link` to `target`, replacing any existing link.
+///
+/// The new link is created alongside the old one and renamed over it, so readers always see either
+/// the old listing or the new one.
+pub fn repoint_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    let mut staging = link.as_os_str().to_owned();
+    staging.push(".new");
+    let staging = PathBuf::from(staging);
+
+    if let Err(err) = fs::remove_file(&staging) {
+        if err.kind() != io::ErrorKind::NotFound {
+            return Err(err);
+        }
+    }
+    symlink_or_copy(target, &staging)?;
+    fs::rename(&staging, link)
+}
+
+/// Name of the symlink to the listing the current one replaced, alongside the current symlink.
+const PREVIOUS_LISTING_FILE_NAME: &str = "previous.json";
+
+/// The previous listing symlink which goes with the current listing symlink `link`.
+fn previous_link(link: &Path) -> PathBuf {
+    link.with_file_name(PREVIOUS_LISTING_FILE_NAME)
+}
+
+/// Point the current listing symlink at `link` to `target`, first pointing the previous listing
+/// symlink at whatever it replaces.
+pub fn advance_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    if let Ok(current_target) = fs::canonicalize(link) {
+        if current_target != target {
+            repoint_symlink(&current_target, &previous_link(link))?;
+        }
+    }
+    repoint_symlink(target, link)
+}
+
+fn is_symlink(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Roll back where the current and previous listings are copies rather than symlinks, by swapping
+/// the files. Repointing them in turn would copy the current listing over both.
+fn swap_copies(link: &Path, previous: &Path) -> Result<(), String> {
+    let mut staging = link.as_os_str().to_owned();
+    staging.push(".swap");
+    let staging = PathBuf::from(staging);
+    let rename = |from: &Path, to: &Path| {
+        fs::rename(from, to).map_err(|err| {
+            format!(
+                "Couldn't move {} to {}: {}",
+                from.display(),
+                to.display(),
+                err
+            )
+        })
+    };
+    if !previous.exists() {
+        return Err(format!(
+            "There is no previous listing to roll back to at {}",
+            previous.display()
+        ));
+    }
+    rename(link, &staging)?;
+    rename(previous, link)?;
+    rename(&staging, previous)?;
+    eprintln!(
+        "Rolled back to the listing copied to {}, swapping it with {}.",
+        previous.display(),
+        link.display()
+    );
+    Ok(())
+}
+
+/// Make the previous listing current again, and the current one previous, so rolling back twice
+/// puts things as they were.
+pub fn rollback(link: &str) -> Result<(), String> {
+    let link = Path::new(link);
+    let previous = previous_link(link);
+    if !is_symlink(link) {
+        return swap_copies(link, &previous);
+    }
+    let previous_target = fs::canonicalize(&previous).map_err(|err| {
+        format!(
+            "There is no previous listing to roll back to at {}: {}",
+            previous.display(),
+            err
+        )
+    })?;
+    let current_target = fs::canonicalize(link).map_err(|err| {
+        format!(
+            "Couldn't find the current listing {}: {}",
+            link.display(),
+            err
+        )
+    })?;
+
+    repoint_symlink(&previous_target, link).map_err(|err| {
+        format!(
+            "Couldn't point {} at {}: {}",
+            link.display(),
+            previous_target.display(),
+            err
+        )
+    })?;
+    repoint_symlink(&current_target, &previous).map_err(|err| {
+        format!(
+            "Couldn't point {} at {}: {}",
+            previous.display(),
+            current_target.display(),
+            err
+        )
+    })?;
+    eprintln!(
+        "Rolled back to {}, replacing {}.",
+        previous_target.display(),
+        current_target.display()
+    );
+    Ok(())
+}
+
+/// Validate the listing at `path` and make it the current listing at `link`.
+///
+/// Promoting the listing which is already current does nothing, so this is safe to re-run.
+pub fn promote(path: &str, link: &str, max_count_drop: f64) -> Result<(), String> {
+    let target =
+        fs::canonicalize(path).map_err(|err| format!("Couldn't find {}: {}", path, err))?;
+    let bars: Vec<Bar> =
+        crate::export::read_bars(path).map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+    validate(&bars).map_err(|err| format!("{} is not a valid listing: {}", path, err))?;
+
+    if let Ok(current_target) = fs::canonicalize(link) {
+        if current_target == target {
+            eprintln!("{} is already the current listing.", path);
+            return Ok(());
+        }
+        // A current listing which can't be read is no baseline for the guard, and is exactly
+        // what promoting a good listing should fix.
+        if let Ok(current) = crate::export::read_bars(link) {
+            check_count_drop(current.len(), bars.len(), max_count_drop)?;
+        }
+    }
+
+    advance_symlink(&target, Path::new(link))
+        .map_err(|err| format!("Couldn't point {} at {}: {}", link, path, err))?;
+    eprintln!("Promoted {} ({} bars).", path, bars.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory to promote listings in, named after the test using it.
+    fn listing_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "pickletrack-promote-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Write a listing of bars with the given ids to `name` in `dir`, returning its path.
+    fn write_listing(dir: &Path, name: &str, ids: &[&str]) -> String {
+        let bars: Vec<serde_json::Value> = ids
+            .iter()
+            .map(|id| {
+                serde_json::json!({
+                    "id": id,
+                    "name": id,
+                    "lat": 40.7,
+                    "lng": -74.0,
+                    "tips": ["Pickleback!"],
+                })
+            })
+            .collect();
+        let path = dir.join(name);
+        fs::write(&path, serde_json::Value::from(bars).to_string()).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    /// The ids of the bars in the listing at `path`.
+    fn ids(path: &Path) -> Vec<String> {
+        crate::export::read_bars(path.to_str().unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|bar| bar.id)
+            .collect()
+    }
+
+    #[test]
+    fn only_valid_listings_are_promoted() {
+        let dir = listing_dir("validate");
+        let current = dir.join("current.json");
+        let link = current.to_str().unwrap();
+        let first = write_listing(&dir, "first.json", &["a", "b", "c", "d"]);
+        promote(&first, link, 0.5).unwrap();
+        assert_eq!(ids(&current), ["a", "b", "c", "d"]);
+
+        let duplicated = write_listing(&dir, "duplicated.json", &["a", "a", "b", "c"]);
+        let err = promote(&duplicated, link, 0.5).unwrap_err();
+        assert!(err.contains("not a valid listing"), "{}", err);
+        assert_eq!(ids(&current), ["a", "b", "c", "d"]);
+
+        fs::write(dir.join("truncated.json"), "[{\"id\": \"a\", ").unwrap();
+        let truncated = dir.join("truncated.json");
+        assert!(promote(truncated.to_str().unwrap(), link, 0.5).is_err());
+        assert_eq!(ids(&current), ["a", "b", "c", "d"]);
+
+        // Three quarters of the bars are gone, more than the guard allows.
+        let shrunk = write_listing(&dir, "shrunk.json", &["a"]);
+        let err = promote(&shrunk, link, 0.5).unwrap_err();
+        assert!(err.contains("down from 4"), "{}", err);
+        assert_eq!(ids(&current), ["a", "b", "c", "d"]);
+
+        // A smaller drop is allowed, and the listing it replaces is kept as the previous one.
+        let second = write_listing(&dir, "second.json", &["a", "b", "c"]);
+        promote(&second, link, 0.5).unwrap();
+        assert_eq!(ids(&current), ["a", "b", "c"]);
+        assert_eq!(
+            ids(&dir.join(PREVIOUS_LISTING_FILE_NAME)),
+            ["a", "b", "c", "d"]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rolling_back_swaps_the_current_and_previous_listings() {
+        let dir = listing_dir("rollback");
+        let current = dir.join("current.json");
+        let previous = dir.join(PREVIOUS_LISTING_FILE_NAME);
+        let link = current.to_str().unwrap();
+        let first = write_listing(&dir, "first.json", &["a", "b"]);
+        promote(&first, link, 0.5).unwrap();
+        // Nothing has been replaced yet.
+        assert!(rollback(link).is_err());
+        assert_eq!(ids(&current), ["a", "b"]);
+
+        let second = write_listing(&dir, "second.json", &["a", "b", "c"]);
+        promote(&second, link, 0.5).unwrap();
+        rollback(link).unwrap();
+        assert_eq!(ids(&current), ["a", "b"]);
+        assert_eq!(ids(&previous), ["a", "b", "c"]);
+
+        // Rolling back again undoes the rollback.
+        rollback(link).unwrap();
+        assert_eq!(ids(&current), ["a", "b", "c"]);
+        assert_eq!(ids(&previous), ["a", "b"]);
+        // The listings themselves are untouched.
+        assert_eq!(ids(Path::new(&first)), ["a", "b"]);
+        assert_eq!(ids(Path::new(&second)), ["a", "b", "c"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
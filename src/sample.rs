@@ -0,0 +1,118 @@
+//! Drawing a random sample of tips from a listing, for a quick look at how well the search phrases
+//! pick out tips about picklebacks.
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{FromEntropy, SeedableRng};
+
+use crate::phrases::phrase_matches;
+use crate::{Bar, Tip};
+
+/// Choose up to `count` tips at random from across the listing, along with the bar of each.
+///
+/// Every tip is equally likely to be chosen, whichever bar it belongs to. The same seed always
+/// gives the same sample of the same listing.
+pub fn sample_tips(bars: &[Bar], count: usize, seed: Option<u64>) -> Vec<(&Bar, &Tip)> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let tips: Vec<(&Bar, &Tip)> = bars
+        .iter()
+        .flat_map(|bar| bar.tips.iter().map(move |tip| (bar, tip)))
+        .collect();
+    let mut sample: Vec<(&Bar, &Tip)> = tips.choose_multiple(&mut rng, count).cloned().collect();
+    sample.shuffle(&mut rng);
+    sample
+}
+
+/// The phrases in `phrases` which `tip` matches.
+pub fn matched_phrases<'a>(tip: &Tip, phrases: &'a [String]) -> Vec<&'a str> {
+    phrases
+        .iter()
+        .filter(|phrase| phrase_matches(&tip.text, phrase))
+        .map(String::as_str)
+        .collect()
+}
+
+/// Print a sample of the tips in the listing at `path`, with the phrases each matches.
+pub fn print_sample(
+    path: &str,
+    count: usize,
+    seed: Option<u64>,
+    phrases: &[String],
+) -> Result<(), String> {
+    let bars =
+        crate::export::read_bars(path).map_err(|err| format!("Couldn't read {}: {}", path, err))?;
+    for (bar, tip) in sample_tips(&bars, count, seed) {
+        let matched = matched_phrases(tip, phrases);
+        println!("{} ({})", bar.name, bar.id);
+        if matched.is_empty() {
+            println!("  matched: nothing");
+        } else {
+            println!("  matched: {}", matched.join(", "));
+        }
+        println!("  {}", tip.text);
+        println!();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bar with `tip_count` tips, whose texts begin with the bar's id.
+    fn bar(id: &str, tip_count: usize) -> Bar {
+        let tips: Vec<serde_json::Value> = (0..tip_count)
+            .map(|index| serde_json::json!({ "text": format!("{}{}", id, index) }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id.to_uppercase(),
+            "lat": 40.7,
+            "lng": -74.0,
+            "tips": tips,
+        }))
+        .unwrap()
+    }
+
+    /// Three bars with ten tips between them.
+    fn bars() -> Vec<Bar> {
+        vec![bar("a", 6), bar("b", 0), bar("c", 4)]
+    }
+
+    fn texts(sample: &[(&Bar, &Tip)]) -> Vec<String> {
+        sample.iter().map(|(_, tip)| tip.text.clone()).collect()
+    }
+
+    #[test]
+    fn the_sample_has_n_distinct_tips() {
+        let bars = bars();
+        for &n in &[0, 1, 4, 10] {
+            let mut sample = texts(&sample_tips(&bars, n, Some(7)));
+            assert_eq!(sample.len(), n);
+            sample.sort();
+            sample.dedup();
+            assert_eq!(sample.len(), n);
+        }
+        // There are only ten tips to choose from.
+        assert_eq!(sample_tips(&bars, 50, Some(7)).len(), 10);
+    }
+
+    #[test]
+    fn tips_are_sampled_with_their_bar() {
+        let bars = bars();
+        for (bar, tip) in sample_tips(&bars, 10, None) {
+            assert!(tip.text.starts_with(&bar.id));
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_sample() {
+        let bars = bars();
+        let sample = texts(&sample_tips(&bars, 5, Some(42)));
+        assert_eq!(texts(&sample_tips(&bars, 5, Some(42))), sample);
+        // Some other seed draws a different sample, or the same tips in another order.
+        assert!((0..10).any(|seed| texts(&sample_tips(&bars, 5, Some(seed))) != sample));
+    }
+}
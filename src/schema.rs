@@ -0,0 +1,156 @@
+//! Checks that Foursquare's responses still carry the fields the scraper relies on, for
+//! `--strict-schema`.
+//!
+//! The response types leave most fields optional, because some venues really don't have them, so a
+//! field Foursquare stops sending is quietly read as absent. Fields the types don't know about are
+//! quietly ignored too, including ones Foursquare has renamed. These checks catch both instead.
+use serde_json::Value;
+
+/// What `--strict-schema` checks a response against.
+pub struct Schema {
+    /// Paths which must be present, see `missing_field`.
+    pub required: &'static [&'static str],
+    /// Paths of every field the scraper knows about, see `unexpected_field`.
+    pub known: &'static [&'static str],
+}
+
+/// The first of `paths` which is missing or null in `value`, with array indices filled in.
+///
+/// A path is a list of object keys separated by `/`, in which `*` stands for every element of an
+/// array, such as `response/venues/*/id`.
+pub fn missing_field(value: &Value, paths: &[&str]) -> Option<String> {
+    paths.iter().find_map(|path| {
+        let keys: Vec<&str> = path.split('/').collect();
+        missing_at(value, &keys, "")
+    })
+}
+
+/// The first field in `value` which isn't one of the `known` paths, written as for
+/// `missing_field`.
+///
+/// Only objects with fields listed in `known` are checked, so an object which is listed without
+/// any of its fields, such as a venue's location which Foursquare fills with much we have no use
+/// for, may hold anything.
+pub fn unexpected_field(value: &Value, known: &[&str]) -> Option<String> {
+    let known: Vec<Vec<&str>> = known.iter().map(|path| path.split('/').collect()).collect();
+    let known: Vec<&[&str]> = known.iter().map(Vec::as_slice).collect();
+    unexpected_at(value, &known, "")
+}
+
+/// The path `at` extended by `key`.
+fn join(at: &str, key: &str) -> String {
+    if at.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}/{}", at, key)
+    }
+}
+
+fn missing_at(value: &Value, keys: &[&str], at: &str) -> Option<String> {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None if value.is_null() => return Some(at.to_string()),
+        None => return None,
+    };
+    let here = join(at, key);
+    match (*key, value) {
+        ("*", Value::Array(elements)) => elements
+            .iter()
+            .enumerate()
+            .find_map(|(index, element)| missing_at(element, rest, &join(at, &index.to_string()))),
+        ("*", _) => Some(here),
+        (_, Value::Object(fields)) => match fields.get(*key) {
+            Some(field) => missing_at(field, rest, &here),
+            None => Some(here),
+        },
+        (_, _) => Some(here),
+    }
+}
+
+/// The first field of `value`, found at `at`, not among the `known` paths continuing from it.
+fn unexpected_at(value: &Value, known: &[&[&str]], at: &str) -> Option<String> {
+    if known.iter().all(|keys| keys.is_empty()) {
+        return None;
+    }
+    // The rest of each known path continuing with `key`.
+    let below = |key: &str| -> Vec<&[&str]> {
+        known
+            .iter()
+            .filter_map(|keys| match keys.split_first() {
+                Some((first, rest)) if *first == key => Some(rest),
+                _ => None,
+            })
+            .collect()
+    };
+    match value {
+        Value::Array(elements) => {
+            let below = below("*");
+            elements.iter().enumerate().find_map(|(index, element)| {
+                unexpected_at(element, &below, &join(at, &index.to_string()))
+            })
+        }
+        Value::Object(fields) => fields.iter().find_map(|(key, field)| {
+            let here = join(at, key);
+            let below = below(key);
+            if below.is_empty() {
+                Some(here)
+            } else {
+                unexpected_at(field, &below, &here)
+            }
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KNOWN: &[&str] = &[
+        "meta",
+        "response/venues/*/id",
+        "response/venues/*/name",
+        "response/venues/*/location",
+    ];
+
+    #[test]
+    fn expected_response_passes() {
+        let response = json!({
+            "meta": {"code": 200, "requestId": "abc"},
+            "response": {"venues": [
+                {"id": "a", "name": "A", "location": {"lat": 40.7, "lng": -74.0, "cc": "US"}},
+                {"id": "b", "name": "B", "location": {"lat": 40.8, "lng": -73.9}}
+            ]}
+        });
+        assert_eq!(missing_field(&response, &KNOWN[1..]), None);
+        assert_eq!(unexpected_field(&response, KNOWN), None);
+    }
+
+    #[test]
+    fn unexpected_field_is_named() {
+        let response = json!({
+            "response": {"venues": [
+                {"id": "a", "name": "A", "location": {}},
+                {"id": "b", "title": "B", "location": {}}
+            ]}
+        });
+        assert_eq!(
+            unexpected_field(&response, KNOWN),
+            Some("response/venues/1/title".to_string())
+        );
+        assert_eq!(
+            missing_field(&response, &KNOWN[1..]),
+            Some("response/venues/1/name".to_string())
+        );
+    }
+
+    #[test]
+    fn unexpected_top_level_field_is_named() {
+        let response = json!({"response": {"venues": []}, "notifications": []});
+        assert_eq!(
+            unexpected_field(&response, KNOWN),
+            Some("notifications".to_string())
+        );
+    }
+}
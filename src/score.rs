@@ -0,0 +1,173 @@
+//! The quality score used to rank bars for curated feeds, such as the server's `/top` endpoint.
+//!
+//! The score is a weighted sum of four components, each scaled to roughly the range zero to one
+//! for typical bars:
+//!
+//! * `tips`: `ln(1 + n) / ln(21)` for `n` matching tips, so twenty tips score one.
+//! * `recency`: `0.5 ^ (age / 365)` for the age in days of the newest matching tip, halving each
+//!   year the pickleback mentions go stale.
+//! * `rating`: the Foursquare rating divided by ten.
+//! * `popularity`: `ln(1 + c) / ln(10001)` for `c` Foursquare check-ins, so ten thousand check-ins
+//!   score one.
+//!
+//! Components Foursquare didn't give us data for contribute zero. By default the weights are
+//! `tips=1,recency=0.5,rating=0.5,popularity=0.25`, so the number of tips dominates.
+
+/// Weights applied to each component of the quality score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreWeights {
+    pub tips: f64,
+    pub recency: f64,
+    pub rating: f64,
+    pub popularity: f64,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        Self {
+            tips: 1.0,
+            recency: 0.5,
+            rating: 0.5,
+            popularity: 0.25,
+        }
+    }
+}
+
+impl ScoreWeights {
+    /// Parse weights from a comma separated list of `name=weight` pairs, such as
+    /// `tips=1,recency=0`. Weights which aren't mentioned keep their default. Weights must be
+    /// finite, so that scores can be compared.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut weights = Self::default();
+        for pair in value.split(',') {
+            let mut parts = pair.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let weight: f64 = parts
+                .next()
+                .and_then(|weight| weight.trim().parse().ok())
+                .filter(|weight: &f64| weight.is_finite())
+                .ok_or_else(|| format!("Invalid score weight \"{}\"", pair))?;
+            match name {
+                "tips" => weights.tips = weight,
+                "recency" => weights.recency = weight,
+                "rating" => weights.rating = weight,
+                "popularity" => weights.popularity = weight,
+                _ => return Err(format!("Unknown score component \"{}\"", name)),
+            }
+        }
+        Ok(weights)
+    }
+}
+
+/// What we know about a bar when scoring it.
+pub struct ScoreInputs {
+    pub tip_count: usize,
+    /// Age in days of the newest matching tip.
+    pub newest_tip_age_days: Option<f64>,
+    /// Foursquare rating, out of ten.
+    pub rating: Option<f64>,
+    pub checkins: Option<u64>,
+}
+
+/// Compute the quality score of a bar.
+pub fn quality_score(weights: &ScoreWeights, inputs: &ScoreInputs) -> f64 {
+    let tips = (1.0 + inputs.tip_count as f64).ln() / 21f64.ln();
+    let recency = inputs
+        .newest_tip_age_days
+        .map_or(0.0, |age| 0.5f64.powf(age.max(0.0) / 365.0));
+    let rating = inputs.rating.map_or(0.0, |rating| rating / 10.0);
+    let popularity = inputs
+        .checkins
+        .map_or(0.0, |checkins| (1.0 + checkins as f64).ln() / 10001f64.ln());
+
+    weights.tips * tips
+        + weights.recency * recency
+        + weights.rating * rating
+        + weights.popularity * popularity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn omitted_weights_keep_their_default() {
+        let weights = ScoreWeights::parse("tips=2, recency=0").unwrap();
+        assert_eq!(
+            weights,
+            ScoreWeights {
+                tips: 2.0,
+                recency: 0.0,
+                ..ScoreWeights::default()
+            }
+        );
+    }
+
+    #[test]
+    fn non_finite_weights_are_rejected() {
+        for value in &["tips=NaN", "rating=inf", "popularity=-inf", "recency=1e999"] {
+            assert!(ScoreWeights::parse(value).is_err(), "{}", value);
+        }
+    }
+
+    fn inputs(tip_count: usize) -> ScoreInputs {
+        ScoreInputs {
+            tip_count,
+            newest_tip_age_days: None,
+            rating: None,
+            checkins: None,
+        }
+    }
+
+    #[test]
+    fn components_are_scaled_and_weighted() {
+        let only = |weights: &str, inputs: &ScoreInputs| {
+            let weights = ScoreWeights::parse(weights).unwrap();
+            quality_score(&weights, inputs)
+        };
+        let zero = "tips=0,recency=0,rating=0,popularity=0";
+        assert!((only("tips=1,recency=0,rating=0,popularity=0", &inputs(20)) - 1.0).abs() < 1e-9);
+        let year_old = ScoreInputs {
+            newest_tip_age_days: Some(365.0),
+            ..inputs(0)
+        };
+        assert!((only(&format!("{},recency=2", zero), &year_old) - 1.0).abs() < 1e-9);
+        let rated = ScoreInputs {
+            rating: Some(8.0),
+            ..inputs(0)
+        };
+        assert!((only(&format!("{},rating=1", zero), &rated) - 0.8).abs() < 1e-9);
+        let popular = ScoreInputs {
+            checkins: Some(10_000),
+            ..inputs(0)
+        };
+        assert!((only(&format!("{},popularity=3", zero), &popular) - 3.0).abs() < 1e-9);
+
+        // Missing data contributes nothing.
+        assert_eq!(quality_score(&ScoreWeights::default(), &inputs(0)), 0.0);
+    }
+
+    #[test]
+    fn more_and_fresher_tips_score_higher() {
+        let weights = ScoreWeights::default();
+        let score = |tip_count, age| {
+            quality_score(
+                &weights,
+                &ScoreInputs {
+                    newest_tip_age_days: Some(age),
+                    ..inputs(tip_count)
+                },
+            )
+        };
+        assert!(score(5, 30.0) > score(2, 30.0));
+        assert!(score(5, 30.0) > score(5, 400.0));
+        // Tips dated in the future count as brand new rather than scoring above one.
+        assert_eq!(score(1, -10.0), score(1, 0.0));
+    }
+
+    #[test]
+    fn unknown_components_are_rejected() {
+        assert!(ScoreWeights::parse("vibes=1").is_err());
+        assert!(ScoreWeights::parse("tips").is_err());
+    }
+}
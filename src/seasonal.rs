@@ -0,0 +1,80 @@
+//! Spotting bars which only serve picklebacks some of the time, from cues such as "winter special"
+//! in the tips mentioning them.
+//!
+//! This is fuzzy, so it only runs when asked for. Cues are matched as whole words, ignoring case
+//! and punctuation, so that "limited" doesn't match "unlimited".
+use crate::dedup::normalize_text;
+
+/// Cues used when classification is asked for without naming any.
+pub const DEFAULT_SEASONAL_CUES: &[&str] = &["seasonal", "winter", "special", "limited"];
+
+/// Whether `text` contains any of `cues`. A cue of several words matches them in sequence.
+pub fn has_seasonal_cue(text: &str, cues: &[String]) -> bool {
+    let text = format!(" {} ", normalize_text(text));
+    cues.iter()
+        .map(|cue| normalize_text(cue))
+        .filter(|cue| !cue.is_empty())
+        .any(|cue| text.contains(&format!(" {} ", cue)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_cues() -> Vec<String> {
+        DEFAULT_SEASONAL_CUES
+            .iter()
+            .map(|cue| cue.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn seasonal_cues_are_found() {
+        let cues = default_cues();
+        assert!(has_seasonal_cue(
+            "Picklebacks are a WINTER thing here.",
+            &cues
+        ));
+        assert!(has_seasonal_cue("Limited-time pickleback flight!", &cues));
+        assert!(has_seasonal_cue("Ask about the pickleback special", &cues));
+    }
+
+    #[test]
+    fn tips_mentioning_months_are_not_seasonal() {
+        let cues = default_cues();
+        assert!(!has_seasonal_cue(
+            "Came here in December and again in June, picklebacks were great both times",
+            &cues
+        ));
+        assert!(!has_seasonal_cue(
+            "Open since March 2012. Get a pickleback.",
+            &cues
+        ));
+    }
+
+    #[test]
+    fn cues_only_match_whole_words() {
+        let cues = default_cues();
+        assert!(!has_seasonal_cue("Unlimited picklebacks at brunch", &cues));
+        assert!(!has_seasonal_cue(
+            "Try one of their specialty picklebacks",
+            &cues
+        ));
+        assert!(!has_seasonal_cue(
+            "Wintergreen pickles in the pickleback",
+            &cues
+        ));
+    }
+
+    #[test]
+    fn cues_of_several_words_match_in_sequence() {
+        let cues = vec!["pumpkin spice".to_string(), " ".to_string()];
+        assert!(has_seasonal_cue(
+            "The Pumpkin-Spice pickleback is back",
+            &cues
+        ));
+        assert!(!has_seasonal_cue("Spice up a pumpkin pickleback", &cues));
+        // Cues with nothing to match are ignored rather than matching everything.
+        assert!(!has_seasonal_cue("A pickleback", &cues));
+    }
+}
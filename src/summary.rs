@@ -0,0 +1,195 @@
+//! A short account of a scrape, printed when it finishes and kept alongside the listing, so a run
+//! which degraded stands out without diffing listings.
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::partition::partition_by_neighborhood;
+use crate::Bar;
+
+/// How many neighborhoods the summary lists.
+const TOP_NEIGHBORHOODS: usize = 5;
+
+/// Serialize a duration as seconds, with a fraction.
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// What happened during a scrape.
+#[derive(Default, Serialize)]
+pub struct ScrapeSummary {
+    /// Where was searched, such as "Manhattan".
+    pub region: String,
+    /// Venues Foursquare returned, before filtering for picklebacks.
+    pub venues_found: usize,
+    /// Distinct venues in the region's state, or all distinct venues if it has none.
+    pub venues_in_state: usize,
+    /// Venues with tips mentioning picklebacks, before duplicate venues were merged.
+    pub venues_with_tips: usize,
+    /// Bars in the listing.
+    pub bars_kept: usize,
+    /// Search boxes split into quadrants for having too many venues to list at once.
+    pub subdivisions: usize,
+    /// Requests made to Foursquare, including retries.
+    pub requests: usize,
+    /// Requests which failed and were retried.
+    pub retries: usize,
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    /// Neighborhoods with the most bars, most first, with their bar counts.
+    pub top_neighborhoods: Vec<(String, usize)>,
+}
+
+impl ScrapeSummary {
+    /// Fill in the parts of the summary which come from the finished listing.
+    pub fn set_listing(&mut self, bars: &[Bar]) {
+        self.bars_kept = bars.len();
+        let mut neighborhoods: Vec<(String, usize)> = partition_by_neighborhood(bars)
+            .into_iter()
+            .map(|(neighborhood, bars)| (neighborhood, bars.len()))
+            .collect();
+        // The partitions are in alphabetical order, which the stable sort keeps for ties.
+        neighborhoods.sort_by_key(|&(_, bars)| ::std::cmp::Reverse(bars));
+        neighborhoods.truncate(TOP_NEIGHBORHOODS);
+        self.top_neighborhoods = neighborhoods;
+    }
+}
+
+impl fmt::Display for ScrapeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.duration.as_secs();
+        writeln!(f, "Scrape summary")?;
+        writeln!(f, "  Region:            {}", self.region)?;
+        writeln!(f, "  Venues found:      {}", self.venues_found)?;
+        writeln!(f, "  Venues in state:   {}", self.venues_in_state)?;
+        writeln!(f, "  Venues with tips:  {}", self.venues_with_tips)?;
+        writeln!(f, "  Bars kept:         {}", self.bars_kept)?;
+        writeln!(f, "  Subdivisions:      {}", self.subdivisions)?;
+        writeln!(f, "  Requests made:     {}", self.requests)?;
+        writeln!(f, "  Retries:           {}", self.retries)?;
+        writeln!(
+            f,
+            "  Duration:          {}m {}s",
+            seconds / 60,
+            seconds % 60
+        )?;
+        let neighborhoods: Vec<String> = self
+            .top_neighborhoods
+            .iter()
+            .map(|(neighborhood, bars)| format!("{} ({})", neighborhood, bars))
+            .collect();
+        if neighborhoods.is_empty() {
+            writeln!(f, "  Top neighborhoods: none")
+        } else {
+            writeln!(f, "  Top neighborhoods: {}", neighborhoods.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A listing with `count` bars in each of the given neighborhoods.
+    fn bars(neighborhoods: &[(&str, usize)]) -> Vec<Bar> {
+        let mut bars = Vec::new();
+        for &(neighborhood, count) in neighborhoods {
+            for index in 0..count {
+                bars.push(
+                    serde_json::from_value(serde_json::json!({
+                        "id": format!("{}-{}", neighborhood, index),
+                        "name": "Bar",
+                        "lat": 40.7,
+                        "lng": -74.0,
+                        "tips": [],
+                        "neighborhood": neighborhood,
+                    }))
+                    .unwrap(),
+                );
+            }
+        }
+        bars
+    }
+
+    /// The summary of a scrape of Manhattan which made some requests and kept `bars`.
+    fn scrape_summary(bars: &[Bar]) -> ScrapeSummary {
+        let mut summary = ScrapeSummary {
+            region: "Manhattan".to_string(),
+            venues_found: 1200,
+            venues_in_state: 1100,
+            venues_with_tips: 14,
+            subdivisions: 3,
+            requests: 190,
+            retries: 2,
+            duration: Duration::from_millis(125_500),
+            ..ScrapeSummary::default()
+        };
+        summary.set_listing(bars);
+        summary
+    }
+
+    #[test]
+    fn the_summary_lists_each_field() {
+        let bars = bars(&[("Chelsea", 2), ("East Village", 5), ("Harlem", 3)]);
+        let printed = scrape_summary(&bars).to_string();
+        for line in &[
+            "  Region:            Manhattan",
+            "  Venues found:      1200",
+            "  Venues in state:   1100",
+            "  Venues with tips:  14",
+            "  Bars kept:         10",
+            "  Subdivisions:      3",
+            "  Requests made:     190",
+            "  Retries:           2",
+            "  Duration:          2m 5s",
+            "  Top neighborhoods: East Village (5), Harlem (3), Chelsea (2)",
+        ] {
+            assert!(
+                printed.lines().any(|printed| printed == *line),
+                "{}",
+                printed
+            );
+        }
+    }
+
+    #[test]
+    fn only_the_top_neighborhoods_are_listed() {
+        let bars = bars(&[
+            ("Chelsea", 1),
+            ("East Village", 4),
+            ("Harlem", 2),
+            ("Inwood", 1),
+            ("Midtown", 3),
+            ("SoHo", 2),
+            ("Tribeca", 1),
+        ]);
+        let summary = scrape_summary(&bars);
+        // Ties stay in alphabetical order.
+        assert_eq!(
+            summary.top_neighborhoods,
+            [
+                ("East Village".to_string(), 4),
+                ("Midtown".to_string(), 3),
+                ("Harlem".to_string(), 2),
+                ("SoHo".to_string(), 2),
+                ("Chelsea".to_string(), 1),
+            ]
+        );
+        assert!(scrape_summary(&[])
+            .to_string()
+            .contains("  Top neighborhoods: none\n"));
+    }
+
+    #[test]
+    fn the_summary_is_serialized_with_seconds() {
+        let json = serde_json::to_value(scrape_summary(&bars(&[("Harlem", 1)]))).unwrap();
+        assert_eq!(json["region"], "Manhattan");
+        assert_eq!(json["bars_kept"], 1);
+        assert_eq!(json["duration_secs"], 125.5);
+        assert_eq!(
+            json["top_neighborhoods"],
+            serde_json::json!([["Harlem", 1]])
+        );
+    }
+}